@@ -3,6 +3,7 @@ import {
     run as wasmRun,
     backup_data as wasmBackupData,
     restore_from_backup as wasmRestoreFromBackup,
+    open_backup as wasmOpenBackup,
     MyMachine,
 } from "../../pkg/filecoin_rs";
 import fs from "fs/promises";
@@ -12,7 +13,6 @@ import {
     type FilecoinBackupResult as WasmFilecoinBackupResult,
 } from "../../pkg/filecoin_rs";
 import type { RestoreOptions, FilecoinBackupResult } from "./types";
-import { decrypt, encrypt } from "./encryption";
 import { downloadFromStoracha } from "./database/storacha";
 import logger from "./logger";
 import * as dotenv from "dotenv";
@@ -26,7 +26,7 @@ export function convertWasmBackupResult(
     wasmResult: WasmFilecoinBackupResult,
 ): FilecoinBackupResult {
     return {
-        cid: "mock-cid", // Updated later with real CID
+        cid: wasmResult.cid ?? "",
         encrypted: wasmResult.metadata.encrypted ?? false,
         success: wasmResult.success,
         metadata: {
@@ -68,22 +68,33 @@ export class FilecoinRsBindings {
     static async backupDataLocal({
         path = "backup.bin",
         encrypted = false,
+        passphrase,
+        compressionLevel,
+        codec,
         data = "Default backup data",
     }: {
         path?: string;
         encrypted?: boolean;
+        passphrase?: string;
+        compressionLevel?: number;
+        codec?: "zstd" | "gzip" | "none";
         data?: string | Uint8Array;
     } = {}): Promise<FilecoinBackupResult> {
         try {
-            const inputString =
-                typeof data === "string"
-                    ? data
-                    : Buffer.from(data).toString("utf8");
-            const backupData = encrypted
-                ? encrypt(inputString)
-                : Buffer.from(inputString);
-
-            const wasmResult = wasmBackupData(backupData);
+            if (encrypted && !passphrase) {
+                throw new Error("Encrypted backups require a passphrase");
+            }
+            const input =
+                typeof data === "string" ? Buffer.from(data) : data;
+
+            const wasmResult = wasmBackupData(
+                input,
+                encrypted ? passphrase : undefined,
+                compressionLevel,
+                codec,
+                Math.floor(Date.now() / 1000),
+            );
+            const backupData = wasmResult.data;
             const result = convertWasmBackupResult(wasmResult);
 
             const client = getFilecoinClient();
@@ -91,11 +102,13 @@ export class FilecoinRsBindings {
                 type: "application/octet-stream",
             });
             const file = new File([blob], path);
-            const cid = await client.put([file]);
+            const remoteCid = await client.put([file]);
 
             await fs.writeFile(path, backupData);
-            logger.info(`Backup successful. CID: ${cid}, Path: ${path}`);
-            return { ...result, cid, encrypted, data: backupData };
+            logger.info(
+                `Backup successful. CID: ${result.cid}, remote CID: ${remoteCid}, Path: ${path}`,
+            );
+            return { ...result, encrypted, data: backupData };
         } catch (error) {
             const errorMsg =
                 error instanceof Error ? error.message : String(error);
@@ -123,6 +136,16 @@ export class FilecoinRsBindings {
         try {
             let dataToRestore: Uint8Array;
 
+            if (backupPath.startsWith("cid://")) {
+                dataToRestore = wasmRestoreFromBackup(
+                    backupPath.slice("cid://".length),
+                    decryptionKey ?? undefined,
+                );
+                await fs.writeFile(destinationPath, dataToRestore);
+                logger.info(`Restored data to ${destinationPath}`);
+                return true;
+            }
+
             if (
                 backupPath.startsWith("ipfs://") ||
                 backupPath.startsWith("filecoin://")
@@ -136,19 +159,9 @@ export class FilecoinRsBindings {
                 dataToRestore = await fs.readFile(backupPath);
             }
 
-            if (decryptionKey) {
-                dataToRestore = decrypt(
-                    dataToRestore,
-                    Buffer.from(decryptionKey, "hex"),
-                );
-            } else if (dataToRestore.length > 16) {
-                dataToRestore = decrypt(dataToRestore);
-            }
-
-            wasmRestoreFromBackup(
-                backupPath,
-                destinationPath,
-                decryptionKey || null,
+            dataToRestore = wasmOpenBackup(
+                dataToRestore,
+                decryptionKey ?? undefined,
             );
             await fs.writeFile(destinationPath, dataToRestore);
             logger.info(`Restored data to ${destinationPath}`);
@@ -94,7 +94,28 @@ export interface SetData {
 export interface Delegate {
     from: FilecoinCID;
     to: FilecoinCID;
-    permissions: Permissions;
+    permissions: DelegatedPermission[];
+    expires_at?: number;
+}
+
+export type DelegatedPermission =
+    | `transfer-up-to:${number}`
+    | `set-data-prefix:${string}`
+    | "vote"
+    | "withdraw";
+
+export interface TransferFrom {
+    owner: FilecoinCID;
+    to: FilecoinCID;
+    amount: number;
+}
+
+export interface MinterChange {
+    minter: FilecoinCID;
+}
+
+export interface DataKey {
+    key: string;
 }
 
 export interface Revoke {
@@ -122,9 +143,27 @@ export interface Withdraw {
 }
 
 export interface Custom {
+    method: number;
     data: any;
 }
 
+export interface VotingRules {
+    weighting: "TokenWeighted" | "OneMemberOneVote";
+    quorum_pct: number;
+    threshold_pct: number;
+}
+
+export interface Propose {
+    proposer: FilecoinCID;
+    description: string;
+    actions: Message[];
+    voting_period: number;
+}
+
+export interface SetVotingRules {
+    rules: VotingRules;
+}
+
 export interface RestoreOptions {
     backupPath: string;
     destinationPath?: string;
@@ -155,7 +194,14 @@ export type Message =
     | { kind: "QueryBalance"; payload: QueryBalance }
     | { kind: "Vote"; payload: Vote }
     | { kind: "Withdraw"; payload: Withdraw }
-    | { kind: "Custom"; payload: Custom };
+    | { kind: "Custom"; payload: Custom }
+    | { kind: "Propose"; payload: Propose }
+    | { kind: "TransferFrom"; payload: TransferFrom }
+    | { kind: "AddMinter"; payload: MinterChange }
+    | { kind: "RemoveMinter"; payload: MinterChange }
+    | { kind: "DeleteData"; payload: DataKey }
+    | { kind: "GetData"; payload: DataKey }
+    | { kind: "SetVotingRules"; payload: SetVotingRules };
 
 export interface Permissions {
     [key: string]: boolean | string | number;
@@ -1,114 +1,1873 @@
-use crate::messages::Message;
+use crate::custom::{CustomContext, HandlerRegistry};
+use crate::data_store::{DataEntry, DataStore};
+use crate::dao::governance::{is_governance_action, Proposal, ProposalStatus, ProposalTally, VotingRules, Weighting};
+use crate::events::{Event, EventFilter, EventLog, Subscription};
+use crate::messages::{Message, SignedMessage, UnsignedMessage};
+use crate::token::TokenInfo;
+use anyhow::{anyhow, bail, Result};
 use cid::Cid;
-use std::collections::HashMap;
+use fvm_ipld_blockstore::Blockstore;
+use fvm_ipld_encoding::tuple::*;
+use fvm_ipld_encoding::DAG_CBOR;
+use fvm_ipld_hamt::{BytesKey, Hamt};
+use multihash_codetable::{Code, MultihashDigest};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::sync::Arc;
 
-#[derive(Debug)]
+/// State root format written by `ActorState::flush`.
+pub const STATE_VERSION: u64 = 6;
+/// Bit width of the accounts HAMT, the FVM's default.
+pub(crate) const HAMT_BIT_WIDTH: u32 = 5;
+
+/// Errors from executing a message against `ActorState`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ActorError {
+    InsufficientBalance { needed: u64, available: u64 },
+    /// The sender has no delegation to revoke for this delegate.
+    DelegationNotFound { to: Cid },
+    AlreadyVoted { proposal_id: u64, voter: Cid },
+    /// A delegation's expiry epoch is not after the current epoch.
+    InvalidExpiry { expires_at: u64, epoch: u64 },
+    /// Crediting an account would overflow its balance.
+    Overflow,
+    /// A signed message's signature does not match its sender.
+    InvalidSignature,
+    /// A signed message's nonce is not the sender's next nonce.
+    InvalidNonce { expected: u64, got: u64 },
+    /// The sender may not act for `account`.
+    Unauthorized { account: Cid },
+    OutOfGas { limit: u64, needed: u64 },
+    ProposalNotFound { proposal_id: u64 },
+    /// The proposal's voting window has ended.
+    VotingClosed { proposal_id: u64 },
+    /// Voting rules, or the voting period or an action of a new proposal,
+    /// are not allowed.
+    InvalidProposal,
+    /// Only the system, or a passed proposal, may change the voting rules.
+    GovernanceOnly,
+    /// Only the system and minters may mint or change the minter set.
+    NotMinter { account: Cid },
+    /// Minting would take the total supply past the token's cap.
+    SupplyCapExceeded { max_supply: u64 },
+    /// The data key has no current value.
+    KeyNotFound { key: String },
+    /// The data key was written by the system, which alone may change it.
+    SystemKey { key: String },
+    /// No handler is registered for a custom message's method.
+    UnhandledMethod { method: u64 },
+    /// A custom message's parameters could not be decoded.
+    InvalidParams(String),
+    /// Custom handlers sent messages nested more than `max` deep.
+    CallDepthExceeded { max: u32 },
+}
+
+impl ActorError {
+    pub fn exit_code(&self) -> ExitCode {
+        match self {
+            ActorError::InsufficientBalance { .. } => ExitCode::INSUFFICIENT_FUNDS,
+            ActorError::DelegationNotFound { .. } => ExitCode::NOT_FOUND,
+            ActorError::AlreadyVoted { .. } => ExitCode::FORBIDDEN,
+            ActorError::InvalidExpiry { .. } => ExitCode::ILLEGAL_ARGUMENT,
+            ActorError::Overflow => ExitCode::ILLEGAL_STATE,
+            ActorError::InvalidSignature => ExitCode::SENDER_INVALID,
+            ActorError::InvalidNonce { .. } => ExitCode::SENDER_STATE_INVALID,
+            ActorError::Unauthorized { .. } => ExitCode::FORBIDDEN,
+            ActorError::OutOfGas { .. } => ExitCode::OUT_OF_GAS,
+            ActorError::ProposalNotFound { .. } => ExitCode::NOT_FOUND,
+            ActorError::VotingClosed { .. } => ExitCode::FORBIDDEN,
+            ActorError::InvalidProposal => ExitCode::ILLEGAL_ARGUMENT,
+            ActorError::GovernanceOnly => ExitCode::FORBIDDEN,
+            ActorError::NotMinter { .. } => ExitCode::FORBIDDEN,
+            ActorError::SupplyCapExceeded { .. } => ExitCode::FORBIDDEN,
+            ActorError::KeyNotFound { .. } => ExitCode::NOT_FOUND,
+            ActorError::SystemKey { .. } => ExitCode::FORBIDDEN,
+            ActorError::UnhandledMethod { .. } => ExitCode::UNHANDLED_MESSAGE,
+            ActorError::InvalidParams(_) => ExitCode::ILLEGAL_ARGUMENT,
+            ActorError::CallDepthExceeded { .. } => ExitCode::FORBIDDEN,
+        }
+    }
+}
+
+impl fmt::Display for ActorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ActorError::InsufficientBalance { needed, available } => {
+                write!(f, "insufficient balance: need {}, have {}", needed, available)
+            }
+            ActorError::DelegationNotFound { to } => write!(f, "no delegation to {}", to),
+            ActorError::AlreadyVoted { proposal_id, voter } => {
+                write!(f, "{} already voted on proposal {}", voter, proposal_id)
+            }
+            ActorError::InvalidExpiry { expires_at, epoch } => {
+                write!(f, "delegation expiry {} is not after current epoch {}", expires_at, epoch)
+            }
+            ActorError::Overflow => write!(f, "balance overflow"),
+            ActorError::InvalidSignature => write!(f, "invalid message signature"),
+            ActorError::InvalidNonce { expected, got } => write!(f, "invalid nonce {}, expected {}", got, expected),
+            ActorError::Unauthorized { account } => write!(f, "sender may not act for {}", account),
+            ActorError::OutOfGas { limit, needed } => write!(f, "out of gas: limit {}, needed {}", limit, needed),
+            ActorError::ProposalNotFound { proposal_id } => write!(f, "no proposal {}", proposal_id),
+            ActorError::VotingClosed { proposal_id } => write!(f, "voting on proposal {} has closed", proposal_id),
+            ActorError::InvalidProposal => write!(f, "invalid voting rules, voting period or proposal action"),
+            ActorError::GovernanceOnly => write!(f, "only governance may change the voting rules"),
+            ActorError::NotMinter { account } => write!(f, "{} is not a minter", account),
+            ActorError::SupplyCapExceeded { max_supply } => write!(f, "mint would exceed max supply {}", max_supply),
+            ActorError::KeyNotFound { key } => write!(f, "no value for key {:?}", key),
+            ActorError::SystemKey { key } => write!(f, "key {:?} may only be written by the system", key),
+            ActorError::UnhandledMethod { method } => write!(f, "no handler for custom method {}", method),
+            ActorError::InvalidParams(reason) => write!(f, "invalid custom params: {}", reason),
+            ActorError::CallDepthExceeded { max } => write!(f, "custom messages nested more than {} deep", max),
+        }
+    }
+}
+
+impl std::error::Error for ActorError {}
+
+/// Outcome of a message, using the FVM's user exit code numbering.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ExitCode(pub u32);
+
+impl ExitCode {
+    pub const OK: ExitCode = ExitCode(0);
+    pub const SENDER_INVALID: ExitCode = ExitCode(1);
+    pub const SENDER_STATE_INVALID: ExitCode = ExitCode(2);
+    pub const OUT_OF_GAS: ExitCode = ExitCode(7);
+    pub const ILLEGAL_ARGUMENT: ExitCode = ExitCode(16);
+    pub const NOT_FOUND: ExitCode = ExitCode(17);
+    pub const FORBIDDEN: ExitCode = ExitCode(18);
+    pub const INSUFFICIENT_FUNDS: ExitCode = ExitCode(19);
+    pub const ILLEGAL_STATE: ExitCode = ExitCode(20);
+    pub const UNHANDLED_MESSAGE: ExitCode = ExitCode(22);
+
+    pub fn is_success(self) -> bool {
+        self == ExitCode::OK
+    }
+}
+
+/// Gas charged for every message.
+pub const GAS_BASE: u64 = 100;
+/// Gas charged per balance or delegation change.
+pub const GAS_PER_UPDATE: u64 = 50;
+/// Gas charged per byte of message payload.
+pub const GAS_PER_BYTE: u64 = 1;
+/// How deep messages sent by custom handlers may nest, e.g. a handler
+/// sending a custom message whose handler sends another.
+pub const MAX_CALL_DEPTH: u32 = 8;
+
+/// Result of applying a message to `ActorState`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Receipt {
+    pub exit_code: ExitCode,
+    /// DAG-CBOR encoded return value on success, the error message on failure.
+    pub return_data: Vec<u8>,
+    pub gas_used: u64,
+    /// Empty when the message failed.
+    pub events: Vec<Event>,
+}
+
+impl Receipt {
+    pub fn is_success(&self) -> bool {
+        self.exit_code.is_success()
+    }
+}
+
+/// What a delegate may do for the account that granted it. Encoded as a
+/// string: `transfer-up-to:<cap>`, `set-data-prefix:<prefix>`, `vote` or
+/// `withdraw`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub enum Permission {
+    /// Move up to `cap` of the owner's balance in total with `TransferFrom`.
+    TransferUpTo { cap: u64 },
+    /// Write or delete the owner's data keys that start with `prefix`. For a
+    /// key in the owner's `<account>/` namespace the prefix is matched
+    /// against the rest of the key.
+    SetDataPrefix { prefix: String },
+    /// Vote as the owner.
+    Vote,
+    /// Withdraw from the owner's balance.
+    Withdraw,
+}
+
+impl fmt::Display for Permission {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Permission::TransferUpTo { cap } => write!(f, "transfer-up-to:{}", cap),
+            Permission::SetDataPrefix { prefix } => write!(f, "set-data-prefix:{}", prefix),
+            Permission::Vote => write!(f, "vote"),
+            Permission::Withdraw => write!(f, "withdraw"),
+        }
+    }
+}
+
+impl std::str::FromStr for Permission {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s.split_once(':') {
+            Some(("transfer-up-to", cap)) => cap
+                .parse()
+                .map(|cap| Permission::TransferUpTo { cap })
+                .map_err(|_| format!("invalid transfer cap in permission {:?}", s)),
+            Some(("set-data-prefix", prefix)) => Ok(Permission::SetDataPrefix { prefix: prefix.to_string() }),
+            None if s == "vote" => Ok(Permission::Vote),
+            None if s == "withdraw" => Ok(Permission::Withdraw),
+            _ => Err(format!("unknown permission {:?}", s)),
+        }
+    }
+}
+
+impl From<Permission> for String {
+    fn from(permission: Permission) -> Self {
+        permission.to_string()
+    }
+}
+
+impl TryFrom<String> for Permission {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, String> {
+        s.parse()
+    }
+}
+
+/// Permissions granted by one account to another.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize_tuple, Deserialize_tuple)]
+pub struct Delegation {
+    pub permissions: Vec<Permission>,
+    /// Epoch at which the delegation stops being valid; `None` never expires.
+    pub expires_at: Option<u64>,
+    /// Amount moved with `TransferFrom` under this delegation so far.
+    pub spent: u64,
+}
+
+impl Delegation {
+    pub fn is_active(&self, epoch: u64) -> bool {
+        self.expires_at.is_none_or(|expiry| epoch < expiry)
+    }
+
+    /// How much more the delegate may transfer, zero without a
+    /// `TransferUpTo` permission.
+    pub fn remaining(&self) -> u64 {
+        let cap = self
+            .permissions
+            .iter()
+            .filter_map(|p| match p {
+                Permission::TransferUpTo { cap } => Some(*cap),
+                _ => None,
+            })
+            .max()
+            .unwrap_or(0);
+        cap.saturating_sub(self.spent)
+    }
+
+    /// Whether the delegation covers `requested`, ignoring expiry. For
+    /// `TransferUpTo` the cap is the amount to move, which must fit in what
+    /// remains; for `SetDataPrefix` the prefix is the key to write, which
+    /// must start with a granted prefix.
+    pub fn grants(&self, requested: &Permission) -> bool {
+        match requested {
+            Permission::TransferUpTo { cap: amount } => *amount <= self.remaining(),
+            Permission::SetDataPrefix { prefix: key } => self.permissions.iter().any(|p| {
+                matches!(p, Permission::SetDataPrefix { prefix } if key.starts_with(prefix.as_str()))
+            }),
+            other => self.permissions.contains(other),
+        }
+    }
+}
+
+/// Yes/no vote counts for a proposal.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct VoteTally {
+    pub yes: u64,
+    pub no: u64,
+}
+
+/// Token balances, delegations and votes of a set of accounts, the proposals
+/// they vote on, and the data they write with `SetData`. Accounts are held in
+/// memory while messages execute; `flush` writes them to a blockstore as a
+/// HAMT keyed by account CID and returns a state root that `load` restores.
+///
+/// The total supply counts every token in the ledger: the actor balance plus
+/// all account balances. Minting and `fund` add to it; burning and
+/// withdrawing take tokens out of the ledger and subtract from it.
+///
+/// Serializes on its own, without a blockstore, as the tuple
+/// `[version, balance, epoch, [[account, account_state], ...], [proposal, ...],
+/// voting_rules, token, total_supply, [minter, ...], [[key, data_entry], ...]]` with
+/// accounts and minters in CID order, proposals in id order and data in key
+/// order.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(into = "StateRecord", try_from = "StateRecord")]
 pub struct ActorState {
-    pub balance: u64,
+    /// Pool the actor pays out of with system transfers and proposals.
+    balance: u64,
+    /// Current chain epoch, used to expire delegations.
+    pub epoch: u64,
     accounts: HashMap<Cid, ActorAccount>,
+    proposals: BTreeMap<u64, Proposal>,
+    /// Rules for proposals opened from now on.
+    voting_rules: VotingRules,
+    token: TokenInfo,
+    total_supply: u64,
+    /// Accounts that may mint besides the system.
+    minters: BTreeSet<Cid>,
+    data: DataStore,
+    /// Handlers for `Message::Custom`. Not part of the state root; set them
+    /// again after `load`.
+    handlers: Arc<HandlerRegistry>,
+    /// HAMT the accounts were last flushed to or loaded from.
+    accounts_root: Option<Cid>,
+    /// Accounts modified since `accounts_root`.
+    dirty: BTreeSet<Cid>,
+    /// Events of every message applied since the state was created or
+    /// loaded. Not part of the state root.
+    log: EventLog,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(into = "AccountRecord", from = "AccountRecord")]
 struct ActorAccount {
     balance: u64,
+    /// Number of signed messages applied from this account.
+    nonce: u64,
+    /// Delegations granted by this account, keyed by delegate.
+    delegations: BTreeMap<Cid, Delegation>,
+    /// Votes cast by this account, keyed by proposal.
+    votes: BTreeMap<u64, bool>,
+}
+
+/// Stored form of an `ActorAccount`. DAG-CBOR map keys must be strings, so
+/// the maps are stored as lists of pairs in key order.
+#[derive(Serialize_tuple, Deserialize_tuple)]
+struct AccountRecord {
+    balance: u64,
+    nonce: u64,
+    delegations: Vec<(Cid, Delegation)>,
+    votes: Vec<(u64, bool)>,
+}
+
+impl From<ActorAccount> for AccountRecord {
+    fn from(account: ActorAccount) -> Self {
+        AccountRecord {
+            balance: account.balance,
+            nonce: account.nonce,
+            delegations: account.delegations.into_iter().collect(),
+            votes: account.votes.into_iter().collect(),
+        }
+    }
+}
+
+impl From<AccountRecord> for ActorAccount {
+    fn from(record: AccountRecord) -> Self {
+        ActorAccount {
+            balance: record.balance,
+            nonce: record.nonce,
+            delegations: record.delegations.into_iter().collect(),
+            votes: record.votes.into_iter().collect(),
+        }
+    }
+}
+
+#[derive(Serialize_tuple, Deserialize_tuple)]
+struct StateRecord {
+    version: u64,
+    balance: u64,
+    epoch: u64,
+    accounts: Vec<(Cid, ActorAccount)>,
+    proposals: Vec<Proposal>,
+    voting_rules: VotingRules,
+    token: TokenInfo,
+    total_supply: u64,
+    minters: Vec<Cid>,
+    data: Vec<(String, DataEntry)>,
+}
+
+impl From<ActorState> for StateRecord {
+    fn from(state: ActorState) -> Self {
+        let mut accounts: Vec<_> = state.accounts.into_iter().collect();
+        accounts.sort_by_key(|(id, _)| *id);
+        StateRecord {
+            version: STATE_VERSION,
+            balance: state.balance,
+            epoch: state.epoch,
+            accounts,
+            proposals: state.proposals.into_values().collect(),
+            voting_rules: state.voting_rules,
+            token: state.token,
+            total_supply: state.total_supply,
+            minters: state.minters.into_iter().collect(),
+            data: state.data.entries().map(|(k, v)| (k.clone(), v.clone())).collect(),
+        }
+    }
+}
+
+impl TryFrom<StateRecord> for ActorState {
+    type Error = String;
+
+    fn try_from(record: StateRecord) -> Result<Self, String> {
+        if record.version != STATE_VERSION {
+            return Err(format!("unsupported state version {}", record.version));
+        }
+        let accounts: HashMap<_, _> = record.accounts.into_iter().collect();
+        Ok(ActorState {
+            balance: record.balance,
+            epoch: record.epoch,
+            // Nothing has been flushed yet, so every account is new.
+            dirty: accounts.keys().copied().collect(),
+            accounts,
+            proposals: record.proposals.into_iter().map(|p| (p.id, p)).collect(),
+            voting_rules: record.voting_rules,
+            token: record.token,
+            total_supply: record.total_supply,
+            minters: record.minters.into_iter().collect(),
+            data: DataStore::from_entries(record.data),
+            handlers: Arc::default(),
+            accounts_root: None,
+            log: EventLog::new(),
+        })
+    }
+}
+
+/// Root block of a flushed `ActorState`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize_tuple, Deserialize_tuple)]
+struct StateRoot {
+    version: u64,
+    balance: u64,
+    epoch: u64,
+    /// HAMT of account CID bytes to account.
+    accounts: Cid,
+    proposals: Vec<Proposal>,
+    voting_rules: VotingRules,
+    token: TokenInfo,
+    total_supply: u64,
+    minters: Vec<Cid>,
+    /// HAMT of data key bytes to data entry.
+    data: Cid,
+}
+
+/// Differences between two state roots.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StateDiff {
+    /// Actor balance before and after, if it changed.
+    pub balance: Option<(u64, u64)>,
+    pub added: Vec<Cid>,
+    pub removed: Vec<Cid>,
+    pub changed: Vec<Cid>,
+}
+
+impl StateDiff {
+    pub fn is_empty(&self) -> bool {
+        self.balance.is_none() && self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
 }
 
 impl ActorAccount {
-    fn delegate(&mut self, _to: Cid, _permissions: Vec<String>) {
-        unimplemented!()
+    /// Grants `permissions` to `to`, replacing any earlier delegation to it.
+    /// The amount already spent under the earlier one is forgotten.
+    fn delegate(&mut self, to: Cid, permissions: Vec<Permission>, expires_at: Option<u64>) {
+        self.delegations.insert(to, Delegation { permissions, expires_at, spent: 0 });
     }
 
-    fn revoke(&mut self, _to: Cid) {
-        unimplemented!()
+    fn revoke(&mut self, to: &Cid) -> Option<Delegation> {
+        self.delegations.remove(to)
     }
 
-    fn vote(&mut self, proposal_id: String, support: bool) {
-        // Adjusted to expect String for proposal_id
-        unimplemented!()
+    /// Records a vote and returns true, or returns false if this account has
+    /// already voted on the proposal.
+    fn vote(&mut self, proposal_id: u64, support: bool) -> bool {
+        if self.votes.contains_key(&proposal_id) {
+            return false;
+        }
+        self.votes.insert(proposal_id, support);
+        true
     }
 
-    fn withdraw(&mut self, _amount: &u64) {
-        unimplemented!()
+    fn credit(&mut self, amount: u64) -> Result<(), ActorError> {
+        self.balance = self.balance.checked_add(amount).ok_or(ActorError::Overflow)?;
+        Ok(())
+    }
+
+    /// Debits `amount` and returns the remaining balance.
+    fn debit(&mut self, amount: u64) -> Result<u64, ActorError> {
+        self.balance = debit(self.balance, amount)?;
+        Ok(self.balance)
+    }
+}
+
+fn debit(balance: u64, amount: u64) -> Result<u64, ActorError> {
+    balance.checked_sub(amount).ok_or(ActorError::InsufficientBalance {
+        needed: amount,
+        available: balance,
+    })
+}
+
+/// Original values of everything a message has modified so far, used to undo
+/// the message if it fails.
+pub(crate) struct Journal {
+    balance: u64,
+    total_supply: u64,
+    accounts: HashMap<Cid, Option<ActorAccount>>,
+    /// Proposals created so far.
+    proposals: Vec<u64>,
+    voting_rules: VotingRules,
+    /// The minter set, once it has been changed.
+    minters: Option<BTreeSet<Cid>>,
+    data: HashMap<String, Option<DataEntry>>,
+    /// Gas the message may use, `None` for the system.
+    gas_limit: Option<u64>,
+    /// Gas of the message and of every message its handlers sent.
+    gas_used: u64,
+    /// Messages sent by handlers that are still executing.
+    depth: u32,
+}
+
+impl Journal {
+    fn new(state: &ActorState) -> Self {
+        Journal {
+            balance: state.balance,
+            total_supply: state.total_supply,
+            accounts: HashMap::new(),
+            proposals: Vec::new(),
+            voting_rules: state.voting_rules,
+            minters: None,
+            data: HashMap::new(),
+            gas_limit: None,
+            gas_used: 0,
+            depth: 0,
+        }
+    }
+
+    /// Charges for `msg`, sent by a custom handler, and enters it. Each
+    /// successful `enter` must be followed by `leave`.
+    pub(crate) fn enter(&mut self, msg: &Message) -> Result<(), ActorError> {
+        if self.depth >= MAX_CALL_DEPTH {
+            return Err(ActorError::CallDepthExceeded { max: MAX_CALL_DEPTH });
+        }
+        self.gas_used = self.gas_used.saturating_add(gas_cost(msg));
+        self.check_gas()?;
+        self.depth += 1;
+        Ok(())
+    }
+
+    pub(crate) fn leave(&mut self) {
+        self.depth -= 1;
+    }
+
+    fn check_gas(&self) -> Result<(), ActorError> {
+        match self.gas_limit {
+            Some(limit) if self.gas_used > limit => Err(ActorError::OutOfGas { limit, needed: self.gas_used }),
+            _ => Ok(()),
+        }
     }
 }
 
 impl ActorState {
     pub fn new() -> Self {
+        ActorState::default()
+    }
+
+    /// An empty state keeping balances in `token`.
+    pub fn with_token(token: TokenInfo) -> Self {
         ActorState {
-            balance: 0,
-            accounts: HashMap::new(),
+            token,
+            ..ActorState::default()
+        }
+    }
+
+    pub fn token(&self) -> &TokenInfo {
+        &self.token
+    }
+
+    pub fn total_supply(&self) -> u64 {
+        self.total_supply
+    }
+
+    /// The actor's own balance.
+    pub fn balance(&self) -> u64 {
+        self.balance
+    }
+
+    /// Creates `amount` new tokens in the actor balance, counted in the total
+    /// supply like minted ones.
+    pub fn fund(&mut self, amount: u64) -> Result<(), ActorError> {
+        let supply = self.supply_after_mint(amount)?;
+        self.balance = self.balance.checked_add(amount).ok_or(ActorError::Overflow)?;
+        self.total_supply = supply;
+        Ok(())
+    }
+
+    /// The total supply once `amount` more is minted, within the token's cap.
+    fn supply_after_mint(&self, amount: u64) -> Result<u64, ActorError> {
+        let supply = self.total_supply.checked_add(amount).ok_or(ActorError::Overflow)?;
+        match self.token.max_supply {
+            Some(max_supply) if supply > max_supply => Err(ActorError::SupplyCapExceeded { max_supply }),
+            _ => Ok(supply),
+        }
+    }
+
+    pub fn is_minter(&self, account: &Cid) -> bool {
+        self.minters.contains(account)
+    }
+
+    /// Records written with `SetData`.
+    pub fn data(&self) -> &DataStore {
+        &self.data
+    }
+
+    /// Routes `Message::Custom` to `handlers`. Without handlers every custom
+    /// message fails with `ExitCode::UNHANDLED_MESSAGE`.
+    pub fn set_handlers(&mut self, handlers: Arc<HandlerRegistry>) {
+        self.handlers = handlers;
+    }
+
+    pub fn handlers(&self) -> &HandlerRegistry {
+        &self.handlers
+    }
+
+    /// Balance of `account`, zero if it has never been credited.
+    pub fn balance_of(&self, account: &Cid) -> u64 {
+        self.accounts.get(account).map_or(0, |a| a.balance)
+    }
+
+    /// The delegation from `from` to `to`, if one exists and has not expired.
+    pub fn delegation(&self, from: &Cid, to: &Cid) -> Option<&Delegation> {
+        self.accounts
+            .get(from)
+            .and_then(|a| a.delegations.get(to))
+            .filter(|d| d.is_active(self.epoch))
+    }
+
+    /// Whether `to` may currently act for `from` as `permission` describes;
+    /// see `Delegation::grants`.
+    pub fn is_authorized(&self, from: &Cid, to: &Cid, permission: &Permission) -> bool {
+        self.delegation(from, to).is_some_and(|d| d.grants(permission))
+    }
+
+    /// How much `spender` may still move out of `owner`'s balance with
+    /// `TransferFrom`.
+    pub fn allowance(&self, owner: &Cid, spender: &Cid) -> u64 {
+        self.delegation(owner, spender).map_or(0, Delegation::remaining)
+    }
+
+    /// The vote `voter` cast on `proposal_id`, if any.
+    pub fn vote_of(&self, proposal_id: u64, voter: &Cid) -> Option<bool> {
+        self.accounts.get(voter).and_then(|a| a.votes.get(&proposal_id).copied())
+    }
+
+    /// Counts the votes cast on `proposal_id`.
+    pub fn tally(&self, proposal_id: u64) -> VoteTally {
+        let mut tally = VoteTally::default();
+        for support in self.accounts.values().filter_map(|a| a.votes.get(&proposal_id)) {
+            if *support {
+                tally.yes += 1;
+            } else {
+                tally.no += 1;
+            }
+        }
+        tally
+    }
+
+    pub fn proposal(&self, proposal_id: u64) -> Option<&Proposal> {
+        self.proposals.get(&proposal_id)
+    }
+
+    /// Rules that proposals opened from now on are decided by.
+    pub fn voting_rules(&self) -> &VotingRules {
+        &self.voting_rules
+    }
+
+    /// Weighted votes on `proposal_id`, by the weights recorded when it was
+    /// opened. Votes from accounts that were not members then carry no
+    /// weight.
+    pub fn proposal_tally(&self, proposal_id: u64) -> Option<ProposalTally> {
+        let proposal = self.proposals.get(&proposal_id)?;
+        let mut tally = ProposalTally::default();
+        for &(member, weight) in &proposal.weights {
+            tally.eligible = tally.eligible.saturating_add(weight);
+            match self.vote_of(proposal_id, &member) {
+                Some(true) => tally.yes = tally.yes.saturating_add(weight),
+                Some(false) => tally.no = tally.no.saturating_add(weight),
+                None => {}
+            }
+        }
+        Some(tally)
+    }
+
+    /// Voting weight of every member under `rules`, in CID order.
+    fn member_weights(&self, rules: &VotingRules) -> Vec<(Cid, u64)> {
+        let mut weights: Vec<_> = self
+            .accounts
+            .iter()
+            .filter(|(_, account)| account.balance > 0)
+            .map(|(id, account)| match rules.weighting {
+                Weighting::TokenWeighted => (*id, account.balance),
+                Weighting::OneMemberOneVote => (*id, 1),
+            })
+            .collect();
+        weights.sort();
+        weights
+    }
+
+    /// Moves to `epoch` and closes every open proposal whose voting window
+    /// has ended, in id order. A proposal that passes has its actions
+    /// applied as the system, all or nothing: if one fails, the proposal is
+    /// marked `Failed` and none take effect. Returns the closed proposals'
+    /// ids and outcomes.
+    pub fn advance_epoch(&mut self, epoch: u64) -> Vec<(u64, ProposalStatus)> {
+        self.epoch = self.epoch.max(epoch);
+        let due: Vec<u64> = self
+            .proposals
+            .values()
+            .filter(|p| p.status == ProposalStatus::Open && p.voting_end <= self.epoch)
+            .map(|p| p.id)
+            .collect();
+        let mut closed = Vec::new();
+        for proposal_id in due {
+            let status = self.close_proposal(proposal_id);
+            closed.push((proposal_id, status));
+        }
+        closed
+    }
+
+    fn close_proposal(&mut self, proposal_id: u64) -> ProposalStatus {
+        let proposal = self.proposals[&proposal_id].clone();
+        let passed = self
+            .proposal_tally(proposal_id)
+            .is_some_and(|tally| tally.passes(&proposal.rules));
+        let mut events = Vec::new();
+        let status = if !passed {
+            ProposalStatus::Rejected
+        } else {
+            let mut journal = Journal::new(self);
+            let applied = proposal
+                .actions
+                .iter()
+                .try_for_each(|action| self.apply(action, None, &mut journal, &mut events).map(drop));
+            match applied {
+                Ok(()) => ProposalStatus::Executed,
+                Err(_) => {
+                    self.rollback(journal);
+                    events.clear();
+                    ProposalStatus::Failed
+                }
+            }
+        };
+        if let Some(p) = self.proposals.get_mut(&proposal_id) {
+            p.status = status;
+        }
+        events.push(Event::ProposalClosed { proposal_id, status });
+        self.log.append(self.epoch, None, &events);
+        status
+    }
+
+    /// History of the events emitted by applied messages.
+    pub fn events(&self) -> &EventLog {
+        &self.log
+    }
+
+    /// Receives the events of messages applied from now on that match
+    /// `filter`.
+    pub fn subscribe(&mut self, filter: EventFilter) -> Subscription {
+        self.log.subscribe(filter)
+    }
+
+    /// The nonce the next signed message from `account` must carry.
+    pub fn nonce_of(&self, account: &Cid) -> u64 {
+        self.accounts.get(account).map_or(0, |a| a.nonce)
+    }
+
+    /// Applies `msg` with no sender, as the system: `Transfer` pays out of the
+    /// actor balance and no account checks apply. Messages from outside must
+    /// go through `apply_signed`.
+    ///
+    /// A message either takes effect completely or, if it fails, leaves the
+    /// state unchanged; a failing transfer in a `BatchTransfer` undoes the
+    /// transfers before it. Gas is charged by message size whether or not it
+    /// succeeds.
+    pub fn handle_message(&mut self, msg: &Message) -> Receipt {
+        self.execute(msg, None)
+    }
+
+    /// Authenticates and applies a signed message. A bad signature or a nonce
+    /// other than the sender's next one is rejected without a receipt and
+    /// without touching state. Otherwise the sender's nonce advances whether
+    /// or not the message succeeds, so it cannot be replayed. The message may
+    /// only act on the sender's own account.
+    pub fn apply_signed(&mut self, signed: &SignedMessage) -> Result<Receipt, ActorError> {
+        signed.verify()?;
+        let unsigned = &signed.message;
+        let expected = self.nonce_of(&unsigned.from);
+        if unsigned.nonce != expected {
+            return Err(ActorError::InvalidNonce { expected, got: unsigned.nonce });
+        }
+        let receipt = self.execute(&unsigned.message, Some(unsigned));
+        self.accounts.entry(unsigned.from).or_default().nonce += 1;
+        self.dirty.insert(unsigned.from);
+        Ok(receipt)
+    }
+
+    fn execute(&mut self, msg: &Message, sender: Option<&UnsignedMessage>) -> Receipt {
+        let mut journal = Journal::new(self);
+        journal.gas_limit = sender.map(|unsigned| unsigned.gas_limit);
+        journal.gas_used = gas_cost(msg);
+        let mut events = Vec::new();
+        let result = match sender {
+            Some(unsigned) => journal.check_gas().and_then(|()| {
+                self.pay_value(unsigned, &mut journal)
+                    .and_then(|()| self.apply(msg, Some(unsigned.from), &mut journal, &mut events))
+            }),
+            None => self.apply(msg, None, &mut journal, &mut events),
+        };
+        // Handlers may have ignored a failed send; running out of gas still
+        // fails the whole message.
+        let result = result.and_then(|return_data| journal.check_gas().map(|()| return_data));
+        let gas_used = journal.gas_used.min(journal.gas_limit.unwrap_or(u64::MAX));
+        match result {
+            Ok(return_data) => {
+                self.log.append(self.epoch, sender.map(UnsignedMessage::cid), &events);
+                Receipt {
+                    exit_code: ExitCode::OK,
+                    return_data,
+                    gas_used,
+                    events,
+                }
+            }
+            Err(e) => {
+                self.rollback(journal);
+                Receipt {
+                    exit_code: e.exit_code(),
+                    return_data: e.to_string().into_bytes(),
+                    gas_used,
+                    events: Vec::new(),
+                }
+            }
         }
     }
 
-    pub fn set_data(&mut self, key: String, value: Vec<u8>) {
-        println!("Setting data: key = {}, value = {:?}", key, value);
+    /// Moves a signed message's value from the sender to the actor balance.
+    fn pay_value(&mut self, unsigned: &UnsignedMessage, journal: &mut Journal) -> Result<(), ActorError> {
+        if unsigned.value > 0 {
+            self.account_mut(unsigned.from, journal).debit(unsigned.value)?;
+            self.balance = self.balance.checked_add(unsigned.value).ok_or(ActorError::Overflow)?;
+        }
+        Ok(())
     }
 
-    pub fn handle_message(&mut self, msg: &Message) {
+    pub(crate) fn apply(
+        &mut self,
+        msg: &Message,
+        sender: Option<Cid>,
+        journal: &mut Journal,
+        events: &mut Vec<Event>,
+    ) -> Result<Vec<u8>, ActorError> {
         match msg {
             Message::Transfer { to, amount } => {
-                if let Some(account) = self.get_account(to.clone()) {
-                    account.balance += amount;
+                match sender {
+                    Some(from) => {
+                        self.account_mut(from, journal).debit(*amount)?;
+                    }
+                    None => self.balance = debit(self.balance, *amount)?,
                 }
-                self.balance -= amount;
+                self.account_mut(*to, journal).credit(*amount)?;
+                events.push(Event::Transferred { from: sender, to: *to, amount: *amount });
             }
             Message::Mint { to, amount } => {
-                if let Some(account) = self.get_account(to.clone()) {
-                    account.balance += amount;
-                }
+                self.authorize_minter(sender)?;
+                let supply = self.supply_after_mint(*amount)?;
+                self.account_mut(*to, journal).credit(*amount)?;
+                self.total_supply = supply;
+                events.push(Event::Minted { to: *to, amount: *amount });
             }
             Message::Burn { from, amount } => {
-                if let Some(account) = self.get_account(from.clone()) {
-                    if account.balance >= *amount {
-                        account.balance -= amount;
-                    }
+                authorize(sender, from)?;
+                self.account_mut(*from, journal).debit(*amount)?;
+                self.total_supply = self.total_supply.checked_sub(*amount).ok_or(ActorError::Overflow)?;
+                events.push(Event::Burned { from: *from, amount: *amount });
+            }
+            Message::AddMinter { minter } => {
+                self.authorize_minter(sender)?;
+                journal.minters.get_or_insert_with(|| self.minters.clone());
+                self.minters.insert(*minter);
+                events.push(Event::MinterAdded { minter: *minter });
+            }
+            Message::RemoveMinter { minter } => {
+                self.authorize_minter(sender)?;
+                journal.minters.get_or_insert_with(|| self.minters.clone());
+                if !self.minters.remove(minter) {
+                    return Err(ActorError::NotMinter { account: *minter });
                 }
+                events.push(Event::MinterRemoved { minter: *minter });
             }
             Message::SetData { key, value } => {
-                self.set_data(key.clone(), value.clone().into());
+                let mut entry = self.writable_entry(sender, key)?;
+                entry.push(Some(value.clone()), self.epoch, sender);
+                self.put_data(key, entry, journal);
+                events.push(Event::DataSet { key: key.clone() });
             }
-            Message::Delegate { from, to, permissions } => {
-                if let Some(from_account) = self.get_account(from.clone()) {
-                    from_account.delegate(to.clone(), permissions.clone());
+            Message::DeleteData { key } => {
+                let mut entry = self.writable_entry(sender, key)?;
+                if entry.value().is_none() {
+                    return Err(ActorError::KeyNotFound { key: key.clone() });
                 }
+                entry.push(None, self.epoch, sender);
+                self.put_data(key, entry, journal);
+                events.push(Event::DataDeleted { key: key.clone() });
+            }
+            Message::GetData { key } => {
+                return Ok(fvm_ipld_encoding::to_vec(&self.data.get(key)).expect("strings always encode"));
+            }
+            Message::Delegate { from, to, permissions, expires_at } => {
+                authorize(sender, from)?;
+                if let Some(expires_at) = *expires_at {
+                    if expires_at <= self.epoch {
+                        return Err(ActorError::InvalidExpiry { expires_at, epoch: self.epoch });
+                    }
+                }
+                self.account_mut(*from, journal)
+                    .delegate(*to, permissions.clone(), *expires_at);
+                events.push(Event::Delegated { from: *from, to: *to });
             }
             Message::Revoke { from, to } => {
-                if let Some(from_account) = self.get_account(from.clone()) {
-                    from_account.revoke(to.clone());
+                authorize(sender, from)?;
+                self.account_mut(*from, journal)
+                    .revoke(to)
+                    .ok_or(ActorError::DelegationNotFound { to: *to })?;
+                events.push(Event::Revoked { from: *from, to: *to });
+            }
+            Message::TransferFrom { owner, to, amount } => {
+                if let Some(spender) = sender.filter(|s| s != owner) {
+                    self.authorize_delegate(sender, owner, &Permission::TransferUpTo { cap: *amount })?;
+                    let delegation = self
+                        .account_mut(*owner, journal)
+                        .delegations
+                        .get_mut(&spender)
+                        .expect("authorized delegate has a delegation");
+                    delegation.spent += amount;
                 }
+                self.account_mut(*owner, journal).debit(*amount)?;
+                self.account_mut(*to, journal).credit(*amount)?;
+                events.push(Event::Transferred { from: Some(*owner), to: *to, amount: *amount });
             }
             Message::BatchTransfer { transfers } => {
                 for &(to, amount) in transfers {
-                    self.handle_message(&Message::Transfer { to, amount });
+                    self.apply(&Message::Transfer { to, amount }, sender, journal, events)?;
                 }
             }
             Message::QueryBalance { account } => {
-                if let Some(account) = self.get_account(account.clone()) {
-                    // Use {:?} for Debug formatting instead of Display
-                    println!("Balance of {:?}: {}", account, account.balance);
-                }
+                return Ok(encode(self.balance_of(account)));
             }
             Message::Vote { proposal_id, voter, support } => {
-                if let Some(voter_account) = self.get_account(voter.clone()) {
-                    // Convert u64 to String if needed, or adjust Message::Vote type
-                    voter_account.vote(proposal_id.to_string(), *support);
+                self.authorize_delegate(sender, voter, &Permission::Vote)?;
+                let proposal = self
+                    .proposals
+                    .get(proposal_id)
+                    .ok_or(ActorError::ProposalNotFound { proposal_id: *proposal_id })?;
+                if !proposal.is_open(self.epoch) {
+                    return Err(ActorError::VotingClosed { proposal_id: *proposal_id });
+                }
+                if !self.account_mut(*voter, journal).vote(*proposal_id, *support) {
+                    return Err(ActorError::AlreadyVoted { proposal_id: *proposal_id, voter: *voter });
                 }
+                events.push(Event::Voted { proposal_id: *proposal_id, voter: *voter, support: *support });
             }
             Message::Withdraw { from, amount } => {
-                if let Some(account) = self.get_account(from.clone()) {
-                    account.withdraw(amount);
+                self.authorize_delegate(sender, from, &Permission::Withdraw)?;
+                let remaining = self.account_mut(*from, journal).debit(*amount)?;
+                self.total_supply = self.total_supply.checked_sub(*amount).ok_or(ActorError::Overflow)?;
+                events.push(Event::Withdrawn { from: *from, amount: *amount });
+                return Ok(encode(remaining));
+            }
+            Message::Custom { method, data } => {
+                let handlers = Arc::clone(&self.handlers);
+                let mut ctx = CustomContext::new(self, sender, *method, journal, events);
+                return handlers.dispatch(&mut ctx, data);
+            }
+            Message::Propose { proposer, description, actions, voting_period } => {
+                authorize(sender, proposer)?;
+                // Actions run as the system, so they are limited to what the
+                // members collectively own: the actor balance and the rules.
+                if *voting_period == 0 || !actions.iter().all(is_governance_action) {
+                    return Err(ActorError::InvalidProposal);
                 }
+                let voting_end = self.epoch.checked_add(*voting_period).ok_or(ActorError::InvalidProposal)?;
+                let id = self.proposals.keys().next_back().map_or(1, |last| last + 1);
+                self.proposals.insert(
+                    id,
+                    Proposal {
+                        id,
+                        proposer: *proposer,
+                        description: description.clone(),
+                        actions: actions.clone(),
+                        voting_end,
+                        rules: self.voting_rules,
+                        weights: self.member_weights(&self.voting_rules),
+                        status: ProposalStatus::Open,
+                    },
+                );
+                journal.proposals.push(id);
+                events.push(Event::Proposed { proposal_id: id, proposer: *proposer });
+                return Ok(encode(id));
             }
-            Message::Custom { data } => {
-                println!("Received custom message: {:?}", data);
+            Message::SetVotingRules { rules } => {
+                if sender.is_some() {
+                    return Err(ActorError::GovernanceOnly);
+                }
+                if !rules.is_valid() {
+                    return Err(ActorError::InvalidProposal);
+                }
+                self.voting_rules = *rules;
+                events.push(Event::VotingRulesSet { rules: *rules });
             }
         }
+        Ok(Vec::new())
     }
 
-    fn get_account(&mut self, account_id: Cid) -> Option<&mut ActorAccount> {
-        self.accounts
+    /// Checks that a message acting on `account` comes from that account or
+    /// from a delegate holding `permission` for it.
+    fn authorize_delegate(&self, sender: Option<Cid>, account: &Cid, permission: &Permission) -> Result<(), ActorError> {
+        match sender {
+            Some(sender) if sender != *account && !self.is_authorized(account, &sender, permission) => {
+                Err(ActorError::Unauthorized { account: *account })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn authorize_minter(&self, sender: Option<Cid>) -> Result<(), ActorError> {
+        match sender {
+            Some(sender) if !self.minters.contains(&sender) => Err(ActorError::NotMinter { account: sender }),
+            _ => Ok(()),
+        }
+    }
+
+    /// The entry for `key`, or a new one, if `sender` may write it. A key in
+    /// an `<account>/` namespace belongs to that account; any other key to
+    /// whoever wrote it first. Owners and their delegates may write.
+    fn writable_entry(&self, sender: Option<Cid>, key: &str) -> Result<DataEntry, ActorError> {
+        let existing = self.data.entry(key);
+        let (owner, scope) = match key_namespace(key) {
+            Some((account, rest)) => (Some(account), rest),
+            None => (existing.map_or(sender, |e| e.owner), key),
+        };
+        match owner {
+            Some(owner) => {
+                self.authorize_delegate(sender, &owner, &Permission::SetDataPrefix { prefix: scope.to_string() })?
+            }
+            None if sender.is_some() => return Err(ActorError::SystemKey { key: key.to_string() }),
+            None => {}
+        }
+        Ok(existing.cloned().unwrap_or_else(|| DataEntry::new(owner)))
+    }
+
+    fn put_data(&mut self, key: &str, entry: DataEntry, journal: &mut Journal) {
+        let previous = self.data.replace(key, Some(entry));
+        journal.data.entry(key.to_string()).or_insert(previous);
+    }
+
+    /// The account for `account_id`, created if missing. Its prior value is
+    /// recorded in `journal` the first time it is touched.
+    fn account_mut(&mut self, account_id: Cid, journal: &mut Journal) -> &mut ActorAccount {
+        journal
+            .accounts
             .entry(account_id)
-            .or_insert(ActorAccount { balance: 0 })
-            .into()
+            .or_insert_with(|| self.accounts.get(&account_id).cloned());
+        self.dirty.insert(account_id);
+        self.accounts.entry(account_id).or_default()
     }
-}
\ No newline at end of file
+
+    fn rollback(&mut self, journal: Journal) {
+        self.balance = journal.balance;
+        self.total_supply = journal.total_supply;
+        self.voting_rules = journal.voting_rules;
+        if let Some(minters) = journal.minters {
+            self.minters = minters;
+        }
+        for (account_id, original) in journal.accounts {
+            match original {
+                Some(account) => self.accounts.insert(account_id, account),
+                None => self.accounts.remove(&account_id),
+            };
+        }
+        for proposal_id in journal.proposals {
+            self.proposals.remove(&proposal_id);
+        }
+        for (key, original) in journal.data {
+            self.data.replace(&key, original);
+        }
+    }
+
+    /// Writes the state to `store` and returns its root CID. Only accounts
+    /// modified since the last `flush` or `load` are rewritten; unchanged
+    /// HAMT nodes keep their CIDs, so successive roots share most blocks.
+    pub fn flush<B: Blockstore + ?Sized>(&mut self, store: &B) -> Result<Cid> {
+        let mut hamt: Hamt<_, ActorAccount> = match &self.accounts_root {
+            Some(root) => Hamt::load_with_bit_width(root, StoreRef(store), HAMT_BIT_WIDTH)?,
+            None => Hamt::new_with_bit_width(StoreRef(store), HAMT_BIT_WIDTH),
+        };
+        for account_id in &self.dirty {
+            let key = BytesKey(account_id.to_bytes());
+            match self.accounts.get(account_id) {
+                Some(account) => {
+                    hamt.set(key, account.clone())?;
+                }
+                None => {
+                    hamt.delete(&key)?;
+                }
+            }
+        }
+        let accounts = hamt.flush()?;
+        self.accounts_root = Some(accounts);
+        self.dirty.clear();
+        let data = self.data.flush(store)?;
+
+        let root = StateRoot {
+            version: STATE_VERSION,
+            balance: self.balance,
+            epoch: self.epoch,
+            accounts,
+            proposals: self.proposals.values().cloned().collect(),
+            voting_rules: self.voting_rules,
+            token: self.token.clone(),
+            total_supply: self.total_supply,
+            minters: self.minters.iter().copied().collect(),
+            data,
+        };
+        let bytes = fvm_ipld_encoding::to_vec(&root)?;
+        let cid = Cid::new_v1(DAG_CBOR, Code::Sha2_256.digest(&bytes));
+        store.put_keyed(&cid, &bytes)?;
+        Ok(cid)
+    }
+
+    /// Restores the state flushed under `root`. Reverting to an earlier root
+    /// is a rollback; the later state stays in the store until collected.
+    pub fn load<B: Blockstore + ?Sized>(store: &B, root: &Cid) -> Result<Self> {
+        let state_root = load_root(store, root)?;
+        Ok(ActorState {
+            balance: state_root.balance,
+            epoch: state_root.epoch,
+            accounts: load_accounts(store, &state_root.accounts)?,
+            proposals: state_root.proposals.into_iter().map(|p| (p.id, p)).collect(),
+            voting_rules: state_root.voting_rules,
+            token: state_root.token,
+            total_supply: state_root.total_supply,
+            minters: state_root.minters.into_iter().collect(),
+            data: DataStore::load(store, &state_root.data)?,
+            handlers: Arc::default(),
+            accounts_root: Some(state_root.accounts),
+            dirty: BTreeSet::new(),
+            log: EventLog::new(),
+        })
+    }
+}
+
+/// Lists what changed going from state root `a` to state root `b`.
+pub fn diff_roots<B: Blockstore + ?Sized>(store: &B, a: &Cid, b: &Cid) -> Result<StateDiff> {
+    let (old_root, new_root) = (load_root(store, a)?, load_root(store, b)?);
+    let mut diff = StateDiff::default();
+    if old_root.balance != new_root.balance {
+        diff.balance = Some((old_root.balance, new_root.balance));
+    }
+    if old_root.accounts == new_root.accounts {
+        return Ok(diff);
+    }
+    let old = load_accounts(store, &old_root.accounts)?;
+    let new = load_accounts(store, &new_root.accounts)?;
+    for (account_id, account) in &new {
+        match old.get(account_id) {
+            None => diff.added.push(*account_id),
+            Some(previous) if previous != account => diff.changed.push(*account_id),
+            Some(_) => {}
+        }
+    }
+    diff.removed = old.keys().filter(|id| !new.contains_key(id)).copied().collect();
+    diff.added.sort();
+    diff.changed.sort();
+    diff.removed.sort();
+    Ok(diff)
+}
+
+fn load_root<B: Blockstore + ?Sized>(store: &B, root: &Cid) -> Result<StateRoot> {
+    let block = store
+        .get(root)?
+        .ok_or_else(|| anyhow!("state root {} not found", root))?;
+    let state_root: StateRoot = fvm_ipld_encoding::from_slice(&block)
+        .map_err(|e| anyhow!("invalid state root {}: {}", root, e))?;
+    if state_root.version != STATE_VERSION {
+        bail!("unsupported state version {}", state_root.version);
+    }
+    Ok(state_root)
+}
+
+fn load_accounts<B: Blockstore + ?Sized>(store: &B, root: &Cid) -> Result<HashMap<Cid, ActorAccount>> {
+    let hamt: Hamt<_, ActorAccount> = Hamt::load_with_bit_width(root, StoreRef(store), HAMT_BIT_WIDTH)?;
+    let mut accounts = HashMap::new();
+    hamt.for_each(|key, account| {
+        accounts.insert(Cid::try_from(key.0.as_slice())?, account.clone());
+        Ok(())
+    })?;
+    Ok(accounts)
+}
+
+/// Sized view of a possibly unsized blockstore, which the HAMT requires.
+pub(crate) struct StoreRef<'a, B: ?Sized>(pub(crate) &'a B);
+
+impl<B: Blockstore + ?Sized> Blockstore for StoreRef<'_, B> {
+    fn get(&self, k: &Cid) -> Result<Option<Vec<u8>>> {
+        self.0.get(k)
+    }
+
+    fn put_keyed(&self, k: &Cid, block: &[u8]) -> Result<()> {
+        self.0.put_keyed(k, block)
+    }
+
+    fn has(&self, k: &Cid) -> Result<bool> {
+        self.0.has(k)
+    }
+}
+
+/// Checks that a message acting on `account` comes from that account.
+fn authorize(sender: Option<Cid>, account: &Cid) -> Result<(), ActorError> {
+    match sender {
+        Some(sender) if sender != *account => Err(ActorError::Unauthorized { account: *account }),
+        _ => Ok(()),
+    }
+}
+
+/// Splits a data key of the form `<account>/<rest>`.
+fn key_namespace(key: &str) -> Option<(Cid, &str)> {
+    let (namespace, rest) = key.split_once('/')?;
+    Some((Cid::try_from(namespace).ok()?, rest))
+}
+
+/// Gas a message uses: `GAS_BASE` plus a size-dependent part.
+pub fn gas_cost(msg: &Message) -> u64 {
+    let variable = match msg {
+        Message::Transfer { .. }
+        | Message::TransferFrom { .. }
+        | Message::Mint { .. }
+        | Message::Burn { .. }
+        | Message::Withdraw { .. }
+        | Message::AddMinter { .. }
+        | Message::RemoveMinter { .. } => GAS_PER_UPDATE,
+        Message::Delegate { permissions, .. } => {
+            GAS_PER_UPDATE + GAS_PER_BYTE * permissions.iter().map(|p| p.to_string().len() as u64).sum::<u64>()
+        }
+        Message::Revoke { .. } | Message::Vote { .. } | Message::SetVotingRules { .. } => GAS_PER_UPDATE,
+        Message::BatchTransfer { transfers } => GAS_PER_UPDATE * transfers.len() as u64,
+        Message::SetData { key, value } => GAS_PER_BYTE * (key.len() + value.len()) as u64,
+        Message::DeleteData { key } | Message::GetData { key } => GAS_PER_BYTE * key.len() as u64,
+        Message::Custom { data, .. } => GAS_PER_BYTE * data.len() as u64,
+        Message::QueryBalance { .. } => 0,
+        Message::Propose { description, actions, .. } => {
+            let payload = description.len() + actions.iter().map(|a| a.params().len()).sum::<usize>();
+            GAS_PER_UPDATE + GAS_PER_BYTE * payload as u64
+        }
+    };
+    GAS_BASE + variable
+}
+
+fn encode(value: u64) -> Vec<u8> {
+    fvm_ipld_encoding::to_vec(&value).expect("u64 always encodes")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::EventKind;
+    use multihash_codetable::{Code, MultihashDigest};
+
+    fn account(name: &str) -> Cid {
+        Cid::new_v1(0x55, Code::Sha2_256.digest(name.as_bytes()))
+    }
+
+    /// Opens a proposal as the system and returns its id.
+    fn propose(state: &mut ActorState, proposer: Cid, actions: Vec<Message>) -> u64 {
+        let receipt = state.handle_message(&Message::Propose {
+            proposer,
+            description: "pay contributors".to_string(),
+            actions,
+            voting_period: 5,
+        });
+        fvm_ipld_encoding::from_slice(&receipt.return_data).unwrap()
+    }
+
+    #[test]
+    fn test_delegation_expiry_and_revocation() {
+        let (alice, bob) = (account("alice"), account("bob"));
+        let mut state = ActorState::new();
+        state.epoch = 10;
+        let receipt = state.handle_message(&Message::Delegate {
+            from: alice,
+            to: bob,
+            permissions: vec![Permission::TransferUpTo { cap: 100 }],
+            expires_at: Some(20),
+        });
+        assert_eq!(receipt.events, vec![Event::Delegated { from: alice, to: bob }]);
+        assert!(state.is_authorized(&alice, &bob, &Permission::TransferUpTo { cap: 100 }));
+        assert!(!state.is_authorized(&alice, &bob, &Permission::TransferUpTo { cap: 101 }));
+        assert!(!state.is_authorized(&alice, &bob, &Permission::Vote));
+
+        state.epoch = 20;
+        assert_eq!(state.allowance(&alice, &bob), 0);
+        let past = Message::Delegate { from: alice, to: bob, permissions: vec![], expires_at: Some(5) };
+        assert_eq!(state.handle_message(&past).exit_code, ExitCode::ILLEGAL_ARGUMENT);
+
+        assert!(state.handle_message(&Message::Revoke { from: alice, to: bob }).is_success());
+        assert_eq!(state.handle_message(&Message::Revoke { from: alice, to: bob }).exit_code, ExitCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn test_delegate_acts_within_its_permissions() {
+        use crate::messages::{SecretKey, SigType};
+
+        let key = SecretKey::generate(SigType::Secp256k1).unwrap();
+        let (alice, bob, carol) = (account("alice"), key.account(), account("carol"));
+        let mut state = ActorState::new();
+        state.handle_message(&Message::Mint { to: alice, amount: 100 });
+        state.handle_message(&Message::Delegate {
+            from: alice,
+            to: bob,
+            permissions: vec![
+                Permission::TransferUpTo { cap: 30 },
+                Permission::SetDataPrefix { prefix: "agent/".to_string() },
+            ],
+            expires_at: Some(10),
+        });
+        let mut nonce = 0;
+        let mut send = |state: &mut ActorState, message| {
+            let unsigned = UnsignedMessage {
+                from: bob,
+                nonce,
+                value: 0,
+                gas_limit: 1_000,
+                gas_fee_cap: 1,
+                gas_premium: 1,
+                message,
+            };
+            nonce += 1;
+            state.apply_signed(&unsigned.sign(&key)).unwrap().exit_code
+        };
+        let pay = |amount| Message::TransferFrom { owner: alice, to: carol, amount };
+
+        assert_eq!(send(&mut state, pay(20)), ExitCode::OK);
+        assert_eq!(state.allowance(&alice, &bob), 10);
+        assert_eq!(send(&mut state, pay(11)), ExitCode::FORBIDDEN);
+        assert_eq!(send(&mut state, pay(10)), ExitCode::OK);
+        assert_eq!((state.balance_of(&alice), state.balance_of(&carol)), (70, 30));
+        assert_eq!(send(&mut state, Message::Withdraw { from: alice, amount: 1 }), ExitCode::FORBIDDEN);
+
+        let set = |key: &str| Message::SetData { key: format!("{}/{}", alice, key), value: String::new() };
+        assert_eq!(send(&mut state, set("agent/notes")), ExitCode::OK);
+        assert_eq!(send(&mut state, set("profile")), ExitCode::FORBIDDEN);
+
+        // Renewing the delegation resets what has been spent; expiry ends it.
+        state.handle_message(&Message::Delegate {
+            from: alice,
+            to: bob,
+            permissions: vec![Permission::TransferUpTo { cap: 5 }],
+            expires_at: Some(10),
+        });
+        assert_eq!(state.allowance(&alice, &bob), 5);
+        state.epoch = 10;
+        assert_eq!(send(&mut state, pay(5)), ExitCode::FORBIDDEN);
+    }
+
+    #[test]
+    fn test_minting_is_restricted_and_capped() {
+        use crate::messages::{SecretKey, SigType};
+
+        let key = SecretKey::generate(SigType::Secp256k1).unwrap();
+        let (minter, alice) = (key.account(), account("alice"));
+        let mut state = ActorState::with_token(TokenInfo::new("Reward", "RWD").max_supply(100));
+        assert_eq!(state.token().decimals, 18);
+        let mut nonce = 0;
+        let mut send = |state: &mut ActorState, message| {
+            let unsigned = UnsignedMessage {
+                from: minter,
+                nonce,
+                value: 0,
+                gas_limit: 1_000,
+                gas_fee_cap: 1,
+                gas_premium: 1,
+                message,
+            };
+            nonce += 1;
+            state.apply_signed(&unsigned.sign(&key)).unwrap().exit_code
+        };
+
+        assert_eq!(send(&mut state, Message::Mint { to: alice, amount: 10 }), ExitCode::FORBIDDEN);
+        assert!(state.handle_message(&Message::AddMinter { minter }).is_success());
+        assert_eq!(send(&mut state, Message::Mint { to: alice, amount: 60 }), ExitCode::OK);
+        assert_eq!(send(&mut state, Message::Mint { to: alice, amount: 41 }), ExitCode::FORBIDDEN);
+        assert_eq!(state.total_supply(), 60);
+
+        state.handle_message(&Message::Burn { from: alice, amount: 20 });
+        assert_eq!(send(&mut state, Message::Mint { to: alice, amount: 60 }), ExitCode::OK);
+        assert_eq!((state.total_supply(), state.balance_of(&alice)), (100, 100));
+
+        assert_eq!(send(&mut state, Message::RemoveMinter { minter }), ExitCode::OK);
+        assert!(!state.is_minter(&minter));
+        assert_eq!(send(&mut state, Message::AddMinter { minter }), ExitCode::FORBIDDEN);
+    }
+
+    #[test]
+    fn test_data_keys_are_owned_by_their_writer() {
+        use crate::messages::{SecretKey, SigType};
+
+        let alice_key = SecretKey::generate(SigType::Secp256k1).unwrap();
+        let bob_key = SecretKey::generate(SigType::Secp256k1).unwrap();
+        let (alice, bob) = (alice_key.account(), bob_key.account());
+        let mut state = ActorState::new();
+        let send = |state: &mut ActorState, key: &SecretKey, message| {
+            let unsigned = UnsignedMessage {
+                from: key.account(),
+                nonce: state.nonce_of(&key.account()),
+                value: 0,
+                gas_limit: 1_000,
+                gas_fee_cap: 1,
+                gas_premium: 1,
+                message,
+            };
+            state.apply_signed(&unsigned.sign(key)).unwrap()
+        };
+        let set = |key: &str, value: &str| Message::SetData { key: key.to_string(), value: value.to_string() };
+
+        assert!(send(&mut state, &alice_key, set("config/model", "small")).is_success());
+        assert!(send(&mut state, &alice_key, set("config/model", "large")).is_success());
+        assert_eq!(send(&mut state, &bob_key, set("config/model", "mine")).exit_code, ExitCode::FORBIDDEN);
+        state.handle_message(&Message::Delegate {
+            from: alice,
+            to: bob,
+            permissions: vec![Permission::SetDataPrefix { prefix: "config/".to_string() }],
+            expires_at: None,
+        });
+        assert!(send(&mut state, &bob_key, set("config/model", "tuned")).is_success());
+        assert!(send(&mut state, &bob_key, set("config/seed", "7")).is_success());
+        state.handle_message(&set("system/fee", "1"));
+        assert_eq!(send(&mut state, &alice_key, set("system/fee", "0")).exit_code, ExitCode::FORBIDDEN);
+
+        let entry = state.data().entry("config/model").unwrap();
+        assert_eq!(entry.owner, Some(alice));
+        let writers: Vec<_> = entry.versions.iter().map(|v| (v.value.as_deref(), v.writer)).collect();
+        assert_eq!(writers, vec![(Some("small"), Some(alice)), (Some("large"), Some(alice)), (Some("tuned"), Some(bob))]);
+        // Bob wrote `config/seed` first, so it is his, not Alice's.
+        assert_eq!(state.data().list("config/"), vec![("config/model", "tuned"), ("config/seed", "7")]);
+        assert_eq!(state.data().entry("config/seed").unwrap().owner, Some(bob));
+
+        let delete = Message::DeleteData { key: "config/model".to_string() };
+        assert!(send(&mut state, &alice_key, delete.clone()).is_success());
+        assert_eq!(send(&mut state, &alice_key, delete).exit_code, ExitCode::NOT_FOUND);
+        let receipt = state.handle_message(&Message::GetData { key: "config/seed".to_string() });
+        assert_eq!(fvm_ipld_encoding::from_slice::<Option<String>>(&receipt.return_data).unwrap().as_deref(), Some("7"));
+
+        let bs = fvm_ipld_blockstore::MemoryBlockstore::new();
+        let root = state.flush(&bs).unwrap();
+        let restored = ActorState::load(&bs, &root).unwrap();
+        assert_eq!(restored.data().get("config/model"), None);
+        assert_eq!(restored.data().entry("config/model").unwrap().versions.len(), 4);
+        assert_eq!(restored.data().list(""), vec![("config/seed", "7"), ("system/fee", "1")]);
+    }
+
+    #[test]
+    fn test_permission_strings() {
+        for (permission, text) in [
+            (Permission::TransferUpTo { cap: 250 }, "transfer-up-to:250"),
+            (Permission::SetDataPrefix { prefix: "rewards/".to_string() }, "set-data-prefix:rewards/"),
+            (Permission::Vote, "vote"),
+            (Permission::Withdraw, "withdraw"),
+        ] {
+            assert_eq!(permission.to_string(), text);
+            assert_eq!(text.parse::<Permission>(), Ok(permission));
+        }
+        assert!("transfer".parse::<Permission>().is_err());
+        assert!("transfer-up-to:lots".parse::<Permission>().is_err());
+    }
+
+    #[test]
+    fn test_votes_are_counted_once() {
+        let (alice, bob) = (account("alice"), account("bob"));
+        let mut state = ActorState::new();
+        let unknown = state.handle_message(&Message::Vote { proposal_id: 1, voter: alice, support: true });
+        assert_eq!(unknown.exit_code, ExitCode::NOT_FOUND);
+        assert_eq!(propose(&mut state, alice, vec![]), 1);
+        state.handle_message(&Message::Vote { proposal_id: 1, voter: alice, support: true });
+        state.handle_message(&Message::Vote { proposal_id: 1, voter: bob, support: false });
+        let receipt = state.handle_message(&Message::Vote { proposal_id: 1, voter: alice, support: false });
+        assert_eq!(receipt.exit_code, ExitCode::FORBIDDEN);
+        assert!(receipt.events.is_empty());
+        assert_eq!(state.tally(1), VoteTally { yes: 1, no: 1 });
+        assert_eq!(state.vote_of(1, &alice), Some(true));
+
+        state.advance_epoch(5);
+        let late = state.handle_message(&Message::Vote { proposal_id: 1, voter: account("carol"), support: true });
+        assert_eq!(late.exit_code, ExitCode::FORBIDDEN);
+    }
+
+    #[test]
+    fn test_passed_proposal_spends_reward_pool() {
+        let (alice, bob, carol) = (account("alice"), account("bob"), account("carol"));
+        let mut state = ActorState::new();
+        state.fund(100).unwrap();
+        state.handle_message(&Message::Mint { to: alice, amount: 60 });
+        state.handle_message(&Message::Mint { to: bob, amount: 40 });
+        let pay = vec![Message::BatchTransfer { transfers: vec![(carol, 30), (bob, 20)] }];
+        let id = propose(&mut state, alice, pay);
+
+        state.handle_message(&Message::Vote { proposal_id: id, voter: alice, support: true });
+        state.handle_message(&Message::Vote { proposal_id: id, voter: bob, support: false });
+        assert_eq!(state.proposal_tally(id), Some(ProposalTally { yes: 60, no: 40, eligible: 100 }));
+
+        assert!(state.advance_epoch(4).is_empty());
+        assert_eq!(state.advance_epoch(5), vec![(id, ProposalStatus::Executed)]);
+        assert_eq!((state.balance(), state.balance_of(&carol), state.balance_of(&bob)), (50, 30, 60));
+        assert!(state.advance_epoch(6).is_empty());
+
+        // One member one vote: bob and carol outvote alice despite her tokens.
+        let rules = VotingRules { weighting: Weighting::OneMemberOneVote, ..VotingRules::majority() };
+        assert!(state.handle_message(&Message::SetVotingRules { rules }).is_success());
+        let id = propose(&mut state, alice, vec![Message::Transfer { to: alice, amount: 50 }]);
+        state.handle_message(&Message::Vote { proposal_id: id, voter: alice, support: true });
+        state.handle_message(&Message::Vote { proposal_id: id, voter: bob, support: false });
+        state.handle_message(&Message::Vote { proposal_id: id, voter: carol, support: false });
+        assert_eq!(state.advance_epoch(11), vec![(id, ProposalStatus::Rejected)]);
+        assert_eq!(state.balance(), 50);
+    }
+
+    #[test]
+    fn test_failed_proposal_actions_are_all_undone() {
+        let (alice, bob) = (account("alice"), account("bob"));
+        let mut state = ActorState::new();
+        state.fund(10).unwrap();
+        state.handle_message(&Message::Mint { to: alice, amount: 1 });
+        let actions = vec![
+            Message::Transfer { to: bob, amount: 10 },
+            Message::Transfer { to: bob, amount: 1 },
+        ];
+        let id = propose(&mut state, alice, actions);
+        state.handle_message(&Message::Vote { proposal_id: id, voter: alice, support: true });
+
+        assert_eq!(state.advance_epoch(5), vec![(id, ProposalStatus::Failed)]);
+        assert_eq!((state.balance(), state.balance_of(&bob)), (10, 0));
+        assert_eq!(state.proposal(id).unwrap().status, ProposalStatus::Failed);
+        let closed = state.events().query(&EventFilter::new().kind(EventKind::ProposalClosed));
+        assert_eq!(closed.len(), 1);
+
+        let invalid = Message::Propose {
+            proposer: alice,
+            description: String::new(),
+            actions: vec![],
+            voting_period: 0,
+        };
+        assert_eq!(state.handle_message(&invalid).exit_code, ExitCode::ILLEGAL_ARGUMENT);
+    }
+
+    #[test]
+    fn test_proposals_only_spend_the_pool() {
+        use crate::messages::{SecretKey, SigType};
+
+        let key = SecretKey::generate(SigType::Secp256k1).unwrap();
+        let (mallory, victim) = (key.account(), account("victim"));
+        let mut state = ActorState::new();
+        state.fund(10).unwrap();
+        state.handle_message(&Message::Mint { to: mallory, amount: 1 });
+        state.handle_message(&Message::Mint { to: victim, amount: 99 });
+        let mut nonce = 0;
+        let mut send = |state: &mut ActorState, message| {
+            let unsigned = UnsignedMessage {
+                from: mallory,
+                nonce,
+                value: 0,
+                gas_limit: 1_000,
+                gas_fee_cap: 1,
+                gas_premium: 1,
+                message,
+            };
+            nonce += 1;
+            state.apply_signed(&unsigned.sign(&key)).unwrap().exit_code
+        };
+        let proposal = |actions| Message::Propose {
+            proposer: mallory,
+            description: String::new(),
+            actions,
+            voting_period: 5,
+        };
+
+        for action in [
+            Message::Mint { to: mallory, amount: 1_000 },
+            Message::TransferFrom { owner: victim, to: mallory, amount: 99 },
+            Message::Withdraw { from: victim, amount: 99 },
+            Message::AddMinter { minter: mallory },
+        ] {
+            assert_eq!(send(&mut state, proposal(vec![action])), ExitCode::ILLEGAL_ARGUMENT);
+        }
+        let lax = VotingRules { quorum_pct: 1, ..VotingRules::majority() };
+        assert_eq!(send(&mut state, Message::SetVotingRules { rules: lax }), ExitCode::FORBIDDEN);
+        let no_quorum = VotingRules { quorum_pct: 0, ..VotingRules::majority() };
+        let receipt = state.handle_message(&Message::SetVotingRules { rules: no_quorum });
+        assert_eq!(receipt.exit_code, ExitCode::ILLEGAL_ARGUMENT);
+
+        // Alone she holds 1% of the vote, short of quorum.
+        let payout = proposal(vec![Message::Transfer { to: mallory, amount: 10 }]);
+        assert_eq!(send(&mut state, payout), ExitCode::OK);
+        let vote = Message::Vote { proposal_id: 1, voter: mallory, support: true };
+        assert_eq!(send(&mut state, vote), ExitCode::OK);
+        assert_eq!(state.advance_epoch(5), vec![(1, ProposalStatus::Rejected)]);
+        assert_eq!((state.balance(), state.balance_of(&mallory), state.balance_of(&victim)), (10, 1, 99));
+    }
+
+    #[test]
+    fn test_vote_weights_are_fixed_when_proposal_opens() {
+        let (alice, bob, carol, dave) = (account("alice"), account("bob"), account("carol"), account("dave"));
+        let mut state = ActorState::new();
+        state.handle_message(&Message::Mint { to: alice, amount: 60 });
+        state.handle_message(&Message::Mint { to: bob, amount: 40 });
+        let unanimous = VotingRules { threshold_pct: 100, ..VotingRules::majority() };
+        let id = propose(&mut state, bob, vec![Message::SetVotingRules { rules: unanimous }]);
+
+        // Alice votes, then hands her tokens to carol to vote again, and dave
+        // is funded after the proposal opened: neither vote counts.
+        state.handle_message(&Message::Vote { proposal_id: id, voter: alice, support: false });
+        state.handle_message(&Message::TransferFrom { owner: alice, to: carol, amount: 60 });
+        state.handle_message(&Message::Mint { to: dave, amount: 1_000 });
+        state.handle_message(&Message::Vote { proposal_id: id, voter: carol, support: true });
+        state.handle_message(&Message::Vote { proposal_id: id, voter: dave, support: true });
+        state.handle_message(&Message::Vote { proposal_id: id, voter: bob, support: true });
+        assert_eq!(state.proposal_tally(id), Some(ProposalTally { yes: 40, no: 60, eligible: 100 }));
+        assert_eq!(state.advance_epoch(5), vec![(id, ProposalStatus::Rejected)]);
+        assert_eq!(state.voting_rules(), &VotingRules::majority());
+
+        let id = propose(&mut state, bob, vec![Message::SetVotingRules { rules: unanimous }]);
+        let weights = &state.proposal(id).unwrap().weights;
+        assert_eq!(weights.iter().map(|(_, w)| w).sum::<u64>(), 1_100);
+        for voter in [carol, dave] {
+            state.handle_message(&Message::Vote { proposal_id: id, voter, support: true });
+        }
+        assert_eq!(state.advance_epoch(10), vec![(id, ProposalStatus::Executed)]);
+        assert_eq!(state.voting_rules(), &unanimous);
+        assert_eq!(state.proposal(id).unwrap().rules, VotingRules::majority());
+    }
+
+    #[test]
+    fn test_withdraw_and_burn_check_balance() {
+        let alice = account("alice");
+        let mut state = ActorState::new();
+        state.handle_message(&Message::Mint { to: alice, amount: 50 });
+        let receipt = state.handle_message(&Message::Withdraw { from: alice, amount: 30 });
+        assert!(receipt.is_success());
+        assert_eq!(fvm_ipld_encoding::from_slice::<u64>(&receipt.return_data).unwrap(), 20);
+
+        let receipt = state.handle_message(&Message::Burn { from: alice, amount: 30 });
+        assert_eq!(receipt.exit_code, ExitCode::INSUFFICIENT_FUNDS);
+        assert_eq!(receipt.return_data, b"insufficient balance: need 30, have 20");
+        assert_eq!(state.balance_of(&alice), 20);
+
+        let nobody = account("nobody");
+        assert!(!state.handle_message(&Message::Withdraw { from: nobody, amount: 1 }).is_success());
+        assert!(!state.accounts.contains_key(&nobody));
+    }
+
+    #[test]
+    fn test_failed_batch_leaves_state_unchanged() {
+        let (alice, bob, carol) = (account("alice"), account("bob"), account("carol"));
+        let mut state = ActorState::new();
+        state.fund(100).unwrap();
+        let batch = Message::BatchTransfer { transfers: vec![(alice, 40), (bob, 40), (carol, 40)] };
+        let receipt = state.handle_message(&batch);
+        assert_eq!(receipt.exit_code, ExitCode::INSUFFICIENT_FUNDS);
+        assert_eq!(receipt.gas_used, GAS_BASE + 3 * GAS_PER_UPDATE);
+        assert_eq!(state.balance(), 100);
+        assert!(state.accounts.is_empty());
+
+        let receipt = state.handle_message(&Message::Transfer { to: alice, amount: 100 });
+        assert_eq!(receipt.events, vec![Event::Transferred { from: None, to: alice, amount: 100 }]);
+        assert_eq!((state.balance(), state.balance_of(&alice)), (0, 100));
+        let receipt = state.handle_message(&Message::QueryBalance { account: alice });
+        assert_eq!(fvm_ipld_encoding::from_slice::<u64>(&receipt.return_data).unwrap(), 100);
+    }
+
+    #[test]
+    fn test_flush_load_diff_and_rollback() {
+        let bs = fvm_ipld_blockstore::MemoryBlockstore::new();
+        let (alice, bob) = (account("alice"), account("bob"));
+        let mut state = ActorState::new();
+        state.fund(100).unwrap();
+        state.handle_message(&Message::Transfer { to: alice, amount: 60 });
+        state.handle_message(&Message::Delegate {
+            from: alice,
+            to: bob,
+            permissions: vec![Permission::Vote],
+            expires_at: None,
+        });
+        let proposal_id = propose(&mut state, alice, vec![]);
+        let first = state.flush(&bs).unwrap();
+        assert_eq!(state.flush(&bs).unwrap(), first);
+
+        state.handle_message(&Message::Transfer { to: bob, amount: 10 });
+        state.handle_message(&Message::Vote { proposal_id, voter: alice, support: true });
+        let second = state.flush(&bs).unwrap();
+
+        let diff = diff_roots(&bs, &first, &second).unwrap();
+        assert_eq!(diff.balance, Some((40, 30)));
+        assert_eq!(diff.added, vec![bob]);
+        assert_eq!(diff.changed, vec![alice]);
+        assert!(diff_roots(&bs, &second, &second).unwrap().is_empty());
+
+        let restored = ActorState::load(&bs, &second).unwrap();
+        assert_eq!(restored.balance_of(&bob), 10);
+        assert_eq!(restored.vote_of(proposal_id, &alice), Some(true));
+        assert_eq!(restored.proposal(proposal_id), state.proposal(proposal_id));
+        assert!(restored.is_authorized(&alice, &bob, &Permission::Vote));
+
+        let mut rolled_back = ActorState::load(&bs, &first).unwrap();
+        assert_eq!((rolled_back.balance(), rolled_back.balance_of(&bob)), (40, 0));
+        assert_eq!(rolled_back.flush(&bs).unwrap(), first);
+    }
+
+    #[test]
+    fn test_storage_keeps_actor_state_per_file() {
+        let mut storage = crate::MyStorage::new();
+        let cid = storage.store_sealed(b"some file").unwrap();
+        let mut state = storage.actor_state(&cid).unwrap().unwrap();
+        assert_eq!(state.balance(), 0);
+
+        let before = storage.actors_root().unwrap();
+        state.handle_message(&Message::Mint { to: account("alice"), amount: 5 });
+        storage.set_actor_state(&cid, &mut state).unwrap();
+        let stored = storage.actor_state(&cid).unwrap().unwrap();
+        assert_eq!(stored.balance_of(&account("alice")), 5);
+
+        storage.load_actors(before).unwrap();
+        assert_eq!(storage.actor_state(&cid).unwrap().unwrap().balance_of(&account("alice")), 0);
+    }
+
+    #[test]
+    fn test_signed_messages_are_authenticated() {
+        use crate::messages::{SecretKey, SigType};
+
+        let key = SecretKey::generate(SigType::Secp256k1).unwrap();
+        let (alice, bob) = (key.account(), account("bob"));
+        let mut state = ActorState::new();
+        state.handle_message(&Message::Mint { to: alice, amount: 100 });
+        let envelope = |nonce, message| UnsignedMessage {
+            from: alice,
+            nonce,
+            value: 5,
+            gas_limit: 1_000,
+            gas_fee_cap: 1,
+            gas_premium: 1,
+            message,
+        };
+
+        let mut bob_feed = state.subscribe(EventFilter::new().account(bob));
+        let first = envelope(0, Message::Transfer { to: bob, amount: 30 }).sign(&key);
+        assert!(state.apply_signed(&first).unwrap().is_success());
+        let logged = bob_feed.try_next().unwrap();
+        assert_eq!(logged.message, Some(first.message.cid()));
+        assert_eq!(logged.event, Event::Transferred { from: Some(alice), to: bob, amount: 30 });
+        assert_eq!((state.balance_of(&alice), state.balance_of(&bob), state.balance()), (65, 30, 5));
+        assert_eq!(state.apply_signed(&first), Err(ActorError::InvalidNonce { expected: 1, got: 0 }));
+
+        // A failed message still uses up its nonce but has no other effect.
+        let theft = envelope(1, Message::Withdraw { from: bob, amount: 30 }).sign(&key);
+        assert_eq!(state.apply_signed(&theft).unwrap().exit_code, ExitCode::FORBIDDEN);
+        assert_eq!((state.nonce_of(&alice), state.balance_of(&alice), state.balance_of(&bob)), (2, 65, 30));
+
+        let mut starved = envelope(2, Message::Transfer { to: bob, amount: 1 });
+        starved.gas_limit = GAS_BASE;
+        let receipt = state.apply_signed(&starved.sign(&key)).unwrap();
+        assert_eq!((receipt.exit_code, receipt.gas_used), (ExitCode::OUT_OF_GAS, GAS_BASE));
+
+        let mut forged = envelope(3, Message::Transfer { to: bob, amount: 1 }).sign(&key);
+        forged.message.value = 0;
+        assert_eq!(state.apply_signed(&forged), Err(ActorError::InvalidSignature));
+        assert_eq!(state.nonce_of(&alice), 3);
+        // Only the mint and the successful transfer were logged.
+        assert_eq!(state.events().query(&EventFilter::new().account(alice)).len(), 2);
+        assert!(bob_feed.try_next().is_none());
+    }
+
+    #[test]
+    fn test_state_encoding_golden_vector() {
+        use cid::multihash::Multihash;
+
+        let alice = Cid::new_v1(0x55, Multihash::wrap(0, b"alice").unwrap());
+        let bob = Cid::new_v1(0x55, Multihash::wrap(0, b"bob").unwrap());
+        let mut state = ActorState::new();
+        state.fund(10).unwrap();
+        state.epoch = 3;
+        state.handle_message(&Message::Mint { to: alice, amount: 50 });
+        propose(&mut state, alice, vec![Message::Transfer { to: bob, amount: 5 }]);
+        state.handle_message(&Message::Vote { proposal_id: 1, voter: alice, support: true });
+        state.handle_message(&Message::SetData { key: "k".to_string(), value: "v".to_string() });
+        state.handle_message(&Message::Delegate {
+            from: alice,
+            to: bob,
+            permissions: vec![Permission::Vote],
+            expires_at: None,
+        });
+
+        let bytes = fvm_ipld_encoding::to_vec(&state).unwrap();
+        let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+        assert_eq!(
+            hex,
+            "8a060a038182d82a4a0001550005616c696365841832008182d82a480001550003626f62838164766f7465f600818201f5\
+             818801d82a4a0001550005616c6963657070617920636f6e7472696275746f727381820182d82a480001550003626f620508830018321833\
+             8182d82a4a0001550005616c69636518320083001832183384606012f6183c808182616b82f6818401617603f6"
+        );
+
+        let bs = fvm_ipld_blockstore::MemoryBlockstore::new();
+        let mut decoded: ActorState = fvm_ipld_encoding::from_slice(&bytes).unwrap();
+        assert_eq!(decoded.flush(&bs).unwrap(), state.flush(&bs).unwrap());
+        assert!(decoded.is_authorized(&alice, &bob, &Permission::Vote));
+    }
+
+    mod supply {
+        use super::*;
+        use proptest::prelude::*;
+
+        fn message(accounts: &[Cid]) -> impl Strategy<Value = Message> {
+            let account = proptest::sample::select(accounts.to_vec());
+            prop_oneof![
+                (account.clone(), 0..1_000u64).prop_map(|(to, amount)| Message::Mint { to, amount }),
+                (account.clone(), 0..1_000u64).prop_map(|(from, amount)| Message::Burn { from, amount }),
+                (account.clone(), 0..1_000u64).prop_map(|(from, amount)| Message::Withdraw { from, amount }),
+                (account.clone(), 0..1_000u64).prop_map(|(to, amount)| Message::Transfer { to, amount }),
+                (account.clone(), account.clone(), 0..1_000u64)
+                    .prop_map(|(owner, to, amount)| Message::TransferFrom { owner, to, amount }),
+                proptest::collection::vec((account, 0..500u64), 0..4)
+                    .prop_map(|transfers| Message::BatchTransfer { transfers }),
+            ]
+        }
+
+        fn ledger_total(state: &ActorState) -> u128 {
+            state.balance() as u128 + state.accounts.values().map(|a| a.balance as u128).sum::<u128>()
+        }
+
+        proptest! {
+            #[test]
+            fn supply_matches_balances(
+                pool in 0..2_000u64,
+                messages in proptest::collection::vec(message(&[account("a"), account("b"), account("c")]), 1..40)
+            ) {
+                let mut state = ActorState::with_token(TokenInfo::new("Reward", "RWD").max_supply(5_000));
+                state.fund(pool).unwrap();
+                prop_assert_eq!(ledger_total(&state), pool as u128);
+                for message in &messages {
+                    let before = state.total_supply();
+                    let receipt = state.handle_message(message);
+                    prop_assert_eq!(ledger_total(&state), state.total_supply() as u128);
+                    prop_assert!(state.total_supply() <= 5_000);
+                    if !receipt.is_success() {
+                        prop_assert_eq!(state.total_supply(), before);
+                    }
+                }
+                let restored: ActorState =
+                    fvm_ipld_encoding::from_slice(&fvm_ipld_encoding::to_vec(&state).unwrap()).unwrap();
+                prop_assert_eq!(restored.total_supply(), state.total_supply());
+            }
+        }
+    }
+}
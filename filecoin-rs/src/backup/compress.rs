@@ -0,0 +1,115 @@
+// src/backup/compress.rs
+//
+// Payload compression, applied before encryption.
+
+use crate::backup::BackupError;
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+
+/// Compression algorithm applied to a backup payload.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Codec {
+    #[default]
+    None,
+    Zstd,
+    Gzip,
+}
+
+impl Codec {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Codec::None => "none",
+            Codec::Zstd => "zstd",
+            Codec::Gzip => "gzip",
+        }
+    }
+
+    /// Clamps `level` into the range the codec accepts.
+    fn clamp_level(&self, level: i32) -> i32 {
+        match self {
+            Codec::None => 0,
+            Codec::Zstd => {
+                let range = zstd::compression_level_range();
+                level.clamp(*range.start(), *range.end())
+            }
+            Codec::Gzip => level.clamp(0, 9),
+        }
+    }
+}
+
+impl std::str::FromStr for Codec {
+    type Err = BackupError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "none" => Ok(Codec::None),
+            "zstd" => Ok(Codec::Zstd),
+            "gzip" => Ok(Codec::Gzip),
+            other => Err(BackupError::Unsupported(other.to_string())),
+        }
+    }
+}
+
+/// A codec and the level it was (or should be) run at.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Compression {
+    pub codec: Codec,
+    pub level: i32,
+}
+
+impl Compression {
+    pub const ZSTD_DEFAULT_LEVEL: i32 = zstd::DEFAULT_COMPRESSION_LEVEL;
+
+    pub fn none() -> Self {
+        Compression::default()
+    }
+
+    pub fn zstd(level: i32) -> Self {
+        Compression::new(Codec::Zstd, level)
+    }
+
+    pub fn gzip(level: i32) -> Self {
+        Compression::new(Codec::Gzip, level)
+    }
+
+    /// `codec` at `level`, clamped to the levels the codec supports.
+    pub fn new(codec: Codec, level: i32) -> Self {
+        Compression {
+            codec,
+            level: codec.clamp_level(level),
+        }
+    }
+
+    pub fn compress(&self, data: &[u8]) -> Result<Vec<u8>, BackupError> {
+        let io = |e: std::io::Error| BackupError::Format(e.to_string());
+        match self.codec {
+            Codec::None => Ok(data.to_vec()),
+            Codec::Zstd => zstd::bulk::compress(data, self.level).map_err(io),
+            Codec::Gzip => {
+                let level = flate2::Compression::new(self.level as u32);
+                let mut encoder = flate2::write::GzEncoder::new(Vec::new(), level);
+                encoder.write_all(data).map_err(io)?;
+                encoder.finish().map_err(io)
+            }
+        }
+    }
+
+    /// Decompresses `data`, refusing to produce more than `max_size` bytes.
+    pub fn decompress(&self, data: &[u8], max_size: u64) -> Result<Vec<u8>, BackupError> {
+        let mut out = Vec::new();
+        let result = match self.codec {
+            Codec::None => return Ok(data.to_vec()),
+            Codec::Zstd => zstd::stream::read::Decoder::new(data)
+                .and_then(|d| d.take(max_size.saturating_add(1)).read_to_end(&mut out)),
+            Codec::Gzip => flate2::read::GzDecoder::new(data)
+                .take(max_size.saturating_add(1))
+                .read_to_end(&mut out),
+        };
+        result.map_err(|e| BackupError::Format(format!("{} decompression failed: {}", self.codec.name(), e)))?;
+        if out.len() as u64 > max_size {
+            return Err(BackupError::Format("decompressed payload exceeds recorded size".to_string()));
+        }
+        Ok(out)
+    }
+}
@@ -0,0 +1,140 @@
+// src/backup/crypto.rs
+//
+// Passphrase-based authenticated encryption for backup payloads.
+
+use crate::backup::BackupError;
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use serde::{Deserialize, Serialize};
+
+pub const CIPHER_XCHACHA20_POLY1305: &str = "xchacha20-poly1305";
+pub const KDF_ARGON2ID: &str = "argon2id";
+
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 24;
+const SALT_LEN: usize = 16;
+
+/// Bounds on the Argon2id cost a backup may ask for. The parameters come from
+/// the unauthenticated header, so they are checked before any key is
+/// derived; the maximums keep a forged header from exhausting memory or CPU.
+pub const MIN_MEMORY_KIB: u32 = 64;
+pub const MAX_MEMORY_KIB: u32 = 256 * 1024;
+pub const MAX_ITERATIONS: u32 = 16;
+pub const MAX_PARALLELISM: u32 = 16;
+
+/// Argon2id settings used to turn a passphrase into a key. Stored alongside
+/// the ciphertext so restores can re-derive the same key.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KdfParams {
+    pub algorithm: String,
+    pub salt: Vec<u8>,
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl KdfParams {
+    /// Argon2id with a fresh random salt and the OWASP-recommended cost
+    /// (19 MiB, 2 passes, 1 lane).
+    pub fn generate() -> Result<Self, BackupError> {
+        Self::with_cost(19 * 1024, 2, 1)
+    }
+
+    /// Argon2id with a fresh random salt and the given cost, which must be
+    /// within the bounds above.
+    pub fn with_cost(memory_kib: u32, iterations: u32, parallelism: u32) -> Result<Self, BackupError> {
+        let params = KdfParams {
+            algorithm: KDF_ARGON2ID.to_string(),
+            salt: random_bytes(SALT_LEN)?,
+            memory_kib,
+            iterations,
+            parallelism,
+        };
+        params.check_cost()?;
+        Ok(params)
+    }
+
+    pub fn derive_key(&self, passphrase: &str) -> Result<[u8; KEY_LEN], BackupError> {
+        if self.algorithm != KDF_ARGON2ID {
+            return Err(BackupError::Unsupported(self.algorithm.clone()));
+        }
+        self.check_cost()?;
+        let params = Params::new(self.memory_kib, self.iterations, self.parallelism, Some(KEY_LEN))
+            .map_err(|e| BackupError::Kdf(e.to_string()))?;
+        let mut key = [0u8; KEY_LEN];
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(passphrase.as_bytes(), &self.salt, &mut key)
+            .map_err(|e| BackupError::Kdf(e.to_string()))?;
+        Ok(key)
+    }
+
+    fn check_cost(&self) -> Result<(), BackupError> {
+        let out_of_range = |name: &str, value: u32, min: u32, max: u32| {
+            BackupError::Kdf(format!("{} {} outside {}..={}", name, value, min, max))
+        };
+        if !(MIN_MEMORY_KIB..=MAX_MEMORY_KIB).contains(&self.memory_kib) {
+            return Err(out_of_range("memory_kib", self.memory_kib, MIN_MEMORY_KIB, MAX_MEMORY_KIB));
+        }
+        if !(1..=MAX_ITERATIONS).contains(&self.iterations) {
+            return Err(out_of_range("iterations", self.iterations, 1, MAX_ITERATIONS));
+        }
+        if !(1..=MAX_PARALLELISM).contains(&self.parallelism) {
+            return Err(out_of_range("parallelism", self.parallelism, 1, MAX_PARALLELISM));
+        }
+        Ok(())
+    }
+}
+
+/// Describes how a payload was encrypted: cipher, nonce and key derivation.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Envelope {
+    pub cipher: String,
+    pub nonce: Vec<u8>,
+    pub kdf: KdfParams,
+}
+
+impl Envelope {
+    /// A new XChaCha20-Poly1305 envelope with a random nonce.
+    pub fn new(kdf: KdfParams) -> Result<Self, BackupError> {
+        Ok(Envelope {
+            cipher: CIPHER_XCHACHA20_POLY1305.to_string(),
+            nonce: random_bytes(NONCE_LEN)?,
+            kdf,
+        })
+    }
+
+    /// Encrypts `plaintext`, binding `aad` into the authentication tag.
+    pub fn seal(&self, passphrase: &str, plaintext: &[u8], aad: &[u8]) -> Result<Vec<u8>, BackupError> {
+        let cipher = self.cipher(passphrase)?;
+        cipher
+            .encrypt(XNonce::from_slice(&self.nonce), Payload { msg: plaintext, aad })
+            .map_err(|_| BackupError::Format("payload too large to encrypt".to_string()))
+    }
+
+    /// Decrypts `ciphertext`. A wrong passphrase and tampered data both fail
+    /// authentication and are reported as `BackupError::Decryption`.
+    pub fn open(&self, passphrase: &str, ciphertext: &[u8], aad: &[u8]) -> Result<Vec<u8>, BackupError> {
+        let cipher = self.cipher(passphrase)?;
+        cipher
+            .decrypt(XNonce::from_slice(&self.nonce), Payload { msg: ciphertext, aad })
+            .map_err(|_| BackupError::Decryption)
+    }
+
+    fn cipher(&self, passphrase: &str) -> Result<XChaCha20Poly1305, BackupError> {
+        if self.cipher != CIPHER_XCHACHA20_POLY1305 {
+            return Err(BackupError::Unsupported(self.cipher.clone()));
+        }
+        if self.nonce.len() != NONCE_LEN {
+            return Err(BackupError::Format(format!("nonce must be {} bytes", NONCE_LEN)));
+        }
+        let key = self.kdf.derive_key(passphrase)?;
+        Ok(XChaCha20Poly1305::new(&key.into()))
+    }
+}
+
+fn random_bytes(len: usize) -> Result<Vec<u8>, BackupError> {
+    let mut buf = vec![0u8; len];
+    getrandom::fill(&mut buf).map_err(|e| BackupError::Random(e.to_string()))?;
+    Ok(buf)
+}
@@ -0,0 +1,321 @@
+// src/backup/mod.rs
+//
+// Self-describing backup container. A backup is
+//
+//   "FCBK" | version (1 byte) | header length (u32 BE) | header JSON | payload
+//
+// where the header records how the payload was produced. The payload is the
+// SHA-256 digest of the data followed by the data itself, compressed and then
+// encrypted. When encrypted, the header bytes are
+// authenticated together with the ciphertext, so tampering with either is
+// detected on restore.
+
+pub mod compress;
+pub mod crypto;
+pub mod retention;
+pub mod snapshot;
+pub mod store;
+
+pub use compress::{Codec, Compression};
+pub use crypto::{Envelope, KdfParams};
+pub use retention::{CollectReport, PrunePlan, RetentionPolicy, SnapshotInfo};
+pub use snapshot::{SnapshotDiff, SnapshotManifest, SnapshotReceipt};
+pub use store::{backup_to, restore_from, BackupReceipt};
+
+use crate::storage::StorageError;
+use multihash_codetable::{Code, MultihashDigest};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+const MAGIC: &[u8; 4] = b"FCBK";
+const FORMAT_VERSION: u8 = 1;
+const PREAMBLE_LEN: usize = MAGIC.len() + 1 + 4;
+const DIGEST_LEN: usize = 32;
+/// Largest backup `open` restores. The size in an unencrypted header is not
+/// authenticated, so it only bounds decompression up to this limit.
+pub const MAX_BACKUP_SIZE: u64 = 4 << 30;
+
+/// Errors from sealing or opening a backup.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BackupError {
+    /// The data is not a backup container or is truncated.
+    Format(String),
+    /// The backup uses a cipher, KDF or format this build does not know.
+    Unsupported(String),
+    /// Key derivation failed, e.g. because of invalid cost parameters.
+    Kdf(String),
+    /// The backup is encrypted and no key was given.
+    KeyRequired,
+    /// Authentication failed: the key is wrong or the data was modified.
+    Decryption,
+    /// The system random number generator is unavailable.
+    Random(String),
+    /// The restored data does not match the digest recorded at backup time.
+    Integrity,
+    /// Storing or fetching the backup failed.
+    Storage(StorageError),
+}
+
+impl fmt::Display for BackupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BackupError::Format(e) => write!(f, "invalid backup: {}", e),
+            BackupError::Unsupported(what) => write!(f, "unsupported backup algorithm: {}", what),
+            BackupError::Kdf(e) => write!(f, "key derivation failed: {}", e),
+            BackupError::KeyRequired => write!(f, "backup is encrypted; a decryption key is required"),
+            BackupError::Decryption => write!(f, "decryption failed: wrong key or tampered backup"),
+            BackupError::Random(e) => write!(f, "random number generator failed: {}", e),
+            BackupError::Integrity => write!(f, "restored data does not match its recorded digest"),
+            BackupError::Storage(e) => write!(f, "backup storage error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for BackupError {}
+
+impl From<StorageError> for BackupError {
+    fn from(e: StorageError) -> Self {
+        BackupError::Storage(e)
+    }
+}
+
+/// Describes how the payload of a backup was produced.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BackupHeader {
+    /// Length of the original data.
+    pub size: u64,
+    /// How the data was compressed before encryption.
+    #[serde(default)]
+    pub compression: Compression,
+    /// Present when the payload is encrypted.
+    pub envelope: Option<Envelope>,
+}
+
+/// Packs `data` into a backup container, compressing it and then encrypting
+/// it when a passphrase is given. Compression is skipped when it does not
+/// make the data smaller. Returns the header alongside the container bytes.
+pub fn seal(
+    data: &[u8],
+    compression: Compression,
+    passphrase: Option<&str>,
+) -> Result<(BackupHeader, Vec<u8>), BackupError> {
+    let kdf = match passphrase {
+        Some(_) => Some(KdfParams::generate()?),
+        None => None,
+    };
+    seal_with_kdf(data, compression, passphrase.zip(kdf))
+}
+
+/// Like `seal`, with explicit key derivation parameters for the passphrase.
+pub fn seal_with_kdf(
+    data: &[u8],
+    compression: Compression,
+    key: Option<(&str, KdfParams)>,
+) -> Result<(BackupHeader, Vec<u8>), BackupError> {
+    if data.len() as u64 > MAX_BACKUP_SIZE {
+        return Err(BackupError::Format(format!("{} bytes exceeds the {} byte limit", data.len(), MAX_BACKUP_SIZE)));
+    }
+    let mut framed = Code::Sha2_256.digest(data).digest().to_vec();
+    framed.extend_from_slice(data);
+    let mut compressed = compression.compress(&framed)?;
+    let mut compression = compression;
+    if compressed.len() >= framed.len() {
+        compressed = framed;
+        compression = Compression::none();
+    }
+    let mut header = BackupHeader {
+        size: data.len() as u64,
+        compression,
+        envelope: None,
+    };
+    let passphrase = match key {
+        Some((passphrase, kdf)) => {
+            header.envelope = Some(Envelope::new(kdf)?);
+            Some(passphrase)
+        }
+        None => None,
+    };
+
+    let mut out = preamble(&header)?;
+    let payload = match (&header.envelope, passphrase) {
+        (Some(envelope), Some(passphrase)) => envelope.seal(passphrase, &compressed, &out)?,
+        _ => compressed,
+    };
+    out.extend_from_slice(&payload);
+    Ok((header, out))
+}
+
+/// Reads the header of a backup container without decrypting it.
+pub fn read_header(backup: &[u8]) -> Result<BackupHeader, BackupError> {
+    split(backup).map(|(header, _, _)| header)
+}
+
+/// Unpacks a backup container, decrypting it with `passphrase` if needed,
+/// decompressing it and checking the data against its recorded digest.
+pub fn open(backup: &[u8], passphrase: Option<&str>) -> Result<Vec<u8>, BackupError> {
+    let (header, aad, payload) = split(backup)?;
+    let compressed = match &header.envelope {
+        Some(envelope) => {
+            let passphrase = passphrase.ok_or(BackupError::KeyRequired)?;
+            envelope.open(passphrase, payload, aad)?
+        }
+        None => payload.to_vec(),
+    };
+    let expected = header
+        .size
+        .checked_add(DIGEST_LEN as u64)
+        .filter(|_| header.size <= MAX_BACKUP_SIZE)
+        .ok_or_else(|| BackupError::Format(format!("recorded size {} exceeds the {} byte limit", header.size, MAX_BACKUP_SIZE)))?;
+    let framed = header.compression.decompress(&compressed, expected)?;
+    if framed.len() as u64 != expected {
+        return Err(BackupError::Format(format!(
+            "expected {} bytes, got {}",
+            header.size,
+            framed.len().saturating_sub(DIGEST_LEN)
+        )));
+    }
+    let (digest, data) = framed.split_at(DIGEST_LEN);
+    if Code::Sha2_256.digest(data).digest() != digest {
+        return Err(BackupError::Integrity);
+    }
+    Ok(data.to_vec())
+}
+
+fn preamble(header: &BackupHeader) -> Result<Vec<u8>, BackupError> {
+    let json = serde_json::to_vec(header).map_err(|e| BackupError::Format(e.to_string()))?;
+    let mut out = Vec::with_capacity(PREAMBLE_LEN + json.len());
+    out.extend_from_slice(MAGIC);
+    out.push(FORMAT_VERSION);
+    out.extend_from_slice(&(json.len() as u32).to_be_bytes());
+    out.extend_from_slice(&json);
+    Ok(out)
+}
+
+/// Splits a container into its header, the authenticated header bytes and
+/// the payload.
+fn split(backup: &[u8]) -> Result<(BackupHeader, &[u8], &[u8]), BackupError> {
+    if backup.len() < PREAMBLE_LEN || &backup[..MAGIC.len()] != MAGIC {
+        return Err(BackupError::Format("missing backup header".to_string()));
+    }
+    if backup[MAGIC.len()] != FORMAT_VERSION {
+        return Err(BackupError::Unsupported(format!("format version {}", backup[MAGIC.len()])));
+    }
+    let len_bytes: [u8; 4] = backup[MAGIC.len() + 1..PREAMBLE_LEN].try_into().unwrap();
+    let end = PREAMBLE_LEN
+        .checked_add(u32::from_be_bytes(len_bytes) as usize)
+        .filter(|&end| end <= backup.len())
+        .ok_or_else(|| BackupError::Format("truncated header".to_string()))?;
+    let header = serde_json::from_slice(&backup[PREAMBLE_LEN..end]).map_err(|e| BackupError::Format(e.to_string()))?;
+    Ok((header, &backup[..end], &backup[end..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Cheap Argon2id parameters so tests stay fast in debug builds.
+    fn test_kdf() -> KdfParams {
+        KdfParams::with_cost(64, 1, 1).unwrap()
+    }
+
+    #[test]
+    fn test_encrypted_round_trip() {
+        let data = b"backup payload".to_vec();
+        let (header, sealed) = seal_with_kdf(&data, Compression::none(), Some(("correct horse", test_kdf()))).unwrap();
+        assert!(header.envelope.is_some());
+        assert_eq!(read_header(&sealed).unwrap(), header);
+        assert!(!sealed.windows(data.len()).any(|w| w == data.as_slice()));
+
+        assert_eq!(open(&sealed, Some("correct horse")).unwrap(), data);
+        assert_eq!(open(&sealed, Some("wrong")), Err(BackupError::Decryption));
+        assert_eq!(open(&sealed, None), Err(BackupError::KeyRequired));
+    }
+
+    #[test]
+    fn test_tampering_is_detected() {
+        let (_, sealed) = seal_with_kdf(b"backup payload", Compression::none(), Some(("pw", test_kdf()))).unwrap();
+
+        let mut payload = sealed.clone();
+        *payload.last_mut().unwrap() ^= 1;
+        assert_eq!(open(&payload, Some("pw")), Err(BackupError::Decryption));
+
+        // Changing the recorded size is caught by the authenticated header.
+        let mut header = sealed.clone();
+        let pos = header.windows(7).position(|w| w == b"\"size\":").unwrap() + 7;
+        header[pos] = b'9';
+        assert_eq!(open(&header, Some("pw")), Err(BackupError::Decryption));
+
+        assert!(matches!(open(b"not a backup", None), Err(BackupError::Format(_))));
+    }
+
+    #[test]
+    fn test_oversized_kdf_cost_is_rejected() {
+        let (header, sealed) = seal_with_kdf(b"backup payload", Compression::none(), Some(("pw", test_kdf()))).unwrap();
+        let payload = &sealed[preamble(&header).unwrap().len()..];
+
+        // A forged header asking for 4 TiB of memory fails before Argon2 runs.
+        let mut forged = header.clone();
+        forged.envelope.as_mut().unwrap().kdf.memory_kib = u32::MAX;
+        let mut backup = preamble(&forged).unwrap();
+        backup.extend_from_slice(payload);
+        assert!(matches!(open(&backup, Some("pw")), Err(BackupError::Kdf(e)) if e.starts_with("memory_kib")));
+
+        assert!(KdfParams::with_cost(64, crypto::MAX_ITERATIONS + 1, 1).is_err());
+        assert!(KdfParams::with_cost(64, 1, 0).is_err());
+        assert!(KdfParams::with_cost(crypto::MIN_MEMORY_KIB - 1, 1, 1).is_err());
+    }
+
+    #[test]
+    fn test_unencrypted_round_trip() {
+        let (header, sealed) = seal(b"plain", Compression::none(), None).unwrap();
+        assert_eq!(header.envelope, None);
+        assert_eq!(open(&sealed, Some("ignored")).unwrap(), b"plain");
+
+        let mut corrupted = sealed.clone();
+        *corrupted.last_mut().unwrap() ^= 1;
+        assert_eq!(open(&corrupted, None), Err(BackupError::Integrity));
+    }
+
+    #[test]
+    fn test_forged_size_is_rejected() {
+        let text = "zeros compress well ".repeat(1000);
+        let (header, sealed) = seal(text.as_bytes(), Compression::zstd(3), None).unwrap();
+        let payload = &sealed[preamble(&header).unwrap().len()..];
+        let forge = |size| {
+            let mut backup = preamble(&BackupHeader { size, ..header.clone() }).unwrap();
+            backup.extend_from_slice(payload);
+            open(&backup, None)
+        };
+
+        // The header of an unencrypted backup is not authenticated, so its
+        // size may be anything; none of these may panic or inflate unbounded.
+        for size in [u64::MAX, MAX_BACKUP_SIZE + 1, MAX_BACKUP_SIZE, 10] {
+            assert!(matches!(forge(size), Err(BackupError::Format(_))), "size {}", size);
+        }
+        assert_eq!(forge(header.size).unwrap(), text.as_bytes());
+        for compression in [Compression::zstd(3), Compression::gzip(6)] {
+            let compressed = compression.compress(b"unbounded").unwrap();
+            assert_eq!(compression.decompress(&compressed, u64::MAX).unwrap(), b"unbounded");
+        }
+    }
+
+    #[test]
+    fn test_compressed_round_trip() {
+        let text = "agent memory: the user prefers short answers.\n".repeat(200);
+        for compression in [Compression::zstd(19), Compression::gzip(6)] {
+            let (header, sealed) =
+                seal_with_kdf(text.as_bytes(), compression, Some(("pw", test_kdf()))).unwrap();
+            assert_eq!(header.compression, compression);
+            assert!(sealed.len() < text.len() / 10);
+            assert_eq!(open(&sealed, Some("pw")).unwrap(), text.as_bytes());
+        }
+
+        // Incompressible data is stored as-is and recorded as such.
+        let (header, sealed) = seal(&[7, 1, 9], Compression::zstd(3), None).unwrap();
+        assert_eq!(header.compression, Compression::none());
+        assert_eq!(open(&sealed, None).unwrap(), [7, 1, 9]);
+
+        assert_eq!(Compression::zstd(99).level, 22);
+        assert_eq!("GZIP".parse::<Codec>(), Ok(Codec::Gzip));
+    }
+}
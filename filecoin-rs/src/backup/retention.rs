@@ -0,0 +1,389 @@
+// src/backup/retention.rs
+//
+// Retention rules over a set of timestamped backups, in the style of
+// `restic forget`: a snapshot is kept if any keep rule selects it, and
+// everything else is pruned.
+
+use crate::backup::snapshot::load_manifest;
+use crate::blockstore::ManagedBlockstore;
+use crate::storage::index::reachable;
+use crate::storage::{StorageError, StorageProvider};
+use anyhow::Result;
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+const DAY: u64 = 86_400;
+
+/// Maps a snapshot (its position, newest first, and timestamp) to the period
+/// a keep rule counts it in.
+type BucketFn = fn(usize, u64) -> u64;
+
+/// Which backups to keep. Rules are combined: a backup selected by any keep
+/// rule is kept. With no keep rule set, every backup is kept. `max_age_secs`
+/// then expires anything older, regardless of the keep rules. The newest
+/// backup is never pruned.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetentionPolicy {
+    /// Keep the `n` most recent backups.
+    pub keep_last: Option<usize>,
+    /// Keep the newest backup of each of the last `n` days that have one.
+    pub keep_daily: Option<usize>,
+    /// Keep the newest backup of each of the last `n` ISO weeks that have one.
+    pub keep_weekly: Option<usize>,
+    /// Keep the newest backup of each of the last `n` months that have one.
+    pub keep_monthly: Option<usize>,
+    /// Expire backups older than this many seconds.
+    pub max_age_secs: Option<u64>,
+}
+
+/// A backup subject to retention.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotInfo {
+    pub cid: String,
+    /// Seconds since the Unix epoch.
+    pub timestamp: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct KeptSnapshot {
+    pub cid: String,
+    pub timestamp: u64,
+    /// Rules that selected this snapshot, e.g. `"daily"`.
+    pub reasons: Vec<&'static str>,
+}
+
+/// Outcome of evaluating a policy. Nothing is changed until the plan is
+/// passed to `apply_plan`, or to `MyStorage::apply_retention` for snapshots
+/// stored there.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct PrunePlan {
+    /// Newest first.
+    pub keep: Vec<KeptSnapshot>,
+    /// Newest first.
+    pub prune: Vec<SnapshotInfo>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PruneReport {
+    pub unpinned: Vec<String>,
+    pub failed: Vec<(String, StorageError)>,
+}
+
+/// Outcome of `MyStorage::apply_retention`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct CollectReport {
+    /// Snapshots removed, newest first.
+    pub removed: Vec<String>,
+    /// Blocks deleted because nothing left in the store uses them.
+    pub freed_blocks: usize,
+}
+
+impl RetentionPolicy {
+    fn has_keep_rules(&self) -> bool {
+        self.keep_last.is_some() || self.keep_daily.is_some() || self.keep_weekly.is_some() || self.keep_monthly.is_some()
+    }
+
+    /// Decides which of `snapshots` to keep at time `now`.
+    pub fn plan(&self, snapshots: &[SnapshotInfo], now: u64) -> PrunePlan {
+        let mut sorted = snapshots.to_vec();
+        sorted.sort_by(|a, b| b.timestamp.cmp(&a.timestamp).then_with(|| a.cid.cmp(&b.cid)));
+        // A CID listed more than once counts once, at its newest timestamp.
+        let mut seen = HashSet::new();
+        sorted.retain(|s| seen.insert(s.cid.clone()));
+
+        let mut reasons: Vec<Vec<&'static str>> = vec![Vec::new(); sorted.len()];
+        if !self.has_keep_rules() {
+            reasons.iter_mut().for_each(|r| r.push("all"));
+        }
+        let rules: [(Option<usize>, &'static str, BucketFn); 4] = [
+            (self.keep_last, "last", |i, _| i as u64),
+            (self.keep_daily, "daily", |_, ts| ts / DAY),
+            (self.keep_weekly, "weekly", |_, ts| iso_week(ts)),
+            (self.keep_monthly, "monthly", |_, ts| month(ts)),
+        ];
+        for (count, name, bucket_of) in rules {
+            let Some(count) = count else { continue };
+            let mut kept = 0;
+            let mut last_bucket = None;
+            for (i, snapshot) in sorted.iter().enumerate() {
+                if kept == count {
+                    break;
+                }
+                let bucket = bucket_of(i, snapshot.timestamp);
+                if last_bucket != Some(bucket) {
+                    reasons[i].push(name);
+                    kept += 1;
+                    last_bucket = Some(bucket);
+                }
+            }
+        }
+        if let Some(max_age) = self.max_age_secs {
+            for (i, snapshot) in sorted.iter().enumerate() {
+                if now.saturating_sub(snapshot.timestamp) > max_age {
+                    reasons[i].clear();
+                }
+            }
+        }
+        if let Some(newest) = reasons.first_mut() {
+            if newest.is_empty() {
+                newest.push("newest");
+            }
+        }
+
+        let mut plan = PrunePlan::default();
+        for (snapshot, reasons) in sorted.into_iter().zip(reasons) {
+            if reasons.is_empty() {
+                plan.prune.push(snapshot);
+            } else {
+                plan.keep.push(KeptSnapshot {
+                    cid: snapshot.cid,
+                    timestamp: snapshot.timestamp,
+                    reasons,
+                });
+            }
+        }
+        plan
+    }
+}
+
+/// Lists the snapshot chain ending at `head`, newest first, by following
+/// manifest parent links. The walk stops at a parent that is no longer
+/// stored, e.g. because it was pruned and collected.
+pub fn snapshot_chain<B: Blockstore + ?Sized>(store: &B, head: &Cid) -> Result<Vec<SnapshotInfo>> {
+    let mut chain = Vec::new();
+    let mut next = Some(*head);
+    while let Some(cid) = next {
+        if !chain.is_empty() && !store.has(&cid)? {
+            log::warn!("snapshot chain ends at missing parent {}", cid);
+            break;
+        }
+        let manifest = load_manifest(store, &cid)?;
+        chain.push(SnapshotInfo {
+            cid: cid.to_string(),
+            timestamp: manifest.timestamp,
+        });
+        next = manifest.parent;
+    }
+    Ok(chain)
+}
+
+/// Unpins every snapshot in `plan.prune` through `provider`. Failures are
+/// collected rather than stopping the run; a snapshot the provider no longer
+/// knows counts as unpinned.
+pub async fn apply_plan<P: StorageProvider + Sync + ?Sized>(provider: &P, plan: &PrunePlan) -> PruneReport {
+    let mut report = PruneReport::default();
+    for snapshot in &plan.prune {
+        match provider.unpin(&snapshot.cid).await {
+            Ok(()) | Err(StorageError::NotFound(_)) => report.unpinned.push(snapshot.cid.clone()),
+            Err(e) => report.failed.push((snapshot.cid.clone(), e)),
+        }
+    }
+    report
+}
+
+/// Deletes from `store` every block reachable from `pruned` but not from
+/// `live`, and returns how many were deleted. Chunks a pruned snapshot shares
+/// with a live one, or with a file, survive.
+pub fn collect_snapshots<B: ManagedBlockstore + ?Sized>(store: &B, pruned: &[Cid], live: &[Cid]) -> Result<usize> {
+    let live = reachable(store, live)?;
+    let mut freed = 0;
+    for cid in reachable(store, pruned)? {
+        if !live.contains(&cid) && store.delete(&cid)? {
+            freed += 1;
+        }
+    }
+    Ok(freed)
+}
+
+/// ISO week number since the epoch; weeks start on Monday and 1970-01-01
+/// was a Thursday.
+fn iso_week(ts: u64) -> u64 {
+    (ts / DAY + 3) / 7
+}
+
+/// Months since year 0, using the proleptic Gregorian calendar.
+fn month(ts: u64) -> u64 {
+    let (year, month) = civil_from_days((ts / DAY) as i64);
+    (year * 12 + month - 1) as u64
+}
+
+/// Converts days since 1970-01-01 to a (year, month) pair (Howard Hinnant's
+/// `civil_from_days`).
+fn civil_from_days(days: i64) -> (i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorageProvider;
+    use futures::executor::block_on;
+
+    // 2024-01-01T12:00:00Z, a Monday.
+    const JAN_1: u64 = 1_704_110_400;
+
+    fn daily_snapshots(days: u64) -> Vec<SnapshotInfo> {
+        (0..days)
+            .map(|d| SnapshotInfo {
+                cid: format!("snap-{:03}", d),
+                timestamp: JAN_1 + d * DAY,
+            })
+            .collect()
+    }
+
+    fn kept(plan: &PrunePlan) -> Vec<&str> {
+        plan.keep.iter().map(|k| k.cid.as_str()).collect()
+    }
+
+    #[test]
+    fn test_calendar_buckets() {
+        assert_eq!(civil_from_days(0), (1970, 1));
+        assert_eq!(civil_from_days((JAN_1 / DAY) as i64), (2024, 1));
+        assert_eq!(civil_from_days((JAN_1 / DAY) as i64 + 59), (2024, 2)); // leap day, Feb 29
+        assert_eq!(iso_week(JAN_1 - DAY), iso_week(JAN_1) - 1); // Sunday belongs to the previous week
+        assert_eq!(iso_week(JAN_1 + 6 * DAY), iso_week(JAN_1));
+    }
+
+    #[test]
+    fn test_keep_rules_combine() {
+        // Two snapshots a day for 70 days.
+        let mut snapshots = daily_snapshots(70);
+        snapshots.extend(daily_snapshots(70).into_iter().map(|s| SnapshotInfo {
+            cid: format!("{}-am", s.cid),
+            timestamp: s.timestamp - 6 * 3600,
+        }));
+        let policy = RetentionPolicy {
+            keep_last: Some(3),
+            keep_daily: Some(7),
+            keep_weekly: Some(4),
+            keep_monthly: Some(3),
+            max_age_secs: None,
+        };
+        let plan = policy.plan(&snapshots, JAN_1 + 70 * DAY);
+        assert_eq!(plan.keep.len() + plan.prune.len(), 140);
+        assert_eq!(plan.keep[0].reasons, vec!["last", "daily", "weekly", "monthly"]);
+        // The last 3 snapshots, the newest of each of the last 7 days, the
+        // last 4 ISO weeks (Mondays are days 63, 56, 49, ...) and the last 3
+        // months (day 59 is Feb 29, day 30 is Jan 31).
+        assert_eq!(
+            kept(&plan),
+            vec![
+                "snap-069", "snap-069-am", "snap-068", "snap-067", "snap-066", "snap-065", "snap-064",
+                "snap-063", "snap-062", "snap-059", "snap-055", "snap-048", "snap-030",
+            ]
+        );
+        assert!(plan.prune.iter().all(|p| p.timestamp < plan.keep[0].timestamp));
+    }
+
+    #[test]
+    fn test_max_age_expires_but_keeps_newest() {
+        let snapshots = daily_snapshots(10);
+        let policy = RetentionPolicy {
+            max_age_secs: Some(3 * DAY),
+            ..RetentionPolicy::default()
+        };
+        let now = JAN_1 + 9 * DAY;
+        assert_eq!(kept(&policy.plan(&snapshots, now)), vec!["snap-009", "snap-008", "snap-007", "snap-006"]);
+
+        let plan = policy.plan(&snapshots, now + 100 * DAY);
+        assert_eq!(kept(&plan), vec!["snap-009"]);
+        assert_eq!(plan.keep[0].reasons, vec!["newest"]);
+    }
+
+    #[test]
+    fn test_duplicate_cid_counts_once() {
+        let mut snapshots = daily_snapshots(3);
+        snapshots.push(SnapshotInfo {
+            cid: "snap-000".to_string(),
+            timestamp: JAN_1 + 5 * DAY,
+        });
+        let policy = RetentionPolicy {
+            keep_last: Some(2),
+            ..RetentionPolicy::default()
+        };
+        let plan = policy.plan(&snapshots, JAN_1 + 5 * DAY);
+        assert_eq!(kept(&plan), vec!["snap-000", "snap-002"]);
+        assert_eq!(plan.keep[0].timestamp, JAN_1 + 5 * DAY);
+        assert_eq!(plan.prune, vec![snapshots[1].clone()]);
+    }
+
+    #[test]
+    fn test_apply_plan_unpins() {
+        let provider = MemoryStorageProvider::new();
+        let mut snapshots = Vec::new();
+        for day in 0..3u64 {
+            let cid = block_on(provider.upload(format!("backup {}", day).into_bytes())).unwrap();
+            block_on(provider.pin(&cid)).unwrap();
+            snapshots.push(SnapshotInfo {
+                cid,
+                timestamp: JAN_1 + day * DAY,
+            });
+        }
+        let policy = RetentionPolicy {
+            keep_last: Some(1),
+            ..RetentionPolicy::default()
+        };
+        let plan = policy.plan(&snapshots, JAN_1 + 3 * DAY);
+        let report = block_on(apply_plan(&provider, &plan));
+        assert_eq!(report.unpinned.len(), 2);
+        assert!(report.failed.is_empty());
+
+        // Unpinned snapshots can now be deleted; the kept one is still pinned.
+        for cid in &report.unpinned {
+            block_on(provider.delete(cid)).unwrap();
+        }
+        assert!(matches!(block_on(provider.delete(&plan.keep[0].cid)), Err(StorageError::Pinned(_))));
+    }
+
+    #[test]
+    fn test_apply_retention_frees_pruned_snapshots() {
+        let mut storage = crate::MyStorage::new();
+        let file = block_on(storage.upload(b"shared".to_vec())).unwrap();
+        let mut head: Option<crate::backup::SnapshotReceipt> = None;
+        let mut receipts = Vec::new();
+        for day in 0..3u64 {
+            let entries = [
+                ("shared.txt".to_string(), b"shared".to_vec()),
+                ("day.txt".to_string(), format!("day {}", day).into_bytes()),
+            ]
+            .into_iter()
+            .collect();
+            let parent = head.as_ref().map(|h| h.cid.to_string());
+            let receipt = storage.snapshot(&entries, parent.as_deref(), JAN_1 + day * DAY).unwrap();
+            head = Some(receipt.clone());
+            receipts.push(receipt);
+        }
+        let head = head.unwrap().cid.to_string();
+
+        let policy = RetentionPolicy {
+            keep_last: Some(1),
+            ..RetentionPolicy::default()
+        };
+        let plan = policy.plan(&storage.snapshot_history(&head).unwrap(), JAN_1 + 3 * DAY);
+        let report = storage.apply_retention(&plan).unwrap();
+        assert_eq!(report.removed, vec![receipts[1].cid.to_string(), receipts[0].cid.to_string()]);
+        assert!(report.freed_blocks > 0);
+
+        // The pruned manifests and their own chunks are gone; the shared
+        // chunk, the uploaded file and the newest snapshot are intact.
+        let day_chunk = |r: &crate::backup::SnapshotReceipt| r.manifest.entries[0].cid.to_string();
+        assert!(!storage.has(&receipts[0].cid.to_string()).unwrap());
+        assert!(!storage.has(&day_chunk(&receipts[0])).unwrap());
+        assert!(storage.has(&receipts[0].manifest.entries[1].cid.to_string()).unwrap());
+        assert!(storage.has(&file).unwrap());
+        assert_eq!(storage.restore_snapshot(&head, None).unwrap()["day.txt"], b"day 2");
+        assert_eq!(storage.snapshot_history(&head).unwrap().len(), 1);
+        assert_eq!(storage.apply_retention(&plan).unwrap().freed_blocks, 0);
+    }
+}
@@ -0,0 +1,354 @@
+// src/backup/snapshot.rs
+//
+// Incremental snapshots of a set of named entries (a directory tree or a
+// key/value set). Each entry is stored as a UnixFS file split with a
+// content-defined chunker, so unchanged files and unchanged regions of edited
+// files map to blocks that are already stored and are not written again.
+// A DAG-CBOR manifest lists the entries and names the parent snapshot.
+
+use crate::unixfs::{self, verify_block, RabinChunker};
+use anyhow::{anyhow, bail, Result};
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+use fvm_ipld_encoding::tuple::*;
+use fvm_ipld_encoding::{BytesDe, BytesSer, DAG_CBOR};
+use multihash_codetable::{Code, MultihashDigest};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::cell::Cell;
+use std::collections::BTreeMap;
+
+/// Manifest format written by `create_snapshot`.
+pub const MANIFEST_VERSION: u64 = 2;
+
+/// Entries of a snapshot, keyed by `/`-separated relative path.
+pub type Entries = BTreeMap<String, Vec<u8>>;
+
+/// Root block of a snapshot.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize_tuple, Deserialize_tuple)]
+pub struct SnapshotManifest {
+    pub version: u64,
+    /// Stored as the CID's bytes rather than as a link, so garbage
+    /// collection does not keep every ancestor of a kept snapshot alive.
+    #[serde(with = "parent_ref")]
+    pub parent: Option<Cid>,
+    /// Creation time in seconds since the Unix epoch.
+    pub timestamp: u64,
+    /// Sorted by path.
+    pub entries: Vec<SnapshotEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize_tuple, Deserialize_tuple)]
+pub struct SnapshotEntry {
+    pub path: String,
+    /// Root of the entry's UnixFS file.
+    pub cid: Cid,
+    pub size: u64,
+}
+
+/// Block-level write statistics for one snapshot.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SnapshotStats {
+    pub new_blocks: u64,
+    pub reused_blocks: u64,
+    pub new_bytes: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotReceipt {
+    pub cid: Cid,
+    pub manifest: SnapshotManifest,
+    pub stats: SnapshotStats,
+}
+
+/// Paths that differ between two snapshots.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct SnapshotDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub changed: Vec<String>,
+}
+
+/// Stores `entries` as a snapshot whose manifest names `parent`, writing
+/// only blocks the store does not already hold.
+pub fn create_snapshot<B: Blockstore + ?Sized>(
+    store: &B,
+    entries: &Entries,
+    parent: Option<Cid>,
+    timestamp: u64,
+) -> Result<SnapshotReceipt> {
+    if let Some(parent) = &parent {
+        load_manifest(store, parent)?;
+    }
+    let store = DedupStore::new(store);
+    let chunker = RabinChunker::default();
+    let mut manifest_entries = Vec::with_capacity(entries.len());
+    for (path, data) in entries {
+        manifest_entries.push(SnapshotEntry {
+            path: normalize_path(path)?,
+            cid: unixfs::add_bytes(&store, &chunker, data)?,
+            size: data.len() as u64,
+        });
+    }
+    manifest_entries.sort_by(|a, b| a.path.cmp(&b.path));
+    if let Some(pair) = manifest_entries.windows(2).find(|w| w[0].path == w[1].path) {
+        bail!("duplicate snapshot path {}", pair[0].path);
+    }
+
+    let manifest = SnapshotManifest {
+        version: MANIFEST_VERSION,
+        parent,
+        timestamp,
+        entries: manifest_entries,
+    };
+    let bytes = fvm_ipld_encoding::to_vec(&manifest)?;
+    let cid = Cid::new_v1(DAG_CBOR, Code::Sha2_256.digest(&bytes));
+    store.put_keyed(&cid, &bytes)?;
+    Ok(SnapshotReceipt {
+        cid,
+        manifest,
+        stats: store.stats(),
+    })
+}
+
+/// Loads and verifies the manifest stored under `cid`.
+pub fn load_manifest<B: Blockstore + ?Sized>(store: &B, cid: &Cid) -> Result<SnapshotManifest> {
+    if cid.codec() != DAG_CBOR {
+        bail!("{} is not a snapshot manifest", cid);
+    }
+    let block = store
+        .get(cid)?
+        .ok_or(unixfs::DagError::NotFound(*cid))?;
+    verify_block(cid, &block)?;
+    let manifest: SnapshotManifest = fvm_ipld_encoding::from_slice(&block)
+        .map_err(|e| anyhow!("invalid snapshot manifest {}: {}", cid, e))?;
+    if manifest.version != MANIFEST_VERSION {
+        bail!("unsupported snapshot manifest version {}", manifest.version);
+    }
+    Ok(manifest)
+}
+
+/// Restores the entries of the snapshot under `cid`. With a `filter`, only
+/// that path and the entries beneath it are returned.
+pub fn restore_snapshot<B: Blockstore + ?Sized>(store: &B, cid: &Cid, filter: Option<&str>) -> Result<Entries> {
+    let filter = filter.map(normalize_path).transpose()?;
+    let manifest = load_manifest(store, cid)?;
+    let mut entries = Entries::new();
+    for entry in manifest.entries {
+        if filter.as_deref().is_some_and(|f| !is_within(&entry.path, f)) {
+            continue;
+        }
+        let data = unixfs::read_file(store, &entry.cid)?;
+        entries.insert(entry.path, data);
+    }
+    Ok(entries)
+}
+
+/// Lists the entries added, removed and changed going from snapshot `a` to
+/// snapshot `b`.
+pub fn diff_snapshots<B: Blockstore + ?Sized>(store: &B, a: &Cid, b: &Cid) -> Result<SnapshotDiff> {
+    let old: BTreeMap<String, Cid> = load_manifest(store, a)?
+        .entries
+        .into_iter()
+        .map(|e| (e.path, e.cid))
+        .collect();
+    let new = load_manifest(store, b)?.entries;
+
+    let mut diff = SnapshotDiff::default();
+    for entry in &new {
+        match old.get(&entry.path) {
+            None => diff.added.push(entry.path.clone()),
+            Some(cid) if *cid != entry.cid => diff.changed.push(entry.path.clone()),
+            Some(_) => {}
+        }
+    }
+    let new_paths: std::collections::BTreeSet<&str> = new.iter().map(|e| e.path.as_str()).collect();
+    diff.removed = old
+        .into_keys()
+        .filter(|path| !new_paths.contains(path.as_str()))
+        .collect();
+    Ok(diff)
+}
+
+/// Reads every regular file under `root`, keyed by its path relative to
+/// `root`.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn read_dir_entries<P: AsRef<std::path::Path>>(root: P) -> Result<Entries> {
+    let root = root.as_ref();
+    let mut entries = Entries::new();
+    let mut stack = vec![root.to_path_buf()];
+    while let Some(dir) = stack.pop() {
+        for item in std::fs::read_dir(&dir)? {
+            let item = item?;
+            let path = item.path();
+            if item.file_type()?.is_dir() {
+                stack.push(path);
+            } else if item.file_type()?.is_file() {
+                let relative = path
+                    .strip_prefix(root)?
+                    .components()
+                    .map(|c| c.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/");
+                entries.insert(relative, std::fs::read(&path)?);
+            }
+        }
+    }
+    Ok(entries)
+}
+
+/// Writes restored entries below `dest`, creating directories as needed.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn write_entries<P: AsRef<std::path::Path>>(dest: P, entries: &Entries) -> Result<()> {
+    for (path, data) in entries {
+        let target = dest.as_ref().join(normalize_path(path)?);
+        if let Some(dir) = target.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(target, data)?;
+    }
+    Ok(())
+}
+
+/// Canonical `a/b/c` form of an entry path. Rejects empty, `.` and `..`
+/// components so restored paths cannot escape the destination.
+fn normalize_path(path: &str) -> Result<String> {
+    let parts: Vec<&str> = path
+        .split(['/', '\\'])
+        .filter(|p| !p.is_empty())
+        .collect();
+    if parts.is_empty() || parts.iter().any(|p| *p == "." || *p == "..") {
+        bail!("invalid snapshot path {:?}", path);
+    }
+    Ok(parts.join("/"))
+}
+
+fn is_within(path: &str, prefix: &str) -> bool {
+    path == prefix || path.strip_prefix(prefix).is_some_and(|rest| rest.starts_with('/'))
+}
+
+/// (De)serializes a manifest's parent as an optional byte string.
+mod parent_ref {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(parent: &Option<Cid>, s: S) -> Result<S::Ok, S::Error> {
+        parent.map(|cid| cid.to_bytes()).as_deref().map(BytesSer).serialize(s)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Option<Cid>, D::Error> {
+        let bytes: Option<BytesDe> = Deserialize::deserialize(d)?;
+        bytes
+            .map(|BytesDe(bytes)| Cid::try_from(bytes.as_slice()).map_err(de::Error::custom))
+            .transpose()
+    }
+}
+
+/// Write-through view of a blockstore that skips blocks already present and
+/// counts what was actually written.
+struct DedupStore<'a, B: ?Sized> {
+    inner: &'a B,
+    stats: Cell<SnapshotStats>,
+}
+
+impl<'a, B: Blockstore + ?Sized> DedupStore<'a, B> {
+    fn new(inner: &'a B) -> Self {
+        DedupStore {
+            inner,
+            stats: Cell::new(SnapshotStats::default()),
+        }
+    }
+
+    fn stats(&self) -> SnapshotStats {
+        self.stats.get()
+    }
+}
+
+impl<B: Blockstore + ?Sized> Blockstore for DedupStore<'_, B> {
+    fn get(&self, k: &Cid) -> Result<Option<Vec<u8>>> {
+        self.inner.get(k)
+    }
+
+    fn put_keyed(&self, k: &Cid, block: &[u8]) -> Result<()> {
+        let mut stats = self.stats.get();
+        if self.inner.has(k)? {
+            stats.reused_blocks += 1;
+        } else {
+            self.inner.put_keyed(k, block)?;
+            stats.new_blocks += 1;
+            stats.new_bytes += block.len() as u64;
+        }
+        self.stats.set(stats);
+        Ok(())
+    }
+
+    fn has(&self, k: &Cid) -> Result<bool> {
+        self.inner.has(k)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fvm_ipld_blockstore::MemoryBlockstore;
+
+    fn entries(items: &[(&str, Vec<u8>)]) -> Entries {
+        items.iter().map(|(p, d)| (p.to_string(), d.clone())).collect()
+    }
+
+    #[test]
+    fn test_incremental_snapshot_reuses_blocks() {
+        let bs = MemoryBlockstore::new();
+        let big: Vec<u8> = (0..2_000_000u32).map(|i| (i.wrapping_mul(2654435761) >> 11) as u8).collect();
+        let first = entries(&[
+            ("memory/notes.txt", b"remember the milk".to_vec()),
+            ("memory/large.bin", big.clone()),
+            ("config.json", b"{}".to_vec()),
+        ]);
+        let a = create_snapshot(&bs, &first, None, 100).unwrap();
+        assert!(a.stats.new_blocks > 3);
+
+        // Append to the large file, edit one entry, drop another, add one.
+        let mut grown = big.clone();
+        grown.extend_from_slice(b"tail");
+        let second = entries(&[
+            ("memory/notes.txt", b"remember the eggs".to_vec()),
+            ("memory/large.bin", grown.clone()),
+            ("memory/new.txt", b"new".to_vec()),
+        ]);
+        let b = create_snapshot(&bs, &second, Some(a.cid), 200).unwrap();
+        assert_eq!(b.manifest.parent, Some(a.cid));
+        assert_eq!(load_manifest(&bs, &b.cid).unwrap(), b.manifest);
+        assert!(b.stats.reused_blocks > 0);
+        assert!(b.stats.new_bytes < big.len() as u64 / 2);
+
+        let diff = diff_snapshots(&bs, &a.cid, &b.cid).unwrap();
+        assert_eq!(diff.added, vec!["memory/new.txt"]);
+        assert_eq!(diff.removed, vec!["config.json"]);
+        assert_eq!(diff.changed, vec!["memory/large.bin", "memory/notes.txt"]);
+
+        assert_eq!(restore_snapshot(&bs, &b.cid, None).unwrap(), second);
+        let filtered = restore_snapshot(&bs, &a.cid, Some("/memory")).unwrap();
+        assert_eq!(filtered.keys().collect::<Vec<_>>(), vec!["memory/large.bin", "memory/notes.txt"]);
+    }
+
+    #[test]
+    fn test_parent_does_not_keep_pruned_snapshot_alive() {
+        use crate::storage::index::reachable;
+
+        let bs = MemoryBlockstore::new();
+        let old = create_snapshot(&bs, &entries(&[("notes.txt", b"old notes".to_vec())]), None, 100).unwrap();
+        let new = create_snapshot(&bs, &entries(&[("notes.txt", b"new notes".to_vec())]), Some(old.cid), 200).unwrap();
+
+        // Collecting around the newest snapshot alone frees the older one.
+        let live = reachable(&bs, [&new.cid]).unwrap();
+        assert!(live.contains(&new.manifest.entries[0].cid));
+        assert!(!live.contains(&old.cid) && !live.contains(&old.manifest.entries[0].cid));
+    }
+
+    #[test]
+    fn test_rejects_escaping_paths() {
+        let bs = MemoryBlockstore::new();
+        assert!(create_snapshot(&bs, &entries(&[("../etc/passwd", vec![1])]), None, 0).is_err());
+        assert!(create_snapshot(&bs, &entries(&[("a//b", vec![1]), ("a/b", vec![2])]), None, 0).is_err());
+        assert!(is_within("a/b", "a") && !is_within("ab", "a"));
+    }
+}
@@ -0,0 +1,76 @@
+// src/backup/store.rs
+//
+// Storing backup containers through a `StorageProvider`.
+
+use crate::backup::{self, BackupError, BackupHeader, Compression};
+use crate::storage::StorageProvider;
+
+/// A backup that has been stored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackupReceipt {
+    /// Root CID of the stored container.
+    pub cid: String,
+    pub header: BackupHeader,
+    /// Size of the container after compression and encryption.
+    pub stored_size: u64,
+}
+
+/// Seals `data` into a backup container and uploads it to `provider`.
+pub async fn backup_to<P: StorageProvider + ?Sized>(
+    provider: &P,
+    data: &[u8],
+    compression: Compression,
+    passphrase: Option<&str>,
+) -> Result<BackupReceipt, BackupError> {
+    let (header, sealed) = backup::seal(data, compression, passphrase)?;
+    let stored_size = sealed.len() as u64;
+    let cid = provider.upload(sealed).await?;
+    Ok(BackupReceipt {
+        cid,
+        header,
+        stored_size,
+    })
+}
+
+/// Fetches the backup stored under `cid` from `provider` and returns the
+/// original data.
+pub async fn restore_from<P: StorageProvider + ?Sized>(
+    provider: &P,
+    cid: &str,
+    passphrase: Option<&str>,
+) -> Result<Vec<u8>, BackupError> {
+    let sealed = provider.download(cid).await?;
+    backup::open(&sealed, passphrase)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{MemoryStorageProvider, StorageError};
+    use futures::executor::block_on;
+
+    #[test]
+    fn test_round_trip_through_provider() {
+        let provider = MemoryStorageProvider::new();
+        let data = "nightly agent memory\n".repeat(100);
+
+        let receipt = block_on(backup_to(&provider, data.as_bytes(), Compression::zstd(3), None)).unwrap();
+        assert_eq!(receipt.header.size, data.len() as u64);
+        assert!(receipt.stored_size < data.len() as u64);
+        assert_eq!(block_on(restore_from(&provider, &receipt.cid, None)).unwrap(), data.as_bytes());
+
+        let missing = "bafkreifzjut3te2nhyekklss27nh3k72ysco7y32koao5eei66wof36n5e";
+        assert!(matches!(
+            block_on(restore_from(&provider, missing, None)),
+            Err(BackupError::Storage(StorageError::NotFound(_)))
+        ));
+    }
+
+    #[test]
+    fn test_round_trip_through_my_storage() {
+        let mut storage = crate::MyStorage::new();
+        let receipt = storage.backup(b"wallet notes", Compression::gzip(9), None).unwrap();
+        assert!(storage.has(&receipt.cid).unwrap());
+        assert_eq!(storage.restore_backup(&receipt.cid, None).unwrap(), b"wallet notes");
+    }
+}
@@ -0,0 +1,255 @@
+// src/blockstore/fs.rs
+//
+// File-backed blockstore so blocks survive process restarts.
+
+use crate::blockstore::ManagedBlockstore;
+use anyhow::{anyhow, Result};
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+use std::fs::{self, File};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+const BLOCK_EXT: &str = "data";
+const TEMP_EXT: &str = "tmp";
+const ROOT_EXT: &str = "root";
+
+/// Blockstore that keeps one file per block under a root directory.
+///
+/// Blocks are sharded into sub-directories named after the two characters
+/// before the last character of the CID string (the same "next-to-last/2"
+/// scheme used by go-ds-flatfs), which keeps directory sizes bounded.
+///
+/// Writes go to a temporary file that is fsynced and then renamed into place,
+/// so a block is either fully present or absent. Temporary files left behind
+/// by a crash are removed the next time the store is opened. Named roots are
+/// kept the same way, as `<name>.root` files in the root directory.
+#[derive(Debug)]
+pub struct FsBlockstore {
+    root: PathBuf,
+    counter: AtomicU64,
+}
+
+impl FsBlockstore {
+    /// Opens (or creates) a blockstore rooted at `root`, cleaning up any
+    /// partially written blocks from a previous run.
+    pub fn open<P: AsRef<Path>>(root: P) -> Result<Self> {
+        let root = root.as_ref().to_path_buf();
+        fs::create_dir_all(&root)?;
+        let store = FsBlockstore {
+            root,
+            counter: AtomicU64::new(0),
+        };
+        store.recover()?;
+        Ok(store)
+    }
+
+    /// Returns the directory the store lives in.
+    pub fn dir(&self) -> &Path {
+        &self.root
+    }
+
+    /// Lists the CIDs of all blocks currently in the store.
+    pub fn keys(&self) -> Result<Vec<Cid>> {
+        let mut keys = Vec::new();
+        for shard in fs::read_dir(&self.root)? {
+            let shard = shard?;
+            if !shard.file_type()?.is_dir() {
+                continue;
+            }
+            for entry in fs::read_dir(shard.path())? {
+                let path = entry?.path();
+                if path.extension().and_then(|e| e.to_str()) != Some(BLOCK_EXT) {
+                    continue;
+                }
+                if let Some(stem) = path.file_stem().and_then(|s| s.to_str()) {
+                    keys.push(stem.parse::<Cid>()?);
+                }
+            }
+        }
+        Ok(keys)
+    }
+
+    /// Deletes temporary files left over from interrupted writes.
+    fn recover(&self) -> Result<()> {
+        for shard in fs::read_dir(&self.root)? {
+            let shard = shard?;
+            if !shard.file_type()?.is_dir() {
+                if shard.path().extension().and_then(|e| e.to_str()) == Some(TEMP_EXT) {
+                    log::warn!("removing incomplete root write {}", shard.path().display());
+                    fs::remove_file(shard.path())?;
+                }
+                continue;
+            }
+            for entry in fs::read_dir(shard.path())? {
+                let path = entry?.path();
+                if path.extension().and_then(|e| e.to_str()) == Some(TEMP_EXT) {
+                    log::warn!("removing incomplete block write {}", path.display());
+                    fs::remove_file(&path)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn shard_dir(&self, key: &str) -> PathBuf {
+        let shard = if key.len() >= 3 {
+            &key[key.len() - 3..key.len() - 1]
+        } else {
+            "_"
+        };
+        self.root.join(shard)
+    }
+
+    fn block_path(&self, k: &Cid) -> PathBuf {
+        let key = k.to_string();
+        self.shard_dir(&key).join(format!("{}.{}", key, BLOCK_EXT))
+    }
+
+    fn root_path(&self, name: &str) -> PathBuf {
+        self.root.join(format!("{}.{}", name, ROOT_EXT))
+    }
+
+    /// Writes `data` to `path` in `dir` through a synced temporary file, so
+    /// `path` holds either its old contents or all of `data`.
+    fn write_atomic(&self, dir: &Path, path: &Path, data: &[u8]) -> Result<()> {
+        let name = path.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+        let seq = self.counter.fetch_add(1, Ordering::Relaxed);
+        let tmp = dir.join(format!("{}.{}-{}.{}", name, std::process::id(), seq, TEMP_EXT));
+        let result = (|| -> Result<()> {
+            let mut file = File::create(&tmp)?;
+            file.write_all(data)?;
+            file.sync_all()?;
+            fs::rename(&tmp, path)?;
+            // Persist the rename itself; not supported on every platform.
+            if let Ok(dir) = File::open(dir) {
+                let _ = dir.sync_all();
+            }
+            Ok(())
+        })();
+        if result.is_err() {
+            let _ = fs::remove_file(&tmp);
+        }
+        result
+    }
+}
+
+impl ManagedBlockstore for FsBlockstore {
+    fn delete(&self, k: &Cid) -> Result<bool> {
+        match fs::remove_file(self.block_path(k)) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn root(&self, name: &str) -> Result<Option<Cid>> {
+        match fs::read_to_string(self.root_path(name)) {
+            Ok(cid) => Ok(Some(cid.trim().parse()?)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn set_root(&self, name: &str, root: &Cid) -> Result<()> {
+        self.write_atomic(&self.root, &self.root_path(name), root.to_string().as_bytes())
+            .map_err(|e| anyhow!("failed to write root {}: {}", name, e))
+    }
+}
+
+impl Blockstore for FsBlockstore {
+    fn get(&self, k: &Cid) -> Result<Option<Vec<u8>>> {
+        match fs::read(self.block_path(k)) {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn put_keyed(&self, k: &Cid, block: &[u8]) -> Result<()> {
+        let key = k.to_string();
+        let dir = self.shard_dir(&key);
+        let path = dir.join(format!("{}.{}", key, BLOCK_EXT));
+        // Blocks are content addressed, so an existing file already holds these bytes.
+        if path.exists() {
+            return Ok(());
+        }
+        fs::create_dir_all(&dir)?;
+        self.write_atomic(&dir, &path, block)
+            .map_err(|e| anyhow!("failed to write block {}: {}", key, e))
+    }
+
+    fn has(&self, k: &Cid) -> Result<bool> {
+        Ok(self.block_path(k).exists())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use multihash_codetable::{Code, MultihashDigest};
+
+    fn temp_root(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("fs-blockstore-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_put_get_delete_persist() {
+        let root = temp_root("roundtrip");
+        let data = b"hello filecoin".to_vec();
+        let cid = Cid::new_v1(0x55, Code::Sha2_256.digest(&data));
+
+        {
+            let bs = FsBlockstore::open(&root).unwrap();
+            assert!(!bs.has(&cid).unwrap());
+            bs.put_keyed(&cid, &data).unwrap();
+            assert!(bs.has(&cid).unwrap());
+        }
+
+        // Reopening the store sees the block written by the previous instance.
+        let bs = FsBlockstore::open(&root).unwrap();
+        assert_eq!(bs.get(&cid).unwrap(), Some(data));
+        assert_eq!(bs.keys().unwrap(), vec![cid]);
+        assert!(bs.delete(&cid).unwrap());
+        assert!(!bs.delete(&cid).unwrap());
+        assert_eq!(bs.get(&cid).unwrap(), None);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_storage_reopens_with_its_actors() {
+        let root = temp_root("storage");
+        let (file, actors) = {
+            let mut storage = crate::MyStorage::open(&root).unwrap();
+            let file = storage.store_sealed(b"agent memory").unwrap();
+            (file, storage.actors_root().unwrap())
+        };
+
+        let storage = crate::MyStorage::open(&root).unwrap();
+        assert_eq!(storage.actors_root(), Some(actors));
+        assert!(storage.actor_state(&file).unwrap().is_some());
+        assert!(storage.delete(&file).unwrap());
+        assert!(!storage.has(&file).unwrap());
+        assert!(!storage.delete(&file).unwrap());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_open_removes_incomplete_writes() {
+        let root = temp_root("recover");
+        fs::create_dir_all(root.join("ab")).unwrap();
+        let stale = root.join("ab").join("bafy.123-0.tmp");
+        fs::write(&stale, b"partial").unwrap();
+
+        let bs = FsBlockstore::open(&root).unwrap();
+        assert!(!stale.exists());
+        assert!(bs.keys().unwrap().is_empty());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
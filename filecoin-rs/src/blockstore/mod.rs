@@ -0,0 +1,73 @@
+// src/blockstore/mod.rs
+//
+// Blockstores `MyStorage` runs on. Besides reading and writing blocks they
+// can remove them and remember a few named roots, so `MyStorage` can collect
+// garbage and find its state again after a restart.
+
+#[cfg(not(target_arch = "wasm32"))]
+mod fs;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use fs::FsBlockstore;
+
+use anyhow::Result;
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+/// A blockstore that can remove blocks and keep named roots.
+pub trait ManagedBlockstore: Blockstore {
+    /// Removes a block, returning whether it was present.
+    fn delete(&self, k: &Cid) -> Result<bool>;
+
+    /// The root last stored under `name`, if any.
+    fn root(&self, name: &str) -> Result<Option<Cid>>;
+
+    /// Stores `root` under `name`, replacing any earlier one. Blocks are not
+    /// kept alive by being named; callers collect around their own roots.
+    fn set_root(&self, name: &str, root: &Cid) -> Result<()>;
+}
+
+/// Blockstore that keeps blocks in memory, for tests and the browser.
+#[derive(Debug, Default)]
+pub struct InMemoryBlockstore {
+    blocks: RefCell<HashMap<Cid, Vec<u8>>>,
+    roots: RefCell<HashMap<String, Cid>>,
+}
+
+impl InMemoryBlockstore {
+    pub fn new() -> Self {
+        InMemoryBlockstore::default()
+    }
+}
+
+impl Blockstore for InMemoryBlockstore {
+    fn get(&self, k: &Cid) -> Result<Option<Vec<u8>>> {
+        Ok(self.blocks.borrow().get(k).cloned())
+    }
+
+    fn put_keyed(&self, k: &Cid, block: &[u8]) -> Result<()> {
+        self.blocks.borrow_mut().insert(*k, block.to_vec());
+        Ok(())
+    }
+
+    fn has(&self, k: &Cid) -> Result<bool> {
+        Ok(self.blocks.borrow().contains_key(k))
+    }
+}
+
+impl ManagedBlockstore for InMemoryBlockstore {
+    fn delete(&self, k: &Cid) -> Result<bool> {
+        Ok(self.blocks.borrow_mut().remove(k).is_some())
+    }
+
+    fn root(&self, name: &str) -> Result<Option<Cid>> {
+        Ok(self.roots.borrow().get(name).copied())
+    }
+
+    fn set_root(&self, name: &str, root: &Cid) -> Result<()> {
+        self.roots.borrow_mut().insert(name.to_string(), *root);
+        Ok(())
+    }
+}
@@ -0,0 +1,112 @@
+// src/car/links.rs
+//
+// Extracts the CIDs a block links to, for the codecs we store.
+
+use crate::unixfs::pb::PbNode;
+use crate::unixfs::{DAG_PB, RAW};
+use anyhow::{anyhow, bail, Result};
+use cid::Cid;
+use fvm_ipld_encoding::DAG_CBOR;
+
+/// CBOR tag used by DAG-CBOR for CIDs.
+const CID_TAG: u64 = 42;
+/// Guard against maliciously deep nesting.
+const MAX_DEPTH: usize = 256;
+
+/// Returns the links of `block` in order. Unknown codecs are treated as leaves.
+pub fn block_links(cid: &Cid, block: &[u8]) -> Result<Vec<Cid>> {
+    match cid.codec() {
+        RAW => Ok(Vec::new()),
+        DAG_PB => Ok(PbNode::decode(block)?.links.into_iter().map(|l| l.cid).collect()),
+        DAG_CBOR => {
+            let mut links = Vec::new();
+            let mut buf = block;
+            scan_cbor(&mut buf, &mut links, 0)?;
+            Ok(links)
+        }
+        _ => Ok(Vec::new()),
+    }
+}
+
+fn scan_cbor(buf: &mut &[u8], links: &mut Vec<Cid>, depth: usize) -> Result<()> {
+    if depth > MAX_DEPTH {
+        bail!("dag-cbor nesting too deep");
+    }
+    let (major, arg) = read_head(buf)?;
+    match major {
+        0 | 1 | 7 => {}
+        2 | 3 => {
+            take(buf, arg as usize)?;
+        }
+        4 => {
+            for _ in 0..arg {
+                scan_cbor(buf, links, depth + 1)?;
+            }
+        }
+        5 => {
+            let items = arg
+                .checked_mul(2)
+                .ok_or_else(|| anyhow!("dag-cbor map length {} too large", arg))?;
+            for _ in 0..items {
+                scan_cbor(buf, links, depth + 1)?;
+            }
+        }
+        6 if arg == CID_TAG => {
+            let (inner, len) = read_head(buf)?;
+            if inner != 2 {
+                bail!("dag-cbor cid tag must wrap a byte string");
+            }
+            let bytes = take(buf, len as usize)?;
+            match bytes.split_first() {
+                // CIDs carry a leading multibase identity prefix.
+                Some((0, cid)) => links.push(Cid::try_from(cid)?),
+                _ => bail!("dag-cbor cid missing identity prefix"),
+            }
+        }
+        6 => scan_cbor(buf, links, depth + 1)?,
+        _ => unreachable!(),
+    }
+    Ok(())
+}
+
+fn read_head(buf: &mut &[u8]) -> Result<(u8, u64)> {
+    let first = take(buf, 1)?[0];
+    let major = first >> 5;
+    let info = first & 0x1f;
+    let arg = match info {
+        0..=23 => u64::from(info),
+        24 => u64::from(take(buf, 1)?[0]),
+        25 => u64::from(u16::from_be_bytes(take(buf, 2)?.try_into()?)),
+        26 => u64::from(u32::from_be_bytes(take(buf, 4)?.try_into()?)),
+        27 => u64::from_be_bytes(take(buf, 8)?.try_into()?),
+        _ => bail!("indefinite-length or reserved cbor item in dag-cbor block"),
+    };
+    Ok((major, arg))
+}
+
+fn take<'a>(buf: &mut &'a [u8], len: usize) -> Result<&'a [u8]> {
+    if buf.len() < len {
+        bail!("truncated dag-cbor block");
+    }
+    let (head, rest) = buf.split_at(len);
+    *buf = rest;
+    Ok(head)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use multihash_codetable::{Code, MultihashDigest};
+
+    #[test]
+    fn test_map_links_and_oversized_lengths() {
+        let target = Cid::new_v1(RAW, Code::Sha2_256.digest(b"leaf"));
+        let block = fvm_ipld_encoding::to_vec(&std::collections::BTreeMap::from([("link", target)])).unwrap();
+        let cid = Cid::new_v1(DAG_CBOR, Code::Sha2_256.digest(&block));
+        assert_eq!(block_links(&cid, &block).unwrap(), vec![target]);
+
+        // A map claiming u64::MAX entries must fail to decode, not overflow.
+        let huge = [0xbb, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff];
+        assert!(block_links(&cid, &huge).is_err());
+    }
+}
@@ -0,0 +1,270 @@
+// src/car/mod.rs
+//
+// CAR (Content Addressable aRchive) v1/v2 import and export, the format
+// consumed by Filecoin storage deals and Storacha uploads.
+
+pub mod links;
+
+use crate::unixfs::pb::write_varint;
+use crate::unixfs::{verify_block, DagError};
+use anyhow::{anyhow, bail, Result};
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::io::{self, Read, Write};
+
+pub use links::block_links;
+
+/// Fixed bytes opening every CARv2 file: a CARv1-style header `{version: 2}`.
+pub const CARV2_PRAGMA: [u8; 11] = [0x0a, 0xa1, 0x67, 0x76, 0x65, 0x72, 0x73, 0x69, 0x6f, 0x6e, 0x02];
+/// Size of the CARv2 header following the pragma.
+const CARV2_HEADER_LEN: u64 = 40;
+/// Multicodec of the `IndexSorted` CARv2 index.
+const CAR_INDEX_SORTED: u64 = 0x0400;
+/// Upper bound on a single header or section, to reject corrupt length prefixes.
+const MAX_SECTION_LEN: u64 = 32 << 20;
+
+/// CAR format version written by `export`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CarVersion {
+    V1,
+    /// CARv1 payload wrapped with a v2 header and an `IndexSorted` index.
+    V2,
+}
+
+/// CARv1 header. Fields are declared in DAG-CBOR canonical key order.
+#[derive(Debug, Serialize, Deserialize)]
+struct CarHeader {
+    #[serde(default)]
+    roots: Vec<Cid>,
+    version: u64,
+}
+
+/// Writes every block reachable from `root` to `writer` as a CAR file.
+pub fn export<B, W>(store: &B, root: &Cid, version: CarVersion, mut writer: W) -> Result<()>
+where
+    B: Blockstore + ?Sized,
+    W: Write,
+{
+    match version {
+        CarVersion::V1 => {
+            write_v1_header(&mut writer, root)?;
+            walk_dag(store, root, |cid, block| write_section(&mut writer, cid, block))?;
+        }
+        CarVersion::V2 => {
+            // First pass: lay out the payload so the header can be written up front.
+            let mut header = Vec::new();
+            write_v1_header(&mut header, root)?;
+            let mut data_size = header.len() as u64;
+            let mut index: BTreeMap<usize, Vec<(Vec<u8>, u64)>> = BTreeMap::new();
+            walk_dag(store, root, |cid, block| {
+                let digest = cid.hash().digest().to_vec();
+                index.entry(digest.len()).or_default().push((digest, data_size));
+                data_size += section_len(cid, block);
+                Ok(())
+            })?;
+
+            let data_offset = CARV2_PRAGMA.len() as u64 + CARV2_HEADER_LEN;
+            writer.write_all(&CARV2_PRAGMA)?;
+            writer.write_all(&[0u8; 16])?; // characteristics
+            writer.write_all(&data_offset.to_le_bytes())?;
+            writer.write_all(&data_size.to_le_bytes())?;
+            writer.write_all(&(data_offset + data_size).to_le_bytes())?;
+
+            // Second pass: the CARv1 payload, then the index.
+            writer.write_all(&header)?;
+            walk_dag(store, root, |cid, block| write_section(&mut writer, cid, block))?;
+            write_index(&mut writer, index)?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Reads a CARv1 or CARv2 file into `store`, verifying each block against its
+/// CID, and returns the roots listed in the CAR header.
+pub fn import<B, R>(store: &B, mut reader: R) -> Result<Vec<Cid>>
+where
+    B: Blockstore + ?Sized,
+    R: Read,
+{
+    let header = read_header(&mut reader)?;
+    match header.version {
+        1 => import_v1_body(store, reader, header.roots),
+        2 => {
+            let mut v2 = [0u8; CARV2_HEADER_LEN as usize];
+            reader.read_exact(&mut v2)?;
+            let data_offset = u64::from_le_bytes(v2[16..24].try_into()?);
+            let data_size = u64::from_le_bytes(v2[24..32].try_into()?);
+            let consumed = CARV2_PRAGMA.len() as u64 + CARV2_HEADER_LEN;
+            if data_offset < consumed {
+                bail!("invalid CARv2 data offset {}", data_offset);
+            }
+            io::copy(&mut (&mut reader).take(data_offset - consumed), &mut io::sink())?;
+
+            // The index is only an accelerator; every block is rehashed anyway.
+            let mut payload = reader.take(data_size);
+            let inner = read_header(&mut payload)?;
+            if inner.version != 1 {
+                bail!("CARv2 payload must be CARv1, found version {}", inner.version);
+            }
+            import_v1_body(store, payload, inner.roots)
+        }
+        v => bail!("unsupported CAR version {}", v),
+    }
+}
+
+fn import_v1_body<B, R>(store: &B, mut reader: R, roots: Vec<Cid>) -> Result<Vec<Cid>>
+where
+    B: Blockstore + ?Sized,
+    R: Read,
+{
+    while let Some(len) = read_uvarint(&mut reader)? {
+        if len > MAX_SECTION_LEN {
+            bail!("CAR section of {} bytes exceeds limit", len);
+        }
+        let mut section = vec![0u8; len as usize];
+        reader.read_exact(&mut section)?;
+        let mut cursor = io::Cursor::new(&section[..]);
+        let cid = Cid::read_bytes(&mut cursor)?;
+        let block = &section[cursor.position() as usize..];
+        verify_block(&cid, block)?;
+        store.put_keyed(&cid, block)?;
+    }
+    Ok(roots)
+}
+
+/// Visits every block reachable from `root` once, depth first, in link order.
+fn walk_dag<B, F>(store: &B, root: &Cid, mut f: F) -> Result<()>
+where
+    B: Blockstore + ?Sized,
+    F: FnMut(&Cid, &[u8]) -> Result<()>,
+{
+    let mut seen = HashSet::new();
+    let mut stack = vec![*root];
+    while let Some(cid) = stack.pop() {
+        if !seen.insert(cid) {
+            continue;
+        }
+        let block = store
+            .get(&cid)?
+            .ok_or(DagError::NotFound(cid))?;
+        f(&cid, &block)?;
+        stack.extend(block_links(&cid, &block)?.into_iter().rev());
+    }
+    Ok(())
+}
+
+fn write_v1_header<W: Write>(writer: &mut W, root: &Cid) -> Result<()> {
+    let header = fvm_ipld_encoding::to_vec(&CarHeader {
+        roots: vec![*root],
+        version: 1,
+    })?;
+    let mut prefix = Vec::new();
+    write_varint(&mut prefix, header.len() as u64);
+    writer.write_all(&prefix)?;
+    writer.write_all(&header)?;
+    Ok(())
+}
+
+fn read_header<R: Read>(reader: &mut R) -> Result<CarHeader> {
+    let len = read_uvarint(reader)?.ok_or_else(|| anyhow!("empty CAR file"))?;
+    if len > MAX_SECTION_LEN {
+        bail!("CAR header of {} bytes exceeds limit", len);
+    }
+    let mut buf = vec![0u8; len as usize];
+    reader.read_exact(&mut buf)?;
+    Ok(fvm_ipld_encoding::from_slice(&buf)?)
+}
+
+fn section_len(cid: &Cid, block: &[u8]) -> u64 {
+    let body = (cid.encoded_len() + block.len()) as u64;
+    let mut prefix = Vec::new();
+    write_varint(&mut prefix, body);
+    prefix.len() as u64 + body
+}
+
+fn write_section<W: Write>(writer: &mut W, cid: &Cid, block: &[u8]) -> Result<()> {
+    let mut prefix = Vec::new();
+    write_varint(&mut prefix, (cid.encoded_len() + block.len()) as u64);
+    writer.write_all(&prefix)?;
+    cid.write_bytes(&mut *writer)?;
+    writer.write_all(block)?;
+    Ok(())
+}
+
+/// Writes an `IndexSorted` index: buckets of equal digest width, each sorted
+/// by digest, mapping to section offsets within the CARv1 payload.
+fn write_index<W: Write>(writer: &mut W, index: BTreeMap<usize, Vec<(Vec<u8>, u64)>>) -> Result<()> {
+    let mut codec = Vec::new();
+    write_varint(&mut codec, CAR_INDEX_SORTED);
+    writer.write_all(&codec)?;
+    writer.write_all(&(index.len() as u32).to_le_bytes())?;
+    for (digest_len, mut entries) in index {
+        entries.sort();
+        let width = digest_len as u32 + 8;
+        writer.write_all(&width.to_le_bytes())?;
+        writer.write_all(&(u64::from(width) * entries.len() as u64).to_le_bytes())?;
+        for (digest, offset) in entries {
+            writer.write_all(&digest)?;
+            writer.write_all(&offset.to_le_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+/// Reads an unsigned varint, returning `None` on a clean end of input.
+fn read_uvarint<R: Read>(reader: &mut R) -> Result<Option<u64>> {
+    let mut value = 0u64;
+    for i in 0..10 {
+        let mut byte = [0u8; 1];
+        if reader.read(&mut byte)? == 0 {
+            if i == 0 {
+                return Ok(None);
+            }
+            bail!("truncated varint in CAR file");
+        }
+        value |= u64::from(byte[0] & 0x7f) << (7 * i);
+        if byte[0] & 0x80 == 0 {
+            return Ok(Some(value));
+        }
+    }
+    bail!("varint overflow in CAR file")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::unixfs::{add_bytes, read_file, FixedSizeChunker};
+    use fvm_ipld_blockstore::MemoryBlockstore;
+
+    #[test]
+    fn test_car_roundtrip_v1_and_v2() {
+        let src = MemoryBlockstore::new();
+        let data: Vec<u8> = (0..5000u32).map(|i| (i % 13) as u8).collect();
+        let root = add_bytes(&src, &FixedSizeChunker::new(512), &data).unwrap();
+
+        for version in [CarVersion::V1, CarVersion::V2] {
+            let mut car = Vec::new();
+            export(&src, &root, version, &mut car).unwrap();
+            assert_eq!(car.starts_with(&CARV2_PRAGMA), version == CarVersion::V2);
+
+            let dst = MemoryBlockstore::new();
+            assert_eq!(import(&dst, &car[..]).unwrap(), vec![root]);
+            assert_eq!(read_file(&dst, &root).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn test_import_rejects_corrupted_block() {
+        let src = MemoryBlockstore::new();
+        let root = add_bytes(&src, &FixedSizeChunker::default(), b"car payload").unwrap();
+        let mut car = Vec::new();
+        export(&src, &root, CarVersion::V1, &mut car).unwrap();
+
+        let last = car.len() - 1;
+        car[last] ^= 0xff;
+        assert!(import(&MemoryBlockstore::new(), &car[..]).is_err());
+    }
+}
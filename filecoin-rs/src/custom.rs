@@ -0,0 +1,272 @@
+// src/custom.rs
+//
+// Application-defined messages. A `Message::Custom` carries a method number
+// and an opaque parameter block; applications register a decoder and handler
+// for the method in a `HandlerRegistry` attached to the `ActorState`, which
+// routes each custom message to its handler as part of normal execution.
+
+use crate::actor_state::{ActorError, ActorState, Journal};
+use crate::events::Event;
+use crate::messages::Message;
+use cid::Cid;
+use multihash_codetable::{Code, MultihashDigest};
+use serde::de::DeserializeOwned;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// The method number for a type tag such as `"PlayTrack"`, derived as in
+/// FRC-42: the first 4-byte big-endian window of the Blake2b-512 digest of
+/// `"1|" + tag` that is at least 2^24.
+pub fn method_for_tag(tag: &str) -> u64 {
+    let digest = Code::Blake2b512.digest(format!("1|{}", tag).as_bytes());
+    digest
+        .digest()
+        .chunks_exact(4)
+        .map(|window| u32::from_be_bytes(window.try_into().expect("4-byte window")))
+        .find(|&method| method >= 1 << 24)
+        .expect("a 64-byte digest has a window of at least 2^24") as u64
+}
+
+/// What a handler sees of the message it is handling.
+pub struct CustomContext<'a> {
+    state: &'a mut ActorState,
+    sender: Option<Cid>,
+    method: u64,
+    journal: &'a mut Journal,
+    events: &'a mut Vec<Event>,
+}
+
+impl<'a> CustomContext<'a> {
+    pub(crate) fn new(
+        state: &'a mut ActorState,
+        sender: Option<Cid>,
+        method: u64,
+        journal: &'a mut Journal,
+        events: &'a mut Vec<Event>,
+    ) -> Self {
+        CustomContext { state, sender, method, journal, events }
+    }
+
+    /// Signed sender of the message, `None` for the system.
+    pub fn sender(&self) -> Option<Cid> {
+        self.sender
+    }
+
+    pub fn state(&self) -> &ActorState {
+        self.state
+    }
+
+    /// Applies `msg` on behalf of the sender, with the same checks as if the
+    /// sender had sent it. Its changes are undone if the handler fails.
+    ///
+    /// `msg` is charged against the gas limit of the message being handled,
+    /// and may nest at most `MAX_CALL_DEPTH` deep.
+    pub fn send(&mut self, msg: &Message) -> Result<Vec<u8>, ActorError> {
+        self.journal.enter(msg)?;
+        let result = self.state.apply(msg, self.sender, self.journal, self.events);
+        self.journal.leave();
+        result
+    }
+
+    /// Emits an `Event::Custom` for this method, logged if the handler
+    /// succeeds.
+    pub fn emit(&mut self, data: Vec<u8>) {
+        self.events.push(Event::Custom { method: self.method, data });
+    }
+}
+
+type Handler = Box<dyn Fn(&mut CustomContext, &[u8]) -> Result<Vec<u8>, ActorError> + Send + Sync>;
+
+/// Handlers for custom methods, keyed by method number.
+#[derive(Default)]
+pub struct HandlerRegistry {
+    handlers: BTreeMap<u64, Handler>,
+}
+
+impl fmt::Debug for HandlerRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.handlers.keys()).finish()
+    }
+}
+
+impl HandlerRegistry {
+    pub fn new() -> Self {
+        HandlerRegistry::default()
+    }
+
+    /// Routes custom messages for `method` to `handle`, after turning their
+    /// parameters into a `T` with `decode`. Replaces any earlier handler for
+    /// the method. A decoding error fails the message with
+    /// `ActorError::InvalidParams`; `handle` returns the receipt's return
+    /// data.
+    pub fn register<T, D, H>(&mut self, method: u64, decode: D, handle: H) -> &mut Self
+    where
+        D: Fn(&[u8]) -> Result<T, String> + Send + Sync + 'static,
+        H: Fn(&mut CustomContext, T) -> Result<Vec<u8>, ActorError> + Send + Sync + 'static,
+    {
+        let handler: Handler = Box::new(move |ctx, params| {
+            let decoded = decode(params).map_err(ActorError::InvalidParams)?;
+            handle(ctx, decoded)
+        });
+        self.handlers.insert(method, handler);
+        self
+    }
+
+    /// `register` with DAG-CBOR decoding.
+    pub fn register_cbor<T, H>(&mut self, method: u64, handle: H) -> &mut Self
+    where
+        T: DeserializeOwned,
+        H: Fn(&mut CustomContext, T) -> Result<Vec<u8>, ActorError> + Send + Sync + 'static,
+    {
+        self.register(method, |params| fvm_ipld_encoding::from_slice(params).map_err(|e| e.to_string()), handle)
+    }
+
+    /// `register_cbor` under the method number of `tag`; see `method_for_tag`.
+    pub fn register_tag<T, H>(&mut self, tag: &str, handle: H) -> &mut Self
+    where
+        T: DeserializeOwned,
+        H: Fn(&mut CustomContext, T) -> Result<Vec<u8>, ActorError> + Send + Sync + 'static,
+    {
+        self.register_cbor(method_for_tag(tag), handle)
+    }
+
+    pub fn is_registered(&self, method: u64) -> bool {
+        self.handlers.contains_key(&method)
+    }
+
+    pub(crate) fn dispatch(&self, ctx: &mut CustomContext, params: &[u8]) -> Result<Vec<u8>, ActorError> {
+        let handler = self
+            .handlers
+            .get(&ctx.method)
+            .ok_or(ActorError::UnhandledMethod { method: ctx.method })?;
+        handler(ctx, params)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actor_state::ExitCode;
+    use crate::messages::{SecretKey, SigType, UnsignedMessage};
+    use fvm_ipld_encoding::tuple::*;
+    use std::sync::Arc;
+
+    #[derive(Serialize_tuple, Deserialize_tuple)]
+    struct PlayTrack {
+        artist: Cid,
+        royalty: u64,
+    }
+
+    fn music_plugin() -> HandlerRegistry {
+        let mut registry = HandlerRegistry::new();
+        registry.register_tag("PlayTrack", |ctx, play: PlayTrack| {
+            ctx.send(&Message::Transfer { to: play.artist, amount: play.royalty })?;
+            ctx.emit(play.artist.to_bytes());
+            Ok(Vec::new())
+        });
+        registry
+    }
+
+    #[test]
+    fn test_custom_messages_are_routed_to_handlers() {
+        let key = SecretKey::generate(SigType::Secp256k1).unwrap();
+        let listener = key.account();
+        let artist = Cid::try_from("bafkqaaa").unwrap();
+        let mut state = ActorState::new();
+        state.set_handlers(Arc::new(music_plugin()));
+        state.handle_message(&Message::Mint { to: listener, amount: 10 });
+
+        let play = |nonce, royalty| {
+            let params = fvm_ipld_encoding::to_vec(&PlayTrack { artist, royalty }).unwrap();
+            let message = Message::Custom { method: method_for_tag("PlayTrack"), data: params };
+            let unsigned = UnsignedMessage {
+                from: listener,
+                nonce,
+                value: 0,
+                gas_limit: 1_000,
+                gas_fee_cap: 1,
+                gas_premium: 1,
+                message,
+            };
+            unsigned.sign(&key)
+        };
+        let receipt = state.apply_signed(&play(0, 4)).unwrap();
+        assert!(receipt.is_success());
+        assert_eq!(
+            receipt.events,
+            vec![
+                Event::Transferred { from: Some(listener), to: artist, amount: 4 },
+                Event::Custom { method: method_for_tag("PlayTrack"), data: artist.to_bytes() },
+            ]
+        );
+
+        // The royalty transfer fails, so the handler's message leaves no trace.
+        let receipt = state.apply_signed(&play(1, 7)).unwrap();
+        assert_eq!(receipt.exit_code, ExitCode::INSUFFICIENT_FUNDS);
+        assert_eq!((state.balance_of(&listener), state.balance_of(&artist)), (6, 4));
+
+        let garbage = Message::Custom { method: method_for_tag("PlayTrack"), data: vec![0xff] };
+        assert_eq!(state.handle_message(&garbage).exit_code, ExitCode::ILLEGAL_ARGUMENT);
+        let unknown = Message::Custom { method: method_for_tag("ScoreGame"), data: Vec::new() };
+        assert_eq!(state.handle_message(&unknown).exit_code, ExitCode::UNHANDLED_MESSAGE);
+    }
+
+    #[test]
+    fn test_nested_messages_are_metered_and_bounded() {
+        let key = SecretKey::generate(SigType::Secp256k1).unwrap();
+        let sender = key.account();
+        let artist = Cid::try_from("bafkqaaa").unwrap();
+        let mut registry = HandlerRegistry::new();
+        // Ignores failed sends and keeps going.
+        registry.register_tag("Spray", move |ctx, (): ()| {
+            for _ in 0..1_000 {
+                let _ = ctx.send(&Message::Transfer { to: artist, amount: 1 });
+            }
+            Ok(Vec::new())
+        });
+        registry.register_tag("Echo", |ctx, (): ()| {
+            let params = fvm_ipld_encoding::to_vec(&()).unwrap();
+            ctx.send(&Message::Custom { method: method_for_tag("Echo"), data: params })
+        });
+        let mut state = ActorState::new();
+        state.set_handlers(Arc::new(registry));
+        state.handle_message(&Message::Mint { to: sender, amount: 10_000 });
+
+        let params = fvm_ipld_encoding::to_vec(&()).unwrap();
+        let spray = UnsignedMessage {
+            from: sender,
+            nonce: 0,
+            value: 0,
+            gas_limit: 1_000,
+            gas_fee_cap: 1,
+            gas_premium: 1,
+            message: Message::Custom { method: method_for_tag("Spray"), data: params.clone() },
+        };
+        let receipt = state.apply_signed(&spray.sign(&key)).unwrap();
+        assert_eq!((receipt.exit_code, receipt.gas_used), (ExitCode::OUT_OF_GAS, 1_000));
+        assert_eq!((state.balance_of(&sender), state.balance_of(&artist)), (10_000, 0));
+
+        // Without a gas limit, the depth limit stops the recursion.
+        let echo = Message::Custom { method: method_for_tag("Echo"), data: params };
+        let receipt = state.handle_message(&echo);
+        assert_eq!(receipt.exit_code, ExitCode::FORBIDDEN);
+        assert_eq!(
+            String::from_utf8(receipt.return_data).unwrap(),
+            ActorError::CallDepthExceeded { max: crate::actor_state::MAX_CALL_DEPTH }.to_string()
+        );
+    }
+
+    #[test]
+    fn test_tag_methods_are_stable_and_out_of_builtin_range() {
+        let play = method_for_tag("PlayTrack");
+        assert_eq!(play, method_for_tag("PlayTrack"));
+        assert_ne!(play, method_for_tag("ScoreGame"));
+        assert!(play >= 1 << 24 && play <= u32::MAX as u64);
+
+        // Exported methods of the built-in actors, hashed by the FRC-42
+        // reference implementation.
+        assert_eq!(method_for_tag("Receive"), 3726118371);
+        assert_eq!(method_for_tag("InvokeEVM"), 3844450837);
+        assert_eq!(method_for_tag("AuthenticateMessage"), 2643134072);
+    }
+}
@@ -1,5 +1,5 @@
 use serde::{Serialize, Deserialize};
-use crate::storage::StorageProvider;
+use crate::storage::{StorageError, StorageProvider};
 
 #[derive(Serialize, Deserialize)]
 pub struct DataContribution {
@@ -17,7 +17,7 @@ impl<T: StorageProvider> DataDAO<T> {
         DataDAO { storage }
     }
 
-    pub async fn contribute(&self, cid: String, contributor: String) -> Result<String, String> {
+    pub async fn contribute(&self, cid: String, contributor: String) -> Result<String, StorageError> {
         let contribution = DataContribution {
             cid: cid.clone(),
             contributor,
@@ -28,7 +28,7 @@ impl<T: StorageProvider> DataDAO<T> {
         Ok(contribution_cid)
     }
 
-    pub async fn increment_usage(&self, cid: String) -> Result<(), String> {
+    pub async fn increment_usage(&self, _cid: String) -> Result<(), StorageError> {
         // Placeholder: Increment usage count on-chain or in storage
         Ok(())
     }
@@ -0,0 +1,139 @@
+// src/dao/governance.rs
+//
+// Proposals voted on with `Message::Vote`. A proposal carries a batch of
+// payouts from the actor balance, or a change to the voting rules, that the
+// actor applies if the proposal passes when its voting window closes (see
+// `ActorState::advance_epoch`).
+
+use crate::messages::Message;
+use cid::Cid;
+use fvm_ipld_encoding::repr::*;
+use fvm_ipld_encoding::tuple::*;
+
+/// How much a vote counts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize_repr, Deserialize_repr)]
+#[repr(u8)]
+pub enum Weighting {
+    /// In proportion to the voter's balance when the proposal was opened.
+    TokenWeighted = 0,
+    /// One vote per member, i.e. per account holding a balance when the
+    /// proposal was opened.
+    OneMemberOneVote = 1,
+}
+
+/// When a proposal passes. Set on the actor with `Message::SetVotingRules`
+/// and fixed for each proposal when it is opened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize_tuple, Deserialize_tuple)]
+pub struct VotingRules {
+    pub weighting: Weighting,
+    /// Share of the eligible weight, in percent, that must vote either way.
+    pub quorum_pct: u8,
+    /// Share of the votes cast, in percent, that must be in favour.
+    pub threshold_pct: u8,
+}
+
+impl VotingRules {
+    /// Token-weighted, 50% quorum, more than half in favour.
+    pub fn majority() -> Self {
+        VotingRules {
+            weighting: Weighting::TokenWeighted,
+            quorum_pct: 50,
+            threshold_pct: 51,
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        (1..=100).contains(&self.quorum_pct) && (1..=100).contains(&self.threshold_pct)
+    }
+}
+
+impl Default for VotingRules {
+    fn default() -> Self {
+        VotingRules::majority()
+    }
+}
+
+/// Whether a proposal may carry `action`: payouts from the actor balance and
+/// changes to the voting rules. Anything else would let a proposal act as the
+/// system on accounts that never voted for it.
+pub fn is_governance_action(action: &Message) -> bool {
+    matches!(
+        action,
+        Message::Transfer { .. } | Message::BatchTransfer { .. } | Message::SetVotingRules { .. }
+    )
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize_repr, Deserialize_repr)]
+#[repr(u8)]
+pub enum ProposalStatus {
+    /// Voting is open.
+    Open = 0,
+    /// Voting closed without reaching quorum or threshold.
+    Rejected = 1,
+    /// Passed, and its actions were applied.
+    Executed = 2,
+    /// Passed, but an action failed, so none were applied.
+    Failed = 3,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize_tuple, Deserialize_tuple)]
+pub struct Proposal {
+    pub id: u64,
+    pub proposer: Cid,
+    pub description: String,
+    /// Applied in order, all or nothing, if the proposal passes.
+    pub actions: Vec<Message>,
+    /// Votes are accepted while the epoch is below this.
+    pub voting_end: u64,
+    /// The actor's rules when the proposal was opened.
+    pub rules: VotingRules,
+    /// Voting weight of each member when the proposal was opened, in CID
+    /// order. Accounts funded later cannot vote it through.
+    pub weights: Vec<(Cid, u64)>,
+    pub status: ProposalStatus,
+}
+
+impl Proposal {
+    pub fn is_open(&self, epoch: u64) -> bool {
+        self.status == ProposalStatus::Open && epoch < self.voting_end
+    }
+}
+
+/// Weighted votes on a proposal.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ProposalTally {
+    pub yes: u64,
+    pub no: u64,
+    /// Total weight that could have voted.
+    pub eligible: u64,
+}
+
+impl ProposalTally {
+    pub fn passes(&self, rules: &VotingRules) -> bool {
+        let cast = self.yes as u128 + self.no as u128;
+        let quorum = cast * 100 >= rules.quorum_pct as u128 * self.eligible as u128;
+        let threshold = self.yes as u128 * 100 >= rules.threshold_pct as u128 * cast;
+        cast > 0 && quorum && threshold
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quorum_and_threshold() {
+        let rules = VotingRules::majority();
+        let tally = |yes, no, eligible| ProposalTally { yes, no, eligible };
+        assert!(tally(51, 49, 100).passes(&rules));
+        assert!(!tally(50, 50, 100).passes(&rules));
+        // 40 of 100 voted: below the 50% quorum however they voted.
+        assert!(!tally(40, 0, 100).passes(&rules));
+        assert!(!tally(0, 0, 0).passes(&rules));
+
+        let unanimous = VotingRules { threshold_pct: 100, quorum_pct: 1, ..rules };
+        assert!(tally(3, 0, 10).passes(&unanimous) && !tally(3, 1, 10).passes(&unanimous));
+        assert!(!VotingRules { threshold_pct: 0, ..rules }.is_valid());
+        assert!(!VotingRules { quorum_pct: 0, ..rules }.is_valid());
+    }
+}
@@ -0,0 +1,4 @@
+// src/dao/mod.rs
+
+pub mod attribution;
+pub mod governance;
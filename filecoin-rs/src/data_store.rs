@@ -0,0 +1,183 @@
+// src/data_store.rs
+//
+// Key/value records written with `Message::SetData`. Each key keeps its
+// recent versions and the account that owns it; `ActorState` decides who may
+// write. Stored in the blockstore as a HAMT keyed by the key's UTF-8 bytes,
+// flushed incrementally like the accounts.
+
+use crate::actor_state::{StoreRef, HAMT_BIT_WIDTH};
+use anyhow::Result;
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+use fvm_ipld_encoding::tuple::*;
+use fvm_ipld_hamt::{BytesKey, Hamt};
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Bound;
+
+/// Versions kept per key; older ones are dropped.
+pub const MAX_VERSIONS: usize = 16;
+
+/// One write to a key.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize_tuple, Deserialize_tuple)]
+pub struct DataVersion {
+    /// Counts every write to the key, starting at 1.
+    pub version: u64,
+    /// `None` for a delete.
+    pub value: Option<String>,
+    pub epoch: u64,
+    /// Signed sender of the write, `None` for the system.
+    pub writer: Option<Cid>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize_tuple, Deserialize_tuple)]
+pub struct DataEntry {
+    /// Account that may overwrite the key, with its delegates; `None` if the
+    /// system wrote it first. Kept after a delete.
+    pub owner: Option<Cid>,
+    /// Oldest first, at most `MAX_VERSIONS`.
+    pub versions: Vec<DataVersion>,
+}
+
+impl DataEntry {
+    pub fn new(owner: Option<Cid>) -> Self {
+        DataEntry { owner, versions: Vec::new() }
+    }
+
+    /// The current value, `None` if never written or deleted.
+    pub fn value(&self) -> Option<&str> {
+        self.versions.last().and_then(|v| v.value.as_deref())
+    }
+
+    pub fn push(&mut self, value: Option<String>, epoch: u64, writer: Option<Cid>) {
+        let version = self.versions.last().map_or(1, |v| v.version + 1);
+        self.versions.push(DataVersion { version, value, epoch, writer });
+        if self.versions.len() > MAX_VERSIONS {
+            self.versions.remove(0);
+        }
+    }
+}
+
+/// Entries of every key ever written, held in memory.
+#[derive(Debug, Clone, Default)]
+pub struct DataStore {
+    entries: BTreeMap<String, DataEntry>,
+    /// HAMT the entries were last flushed to or loaded from.
+    root: Option<Cid>,
+    /// Keys modified since `root`.
+    dirty: BTreeSet<String>,
+}
+
+impl DataStore {
+    pub fn new() -> Self {
+        DataStore::default()
+    }
+
+    pub fn entry(&self, key: &str) -> Option<&DataEntry> {
+        self.entries.get(key)
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entry(key).and_then(DataEntry::value)
+    }
+
+    /// Live keys starting with `prefix` and their values, in key order.
+    pub fn list(&self, prefix: &str) -> Vec<(&str, &str)> {
+        self.entries
+            .range::<str, _>((Bound::Included(prefix), Bound::Unbounded))
+            .take_while(|(key, _)| key.starts_with(prefix))
+            .filter_map(|(key, entry)| Some((key.as_str(), entry.value()?)))
+            .collect()
+    }
+
+    /// Replaces the entry for `key`, or removes it if `entry` is `None`, and
+    /// returns the previous one.
+    pub(crate) fn replace(&mut self, key: &str, entry: Option<DataEntry>) -> Option<DataEntry> {
+        self.dirty.insert(key.to_string());
+        match entry {
+            Some(entry) => self.entries.insert(key.to_string(), entry),
+            None => self.entries.remove(key),
+        }
+    }
+
+    pub(crate) fn entries(&self) -> impl Iterator<Item = (&String, &DataEntry)> {
+        self.entries.iter()
+    }
+
+    /// Builds a store whose entries have all yet to be flushed.
+    pub(crate) fn from_entries(entries: impl IntoIterator<Item = (String, DataEntry)>) -> Self {
+        let entries: BTreeMap<_, _> = entries.into_iter().collect();
+        DataStore {
+            dirty: entries.keys().cloned().collect(),
+            entries,
+            root: None,
+        }
+    }
+
+    /// Writes modified entries to `store` and returns the HAMT root.
+    pub fn flush<B: Blockstore + ?Sized>(&mut self, store: &B) -> Result<Cid> {
+        let mut hamt: Hamt<_, DataEntry> = match &self.root {
+            Some(root) => Hamt::load_with_bit_width(root, StoreRef(store), HAMT_BIT_WIDTH)?,
+            None => Hamt::new_with_bit_width(StoreRef(store), HAMT_BIT_WIDTH),
+        };
+        for key in &self.dirty {
+            let hamt_key = BytesKey(key.as_bytes().to_vec());
+            match self.entries.get(key) {
+                Some(entry) => {
+                    hamt.set(hamt_key, entry.clone())?;
+                }
+                None => {
+                    hamt.delete(&hamt_key)?;
+                }
+            }
+        }
+        let root = hamt.flush()?;
+        self.root = Some(root);
+        self.dirty.clear();
+        Ok(root)
+    }
+
+    pub fn load<B: Blockstore + ?Sized>(store: &B, root: &Cid) -> Result<Self> {
+        let hamt: Hamt<_, DataEntry> = Hamt::load_with_bit_width(root, StoreRef(store), HAMT_BIT_WIDTH)?;
+        let mut entries = BTreeMap::new();
+        hamt.for_each(|key, entry| {
+            entries.insert(String::from_utf8(key.0.clone())?, entry.clone());
+            Ok(())
+        })?;
+        Ok(DataStore {
+            entries,
+            root: Some(*root),
+            dirty: BTreeSet::new(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_history_and_prefix_listing() {
+        let mut data = DataStore::new();
+        let mut entry = DataEntry::new(None);
+        for i in 0..20 {
+            entry.push(Some(i.to_string()), i, None);
+        }
+        data.replace("agent/config", Some(entry));
+        let mut deleted = DataEntry::new(None);
+        deleted.push(Some("x".to_string()), 0, None);
+        deleted.push(None, 1, None);
+        data.replace("agent/old", Some(deleted));
+        data.replace("agents", Some(DataEntry::new(None)));
+
+        let history = &data.entry("agent/config").unwrap().versions;
+        assert_eq!(history.len(), MAX_VERSIONS);
+        assert_eq!((history[0].version, history.last().unwrap().version), (5, 20));
+        assert_eq!(data.list("agent/"), vec![("agent/config", "19")]);
+        assert_eq!(data.get("agent/old"), None);
+
+        let bs = fvm_ipld_blockstore::MemoryBlockstore::new();
+        let root = data.flush(&bs).unwrap();
+        let loaded = DataStore::load(&bs, &root).unwrap();
+        assert_eq!(loaded.entries, data.entries);
+    }
+}
@@ -0,0 +1,289 @@
+// src/events.rs
+//
+// Structured record of what messages did to an `ActorState`. Every
+// successful message appends its events to the state's `EventLog`, indexed by
+// account and kind so callers can look up history without scanning, and
+// pushes them to live subscribers.
+
+use crate::dao::governance::{ProposalStatus, VotingRules};
+use cid::Cid;
+use futures::channel::mpsc;
+use futures::Stream;
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// A state change emitted by a successful message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// `from` is the signed sender, or `None` for a payout from the actor
+    /// balance.
+    Transferred { from: Option<Cid>, to: Cid, amount: u64 },
+    Minted { to: Cid, amount: u64 },
+    Burned { from: Cid, amount: u64 },
+    Withdrawn { from: Cid, amount: u64 },
+    Delegated { from: Cid, to: Cid },
+    Revoked { from: Cid, to: Cid },
+    Voted { proposal_id: u64, voter: Cid, support: bool },
+    DataSet { key: String },
+    DataDeleted { key: String },
+    Proposed { proposal_id: u64, proposer: Cid },
+    /// Voting on a proposal ended with `status`. Follows the events of its
+    /// actions if it executed.
+    ProposalClosed { proposal_id: u64, status: ProposalStatus },
+    MinterAdded { minter: Cid },
+    MinterRemoved { minter: Cid },
+    VotingRulesSet { rules: VotingRules },
+    /// Emitted by the handler of a custom message.
+    Custom { method: u64, data: Vec<u8> },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EventKind {
+    Transferred,
+    Minted,
+    Burned,
+    Withdrawn,
+    Delegated,
+    Revoked,
+    Voted,
+    DataSet,
+    DataDeleted,
+    Proposed,
+    ProposalClosed,
+    MinterAdded,
+    MinterRemoved,
+    VotingRulesSet,
+    Custom,
+}
+
+impl Event {
+    pub fn kind(&self) -> EventKind {
+        match self {
+            Event::Transferred { .. } => EventKind::Transferred,
+            Event::Minted { .. } => EventKind::Minted,
+            Event::Burned { .. } => EventKind::Burned,
+            Event::Withdrawn { .. } => EventKind::Withdrawn,
+            Event::Delegated { .. } => EventKind::Delegated,
+            Event::Revoked { .. } => EventKind::Revoked,
+            Event::Voted { .. } => EventKind::Voted,
+            Event::DataSet { .. } => EventKind::DataSet,
+            Event::DataDeleted { .. } => EventKind::DataDeleted,
+            Event::Proposed { .. } => EventKind::Proposed,
+            Event::ProposalClosed { .. } => EventKind::ProposalClosed,
+            Event::MinterAdded { .. } => EventKind::MinterAdded,
+            Event::MinterRemoved { .. } => EventKind::MinterRemoved,
+            Event::VotingRulesSet { .. } => EventKind::VotingRulesSet,
+            Event::Custom { .. } => EventKind::Custom,
+        }
+    }
+
+    /// Accounts the event involves.
+    pub fn accounts(&self) -> Vec<Cid> {
+        match self {
+            Event::Transferred { from, to, .. } => from.iter().copied().chain([*to]).collect(),
+            Event::Minted { to, .. } => vec![*to],
+            Event::Burned { from, .. } | Event::Withdrawn { from, .. } => vec![*from],
+            Event::Delegated { from, to } | Event::Revoked { from, to } => vec![*from, *to],
+            Event::Voted { voter, .. } => vec![*voter],
+            Event::Proposed { proposer, .. } => vec![*proposer],
+            Event::MinterAdded { minter } | Event::MinterRemoved { minter } => vec![*minter],
+            Event::DataSet { .. }
+            | Event::DataDeleted { .. }
+            | Event::ProposalClosed { .. }
+            | Event::VotingRulesSet { .. }
+            | Event::Custom { .. } => Vec::new(),
+        }
+    }
+}
+
+/// An event as recorded in an `EventLog`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoggedEvent {
+    /// Position in the log, starting at 0.
+    pub seq: u64,
+    /// State epoch when the event was emitted.
+    pub height: u64,
+    /// CID of the signed message that caused it, if any.
+    pub message: Option<Cid>,
+    pub event: Event,
+}
+
+/// Selects events by account, kind and height. Unset criteria match
+/// everything.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EventFilter {
+    pub account: Option<Cid>,
+    pub kinds: Vec<EventKind>,
+    pub heights: Option<RangeInclusive<u64>>,
+}
+
+impl EventFilter {
+    pub fn new() -> Self {
+        EventFilter::default()
+    }
+
+    pub fn account(mut self, account: Cid) -> Self {
+        self.account = Some(account);
+        self
+    }
+
+    pub fn kind(mut self, kind: EventKind) -> Self {
+        self.kinds.push(kind);
+        self
+    }
+
+    pub fn heights(mut self, heights: RangeInclusive<u64>) -> Self {
+        self.heights = Some(heights);
+        self
+    }
+
+    pub fn matches(&self, logged: &LoggedEvent) -> bool {
+        self.account.is_none_or(|a| logged.event.accounts().contains(&a))
+            && (self.kinds.is_empty() || self.kinds.contains(&logged.event.kind()))
+            && self.heights.as_ref().is_none_or(|h| h.contains(&logged.height))
+    }
+}
+
+/// Live feed of events matching a filter. Ends when the log is dropped.
+pub struct Subscription {
+    receiver: mpsc::UnboundedReceiver<LoggedEvent>,
+}
+
+impl Subscription {
+    /// The next event that has already arrived, without waiting.
+    pub fn try_next(&mut self) -> Option<LoggedEvent> {
+        self.receiver.try_next().ok().flatten()
+    }
+}
+
+impl Stream for Subscription {
+    type Item = LoggedEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<LoggedEvent>> {
+        Pin::new(&mut self.receiver).poll_next(cx)
+    }
+}
+
+/// Append-only event history with account and kind indexes.
+#[derive(Debug, Default)]
+pub struct EventLog {
+    entries: Vec<LoggedEvent>,
+    by_account: HashMap<Cid, Vec<usize>>,
+    by_kind: HashMap<EventKind, Vec<usize>>,
+    subscribers: Vec<(EventFilter, mpsc::UnboundedSender<LoggedEvent>)>,
+}
+
+/// Copies the history; subscribers stay with the original.
+impl Clone for EventLog {
+    fn clone(&self) -> Self {
+        EventLog {
+            entries: self.entries.clone(),
+            by_account: self.by_account.clone(),
+            by_kind: self.by_kind.clone(),
+            subscribers: Vec::new(),
+        }
+    }
+}
+
+impl EventLog {
+    pub fn new() -> Self {
+        EventLog::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Records `events`, emitted together at `height`, and delivers them to
+    /// matching subscribers. Subscribers that have gone away are dropped.
+    pub fn append(&mut self, height: u64, message: Option<Cid>, events: &[Event]) {
+        for event in events {
+            let index = self.entries.len();
+            let logged = LoggedEvent {
+                seq: index as u64,
+                height,
+                message,
+                event: event.clone(),
+            };
+            let mut accounts = event.accounts();
+            accounts.dedup();
+            for account in accounts {
+                self.by_account.entry(account).or_default().push(index);
+            }
+            self.by_kind.entry(event.kind()).or_default().push(index);
+            self.subscribers.retain(|(filter, sender)| {
+                !filter.matches(&logged) || sender.unbounded_send(logged.clone()).is_ok()
+            });
+            self.entries.push(logged);
+        }
+    }
+
+    /// Events matching `filter`, oldest first.
+    pub fn query(&self, filter: &EventFilter) -> Vec<&LoggedEvent> {
+        let candidates: Box<dyn Iterator<Item = &LoggedEvent>> = match (&filter.account, filter.kinds.as_slice()) {
+            (Some(account), _) => Box::new(self.indexed(self.by_account.get(account))),
+            (None, [kind]) => Box::new(self.indexed(self.by_kind.get(kind))),
+            _ => Box::new(self.entries.iter()),
+        };
+        candidates.filter(|logged| filter.matches(logged)).collect()
+    }
+
+    /// Receives every event appended from now on that matches `filter`.
+    pub fn subscribe(&mut self, filter: EventFilter) -> Subscription {
+        let (sender, receiver) = mpsc::unbounded();
+        self.subscribers.push((filter, sender));
+        Subscription { receiver }
+    }
+
+    fn indexed<'a>(&'a self, index: Option<&'a Vec<usize>>) -> impl Iterator<Item = &'a LoggedEvent> {
+        index.into_iter().flatten().map(|&i| &self.entries[i])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use futures::StreamExt;
+    use multihash_codetable::{Code, MultihashDigest};
+
+    fn account(name: &str) -> Cid {
+        Cid::new_v1(0x55, Code::Sha2_256.digest(name.as_bytes()))
+    }
+
+    #[test]
+    fn test_query_and_subscribe() {
+        let (alice, bob) = (account("alice"), account("bob"));
+        let mut log = EventLog::new();
+        let mut bob_feed = log.subscribe(EventFilter::new().account(bob));
+
+        log.append(1, None, &[Event::Minted { to: alice, amount: 10 }]);
+        log.append(2, None, &[Event::Transferred { from: Some(alice), to: bob, amount: 4 }]);
+        log.append(3, None, &[
+            Event::Voted { proposal_id: 1, voter: alice, support: true },
+            Event::DataSet { key: "k".to_string() },
+        ]);
+
+        let seqs = |events: Vec<&LoggedEvent>| events.iter().map(|e| e.seq).collect::<Vec<_>>();
+        assert_eq!(seqs(log.query(&EventFilter::new().account(alice))), vec![0, 1, 2]);
+        assert_eq!(seqs(log.query(&EventFilter::new().account(alice).heights(2..=3))), vec![1, 2]);
+        assert_eq!(seqs(log.query(&EventFilter::new().kind(EventKind::DataSet))), vec![3]);
+        assert_eq!(
+            seqs(log.query(&EventFilter::new().kind(EventKind::Minted).kind(EventKind::Voted))),
+            vec![0, 2]
+        );
+
+        let received = bob_feed.try_next().unwrap();
+        assert_eq!((received.seq, received.height), (1, 2));
+        assert!(bob_feed.try_next().is_none());
+
+        drop(log);
+        assert!(block_on(bob_feed.next()).is_none());
+    }
+}
@@ -1,10 +1,33 @@
-use fvm_ipld_blockstore::{MemoryBlockstore, Blockstore};
-use multihash::Code;
-use cid::{Cid, Version};
-use std::collections::HashMap;
+use blockstore::{InMemoryBlockstore, ManagedBlockstore};
+use cid::Cid;
 use std::sync::Arc;
 use wasm_bindgen::prelude::*;
 
+// In-memory and, on native targets, file-backed blockstores.
+pub mod blockstore;
+pub mod actor_state;
+pub mod backup;
+pub mod car;
+pub mod custom;
+pub mod dao;
+pub mod data_store;
+pub mod events;
+pub mod message_pool;
+pub mod messages;
+pub mod provenance;
+pub mod storage;
+pub mod token;
+pub mod unixfs;
+pub mod wasm;
+
+use bytes::Bytes;
+use futures::io::{AsyncRead, AsyncWrite};
+use futures::stream::{Stream, StreamExt};
+use actor_state::StoreRef;
+use fvm_ipld_hamt::{BytesKey, Hamt};
+use multihash_codetable::{Code, MultihashDigest};
+use unixfs::{Chunker, FixedSizeChunker, TransferProgress};
+
 // Stub native module for non-wasm targets.
 #[cfg(not(target_arch = "wasm32"))]
 mod native {
@@ -13,12 +36,7 @@ mod native {
     }
 }
 
-// Define an ActorState to use in the actors map.
-#[derive(Debug, Default)]
-pub struct ActorState {
-    balance: u64,
-    accounts: HashMap<String, u64>, // For example, an account mapping.
-}
+pub use actor_state::ActorState;
 
 // Define the intents for storage actions.
 pub enum MyIntent {
@@ -26,58 +44,331 @@ pub enum MyIntent {
     Download(String),
 }
 
+/// Name under which `MyStorage` records its actors root in the blockstore.
+pub const ACTORS_ROOT: &str = "actors";
+
+/// Name under which `MyStorage` records the list of snapshots it holds.
+pub const SNAPSHOTS_ROOT: &str = "snapshots";
+
 // Update MyStorage to include an actors map.
 pub struct MyStorage {
-    blockstore: Arc<dyn Blockstore>,
-    actors: HashMap<Cid, ActorState>,
+    blockstore: Arc<dyn ManagedBlockstore>,
+    /// HAMT of file CID to the state root of that file's actor, `None` until
+    /// the first actor is created.
+    actors: Option<Cid>,
+}
+
+impl Default for MyStorage {
+    fn default() -> Self {
+        Self::with_blockstore(InMemoryBlockstore::new()).expect("a new in-memory store has no roots to read")
+    }
 }
 
 impl MyStorage {
+    /// Creates a storage backed by an in-memory blockstore.
     pub fn new() -> Self {
-        let bs = MemoryBlockstore::new();
-        MyStorage {
-            blockstore: Arc::new(bs),
-            actors: HashMap::new(),
-        }
+        Self::default()
+    }
+
+    /// Creates a storage backed by any managed blockstore, picking up the
+    /// actors root it recorded under `ACTORS_ROOT`, if any.
+    pub fn with_blockstore<B: ManagedBlockstore + 'static>(blockstore: B) -> Result<Self, anyhow::Error> {
+        Ok(MyStorage {
+            actors: blockstore.root(ACTORS_ROOT)?,
+            blockstore: Arc::new(blockstore),
+        })
     }
 
-    /// Uploads data by computing its CID, storing it in the blockstore,
-    /// and creating a new actor state.
+    /// Opens a storage persisted on disk under `path`, with the actors it had
+    /// when last used.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn open<P: AsRef<std::path::Path>>(path: P) -> Result<Self, anyhow::Error> {
+        Self::with_blockstore(blockstore::FsBlockstore::open(path)?)
+    }
+
+    /// Returns whether a block with the given CID is stored.
+    pub fn has(&self, cid_str: &str) -> Result<bool, anyhow::Error> {
+        let cid = cid_str.parse::<Cid>()?;
+        self.blockstore.has(&cid)
+    }
+
+    /// Removes the block with the given CID, returning whether it was stored.
+    /// Only that block goes; DAGs linking to it are left incomplete.
+    pub fn delete(&self, cid_str: &str) -> Result<bool, anyhow::Error> {
+        let cid = cid_str.parse::<Cid>()?;
+        self.blockstore.delete(&cid)
+    }
+
+    /// Uploads data as a UnixFS file DAG using the default fixed-size chunker,
+    /// and creates a new actor state for the root CID.
     pub async fn upload(&mut self, data: Vec<u8>) -> Result<String, JsValue> {
-        // Compute the multihash of the data.
-        let hash = Code::Sha2_256.digest(&data);
-        // Create a new CID (here using raw codec 0x55 as an example; adjust as needed).
-        let cid = Cid::new_v1(0x55, hash);
+        self.upload_with_chunker(data, &FixedSizeChunker::default()).await
+    }
+
+    /// Uploads data using the given chunker and returns the root CID.
+    pub async fn upload_with_chunker<C: Chunker + ?Sized>(&mut self, data: Vec<u8>, chunker: &C) -> Result<String, JsValue> {
+        let cid = self
+            .add_file(&data, chunker)
+            .map_err(|e| JsValue::from_str(&e.to_string()))?;
+        Ok(cid.to_string())
+    }
+
+    fn add_file<C: Chunker + ?Sized>(&mut self, data: &[u8], chunker: &C) -> Result<Cid, anyhow::Error> {
+        // Chunk the data and build a balanced DAG (raw leaves, DAG-PB internal nodes).
+        let cid = unixfs::add_bytes(&*self.blockstore, chunker, data)?;
         // Create and insert an empty actor state.
-        let actor_state = ActorState {
-            balance: 0,
-            accounts: HashMap::new(),
+        self.put_actor(cid, &mut ActorState::default())?;
+        Ok(cid)
+    }
+
+    /// Root of the actors HAMT, also recorded in the blockstore under
+    /// `ACTORS_ROOT`.
+    pub fn actors_root(&self) -> Option<Cid> {
+        self.actors
+    }
+
+    /// Switches to the actors HAMT under `root`, e.g. one recorded by
+    /// `actors_root` before the storage was closed, or an earlier root to roll
+    /// back to.
+    pub fn load_actors(&mut self, root: Cid) -> Result<(), anyhow::Error> {
+        Hamt::<_, Cid>::load_with_bit_width(&root, StoreRef(&*self.blockstore), actor_state::HAMT_BIT_WIDTH)?;
+        self.blockstore.set_root(ACTORS_ROOT, &root)?;
+        self.actors = Some(root);
+        Ok(())
+    }
+
+    /// Loads the actor state of the file stored under `cid_str`.
+    pub fn actor_state(&self, cid_str: &str) -> Result<Option<ActorState>, anyhow::Error> {
+        let Some(root) = &self.actors else { return Ok(None) };
+        let hamt = Hamt::<_, Cid>::load_with_bit_width(root, StoreRef(&*self.blockstore), actor_state::HAMT_BIT_WIDTH)?;
+        match hamt.get(&BytesKey(cid_str.parse::<Cid>()?.to_bytes()))? {
+            Some(state_root) => Ok(Some(ActorState::load(&*self.blockstore, state_root)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Flushes `state` as the actor state of the file stored under `cid_str`
+    /// and returns the new actors root.
+    pub fn set_actor_state(&mut self, cid_str: &str, state: &mut ActorState) -> Result<Cid, anyhow::Error> {
+        self.put_actor(cid_str.parse()?, state)
+    }
+
+    fn put_actor(&mut self, cid: Cid, state: &mut ActorState) -> Result<Cid, anyhow::Error> {
+        let store = StoreRef(&*self.blockstore);
+        let mut hamt = match &self.actors {
+            Some(root) => Hamt::<_, Cid>::load_with_bit_width(root, store, actor_state::HAMT_BIT_WIDTH)?,
+            None => Hamt::new_with_bit_width(store, actor_state::HAMT_BIT_WIDTH),
         };
-        self.actors.insert(cid, actor_state);
-        // Store the data in the blockstore. (Assumes put_keyed exists on your blockstore.)
-        self.blockstore
-            .put_keyed(&cid, &data)
+        hamt.set(BytesKey(cid.to_bytes()), state.flush(&*self.blockstore)?)?;
+        let root = hamt.flush()?;
+        self.blockstore.set_root(ACTORS_ROOT, &root)?;
+        self.actors = Some(root);
+        Ok(root)
+    }
+
+    /// Compresses and optionally encrypts `data` into a backup container,
+    /// stores it, and returns the container's root CID.
+    pub fn backup(
+        &mut self,
+        data: &[u8],
+        compression: backup::Compression,
+        passphrase: Option<&str>,
+    ) -> Result<backup::BackupReceipt, anyhow::Error> {
+        let (header, sealed) = backup::seal(data, compression, passphrase)?;
+        Ok(backup::BackupReceipt {
+            cid: self.store_sealed(&sealed)?,
+            header,
+            stored_size: sealed.len() as u64,
+        })
+    }
+
+    /// Stores `entries` as an incremental snapshot on top of `parent`, writing
+    /// only chunks that are not already stored.
+    pub fn snapshot(
+        &mut self,
+        entries: &backup::snapshot::Entries,
+        parent: Option<&str>,
+        timestamp: u64,
+    ) -> Result<backup::SnapshotReceipt, anyhow::Error> {
+        let parent = parent.map(|p| p.parse::<Cid>()).transpose()?;
+        let receipt = backup::snapshot::create_snapshot(&*self.blockstore, entries, parent, timestamp)?;
+        let mut snapshots = self.snapshots()?;
+        if !snapshots.contains(&receipt.cid) {
+            snapshots.push(receipt.cid);
+            self.set_snapshots(&snapshots)?;
+        }
+        Ok(receipt)
+    }
+
+    /// Snapshots stored through `snapshot` and not yet pruned, listed in a
+    /// DAG-CBOR block recorded under `SNAPSHOTS_ROOT`.
+    fn snapshots(&self) -> Result<Vec<Cid>, anyhow::Error> {
+        let Some(root) = self.blockstore.root(SNAPSHOTS_ROOT)? else { return Ok(Vec::new()) };
+        let block = self
+            .blockstore
+            .get(&root)?
+            .ok_or_else(|| anyhow::anyhow!("snapshot list {} not found", root))?;
+        Ok(fvm_ipld_encoding::from_slice(&block)?)
+    }
+
+    fn set_snapshots(&self, snapshots: &[Cid]) -> Result<Cid, anyhow::Error> {
+        let bytes = fvm_ipld_encoding::to_vec(snapshots)?;
+        let cid = Cid::new_v1(fvm_ipld_encoding::DAG_CBOR, Code::Sha2_256.digest(&bytes));
+        self.blockstore.put_keyed(&cid, &bytes)?;
+        self.blockstore.set_root(SNAPSHOTS_ROOT, &cid)?;
+        Ok(cid)
+    }
+
+    /// CIDs of the files that have an actor, i.e. everything uploaded.
+    fn file_cids(&self) -> Result<Vec<Cid>, anyhow::Error> {
+        let Some(root) = &self.actors else { return Ok(Vec::new()) };
+        let hamt = Hamt::<_, Cid>::load_with_bit_width(root, StoreRef(&*self.blockstore), actor_state::HAMT_BIT_WIDTH)?;
+        let mut files = Vec::new();
+        hamt.for_each(|key, _| {
+            files.push(Cid::try_from(key.0.as_slice())?);
+            Ok(())
+        })?;
+        Ok(files)
+    }
+
+    /// Removes the snapshots in `plan.prune` and deletes every block that
+    /// only they used. Blocks still reachable from a kept snapshot, another
+    /// stored snapshot, an uploaded file or the actors stay.
+    pub fn apply_retention(&mut self, plan: &backup::PrunePlan) -> Result<backup::CollectReport, anyhow::Error> {
+        let parse = |cid: &String| cid.parse::<Cid>();
+        let pruned = plan.prune.iter().map(|s| parse(&s.cid)).collect::<Result<Vec<_>, _>>()?;
+        let mut live = plan.keep.iter().map(|s| parse(&s.cid)).collect::<Result<Vec<_>, _>>()?;
+
+        // The replaced snapshot list is collected along with the snapshots.
+        let mut candidates = pruned.clone();
+        candidates.extend(self.blockstore.root(SNAPSHOTS_ROOT)?);
+        let mut snapshots = self.snapshots()?;
+        snapshots.retain(|cid| !pruned.contains(cid));
+        live.push(self.set_snapshots(&snapshots)?);
+        live.extend(self.actors);
+        live.extend(self.file_cids()?);
+
+        Ok(backup::CollectReport {
+            removed: plan.prune.iter().map(|s| s.cid.clone()).collect(),
+            freed_blocks: backup::retention::collect_snapshots(&*self.blockstore, &candidates, &live)?,
+        })
+    }
+
+    /// Restores the entries of a snapshot, optionally only those under `filter`.
+    pub fn restore_snapshot(
+        &self,
+        cid_str: &str,
+        filter: Option<&str>,
+    ) -> Result<backup::snapshot::Entries, anyhow::Error> {
+        backup::snapshot::restore_snapshot(&*self.blockstore, &cid_str.parse()?, filter)
+    }
+
+    /// Lists entries added, removed and changed between two snapshots.
+    pub fn diff_snapshots(&self, a: &str, b: &str) -> Result<backup::SnapshotDiff, anyhow::Error> {
+        backup::snapshot::diff_snapshots(&*self.blockstore, &a.parse()?, &b.parse()?)
+    }
+
+    /// Lists the snapshot chain ending at `head`, newest first, for retention.
+    pub fn snapshot_history(&self, head: &str) -> Result<Vec<backup::SnapshotInfo>, anyhow::Error> {
+        backup::retention::snapshot_chain(&*self.blockstore, &head.parse()?)
+    }
+
+    /// Stores an already sealed backup container and returns its root CID.
+    pub fn store_sealed(&mut self, sealed: &[u8]) -> Result<String, anyhow::Error> {
+        Ok(self.add_file(sealed, &FixedSizeChunker::default())?.to_string())
+    }
+
+    /// Restores the backup stored under `cid_str`, returning the original data
+    /// after checking it against the digest recorded at backup time.
+    pub fn restore_backup(&self, cid_str: &str, passphrase: Option<&str>) -> Result<Vec<u8>, anyhow::Error> {
+        let sealed = self.retrieve_data(cid_str.to_string())?;
+        Ok(backup::open(&sealed, passphrase)?)
+    }
+
+    /// Uploads everything readable from `reader` chunk by chunk, so memory use
+    /// stays bounded by the chunk size. `progress` is called after every read.
+    pub async fn upload_reader<R, P>(&mut self, reader: R, progress: P) -> Result<String, JsValue>
+    where
+        R: AsyncRead + Unpin,
+        P: FnMut(TransferProgress),
+    {
+        let cid = unixfs::stream::add_reader(&*self.blockstore, &FixedSizeChunker::default(), reader, progress)
+            .await
+            .map_err(|e| JsValue::from_str(&e.to_string()))?;
+        self.put_actor(cid, &mut ActorState::default())
             .map_err(|e| JsValue::from_str(&e.to_string()))?;
         Ok(cid.to_string())
     }
 
-    /// Downloads data from the blockstore via its CID (provided as a string).
-    pub async fn download(&self, cid_str: String) -> Result<Vec<u8>, JsValue> {
+    /// Uploads a stream of byte buffers, e.g. a `ReadableStream` bridged from JS.
+    pub async fn upload_stream<S, P>(&mut self, stream: S, progress: P) -> Result<String, JsValue>
+    where
+        S: Stream<Item = Result<Bytes, JsValue>>,
+        P: FnMut(TransferProgress),
+    {
+        let stream = stream.map(|item| item.map_err(|e| format!("{:?}", e)));
+        let cid = unixfs::stream::add_stream(&*self.blockstore, &FixedSizeChunker::default(), stream, progress)
+            .await
+            .map_err(|e| JsValue::from_str(&e.to_string()))?;
+        self.put_actor(cid, &mut ActorState::default())
+            .map_err(|e| JsValue::from_str(&e.to_string()))?;
+        Ok(cid.to_string())
+    }
+
+    /// Writes the file stored under `cid_str` to `writer` block by block and
+    /// returns the number of bytes written.
+    pub async fn download_writer<W, P>(&self, cid_str: String, writer: W, progress: P) -> Result<u64, JsValue>
+    where
+        W: AsyncWrite + Unpin,
+        P: FnMut(TransferProgress),
+    {
         let cid = cid_str
             .parse::<Cid>()
             .map_err(|e| JsValue::from_str(&e.to_string()))?;
-        self.blockstore
-            .get(&cid)
-            .map_err(|e| JsValue::from_str(&e.to_string()))?
-            .ok_or_else(|| JsValue::from_str("Data not found"))
+        unixfs::stream::write_file(&*self.blockstore, &cid, writer, progress)
+            .await
+            .map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
+    /// Returns the file stored under `cid_str` as a stream of chunks.
+    pub fn download_stream(&self, cid_str: String) -> Result<impl Stream<Item = Result<Bytes, JsValue>> + '_, JsValue> {
+        let cid = cid_str
+            .parse::<Cid>()
+            .map_err(|e| JsValue::from_str(&e.to_string()))?;
+        Ok(unixfs::stream::file_stream(&*self.blockstore, &cid)
+            .map(|chunk| chunk.map_err(|e| JsValue::from_str(&e.to_string()))))
+    }
+
+    /// Downloads data from the blockstore via its root CID (provided as a string),
+    /// reassembling the file by walking the DAG links.
+    pub async fn download(&self, cid_str: String) -> Result<Vec<u8>, JsValue> {
+        self.retrieve_data(cid_str)
+            .map_err(|e| JsValue::from_str(&e.to_string()))
     }
 
     /// Synchronous version of download that returns an anyhow error.
     pub fn retrieve_data(&self, cid_str: String) -> Result<Vec<u8>, anyhow::Error> {
         let cid = cid_str.parse::<Cid>()?;
-        self.blockstore
-            .get(&cid)?
-            .ok_or_else(|| anyhow::anyhow!("Data not found"))
+        if !self.blockstore.has(&cid)? {
+            return Err(anyhow::anyhow!("Data not found"));
+        }
+        unixfs::read_file(&*self.blockstore, &cid)
+    }
+
+    /// Writes the DAG under `root` as a CARv1 file.
+    pub fn export_car<W: std::io::Write>(&self, root: Cid, writer: W) -> Result<(), anyhow::Error> {
+        car::export(&*self.blockstore, &root, car::CarVersion::V1, writer)
+    }
+
+    /// Writes the DAG under `root` as an indexed CARv2 file.
+    pub fn export_car_v2<W: std::io::Write>(&self, root: Cid, writer: W) -> Result<(), anyhow::Error> {
+        car::export(&*self.blockstore, &root, car::CarVersion::V2, writer)
+    }
+
+    /// Imports a CARv1 or CARv2 file, verifying every block's hash, and
+    /// returns the root CIDs from its header.
+    pub fn import_car<R: std::io::Read>(&mut self, reader: R) -> Result<Vec<Cid>, anyhow::Error> {
+        car::import(&*self.blockstore, reader)
     }
 
     /// Handle an intent (either upload or download) and return a string result.
@@ -97,10 +388,15 @@ pub struct MyMachine {
     storage: MyStorage,
 }
 
+impl Default for MyMachine {
+    fn default() -> Self {
+        MyMachine { storage: MyStorage::new() }
+    }
+}
+
 impl MyMachine {
     pub fn new() -> Self {
-        let storage = MyStorage::new();
-        MyMachine { storage }
+        Self::default()
     }
 
     pub async fn process_intent(&mut self, intent: MyIntent) -> Result<String, JsValue> {
@@ -0,0 +1,375 @@
+// src/message_pool.rs
+//
+// Pending signed messages waiting to be executed against an `ActorState`.
+// Selection depends only on the pool's contents and the state, never on the
+// order messages arrived in, so agents submitting concurrently all see the
+// same batches and the same state roots.
+
+use crate::actor_state::{gas_cost, ActorError, ActorState, Receipt};
+use crate::messages::SignedMessage;
+use anyhow::Result;
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BinaryHeap, HashMap};
+use std::fmt;
+
+/// Why a message was not accepted into the pool.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PoolError {
+    /// The signature does not verify.
+    Invalid(ActorError),
+    /// The same message is already pending.
+    Duplicate,
+    /// The sender has already used this nonce.
+    NonceTooLow { expected: u64, got: u64 },
+    /// Another message with this nonce is pending and pays at least as much.
+    FeeTooLow { pending_premium: u64 },
+    /// The gas limit does not cover the message, or the premium exceeds the
+    /// fee cap.
+    BadGas,
+    /// The sender already has `MAX_PENDING_PER_SENDER` messages pending.
+    SenderFull,
+}
+
+impl fmt::Display for PoolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PoolError::Invalid(e) => write!(f, "invalid message: {}", e),
+            PoolError::Duplicate => write!(f, "message already pending"),
+            PoolError::NonceTooLow { expected, got } => write!(f, "nonce {} already used, next is {}", got, expected),
+            PoolError::FeeTooLow { pending_premium } => {
+                write!(f, "replacement must pay a gas premium above {}", pending_premium)
+            }
+            PoolError::BadGas => write!(f, "gas limit below message cost or premium above fee cap"),
+            PoolError::SenderFull => write!(f, "too many pending messages from sender"),
+        }
+    }
+}
+
+impl std::error::Error for PoolError {}
+
+/// Most messages a single sender may have pending.
+pub const MAX_PENDING_PER_SENDER: usize = 64;
+
+/// Bounds on a batch produced by `MessagePool::select`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatchLimits {
+    pub max_messages: usize,
+    /// Sum of the gas limits of the selected messages.
+    pub gas_limit: u64,
+}
+
+impl Default for BatchLimits {
+    fn default() -> Self {
+        BatchLimits {
+            max_messages: 1_000,
+            gas_limit: 10_000_000,
+        }
+    }
+}
+
+/// A batch of messages executed against a state.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Batch {
+    /// State root the batch was applied to.
+    pub parent: Cid,
+    /// CIDs of the applied messages, in execution order.
+    pub messages: Vec<Cid>,
+    /// One receipt per message.
+    pub receipts: Vec<Receipt>,
+    /// State root after the batch.
+    pub state_root: Cid,
+}
+
+#[derive(Debug, Default)]
+pub struct MessagePool {
+    /// Pending messages by sender, then nonce.
+    pending: BTreeMap<Cid, BTreeMap<u64, (Cid, SignedMessage)>>,
+    /// Sender and nonce of every pending message, by message CID.
+    by_cid: HashMap<Cid, (Cid, u64)>,
+}
+
+impl MessagePool {
+    pub fn new() -> Self {
+        MessagePool::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_cid.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_cid.is_empty()
+    }
+
+    pub fn contains(&self, cid: &Cid) -> bool {
+        self.by_cid.contains_key(cid)
+    }
+
+    /// Validates `msg` against `state` and queues it, returning its CID.
+    /// A message reusing a pending message's nonce replaces it if it pays a
+    /// higher gas premium.
+    pub fn add(&mut self, msg: SignedMessage, state: &ActorState) -> Result<Cid, PoolError> {
+        let cid = msg.cid();
+        if self.by_cid.contains_key(&cid) {
+            return Err(PoolError::Duplicate);
+        }
+        msg.verify().map_err(PoolError::Invalid)?;
+        let unsigned = &msg.message;
+        if unsigned.gas_limit < gas_cost(&unsigned.message) || unsigned.gas_premium > unsigned.gas_fee_cap {
+            return Err(PoolError::BadGas);
+        }
+        let expected = state.nonce_of(&unsigned.from);
+        if unsigned.nonce < expected {
+            return Err(PoolError::NonceTooLow { expected, got: unsigned.nonce });
+        }
+
+        let (from, nonce) = (unsigned.from, unsigned.nonce);
+        let queue = self.pending.entry(from).or_default();
+        match queue.get(&nonce) {
+            Some((_, pending)) if pending.message.gas_premium >= unsigned.gas_premium => {
+                return Err(PoolError::FeeTooLow {
+                    pending_premium: pending.message.gas_premium,
+                });
+            }
+            Some((replaced, _)) => {
+                self.by_cid.remove(replaced);
+            }
+            None if queue.len() >= MAX_PENDING_PER_SENDER => return Err(PoolError::SenderFull),
+            None => {}
+        }
+        queue.insert(nonce, (cid, msg));
+        self.by_cid.insert(cid, (from, nonce));
+        Ok(cid)
+    }
+
+    /// Picks the next batch to execute against `state`. Each sender
+    /// contributes a run of consecutive nonces starting at its next nonce;
+    /// runs are interleaved by gas premium, highest first, with ties broken
+    /// by message CID.
+    pub fn select(&self, state: &ActorState, limits: BatchLimits) -> Vec<SignedMessage> {
+        let mut heads = BinaryHeap::new();
+        for (sender, queue) in &self.pending {
+            let next = state.nonce_of(sender);
+            if let Some((cid, msg)) = queue.get(&next) {
+                heads.push(Candidate::new(*cid, msg));
+            }
+        }
+
+        let mut selected = Vec::new();
+        let mut gas = 0u64;
+        while let Some(best) = heads.pop() {
+            if selected.len() == limits.max_messages {
+                break;
+            }
+            let msg = best.msg;
+            // A sender whose next message does not fit is skipped entirely,
+            // since its later nonces cannot run without it.
+            let Some(total) = gas.checked_add(msg.message.gas_limit).filter(|g| *g <= limits.gas_limit) else {
+                continue;
+            };
+            gas = total;
+            // No nonce follows u64::MAX, so the sender's run ends there.
+            let following = msg.message.nonce.checked_add(1);
+            if let Some((cid, next)) = following.and_then(|nonce| self.pending[&msg.message.from].get(&nonce)) {
+                heads.push(Candidate::new(*cid, next));
+            }
+            selected.push(msg.clone());
+        }
+        selected
+    }
+
+    /// Selects a batch, applies it to `state`, flushes the result to `store`
+    /// and removes the applied messages, and any made stale, from the pool.
+    pub fn execute_batch<B: Blockstore + ?Sized>(
+        &mut self,
+        state: &mut ActorState,
+        store: &B,
+        limits: BatchLimits,
+    ) -> Result<Batch> {
+        let parent = state.flush(store)?;
+        let mut messages = Vec::new();
+        let mut receipts = Vec::new();
+        for msg in self.select(state, limits) {
+            // Selection already checked signatures and nonces against this
+            // state, so a rejection here means the message cannot apply.
+            if let Ok(receipt) = state.apply_signed(&msg) {
+                messages.push(msg.cid());
+                receipts.push(receipt);
+            }
+        }
+        let state_root = state.flush(store)?;
+        self.prune(state);
+        Ok(Batch {
+            parent,
+            messages,
+            receipts,
+            state_root,
+        })
+    }
+
+    /// Drops messages whose nonce `state` has already used.
+    pub fn prune(&mut self, state: &ActorState) {
+        let by_cid = &mut self.by_cid;
+        self.pending.retain(|sender, queue| {
+            let next = state.nonce_of(sender);
+            let live = queue.split_off(&next);
+            for (cid, _) in queue.values() {
+                by_cid.remove(cid);
+            }
+            *queue = live;
+            !queue.is_empty()
+        });
+    }
+}
+
+/// A sender's next selectable message, ordered for the selection heap.
+struct Candidate<'a> {
+    cid: Cid,
+    msg: &'a SignedMessage,
+}
+
+impl<'a> Candidate<'a> {
+    fn new(cid: Cid, msg: &'a SignedMessage) -> Self {
+        Candidate { cid, msg }
+    }
+}
+
+impl Ord for Candidate<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.msg
+            .message
+            .gas_premium
+            .cmp(&other.msg.message.gas_premium)
+            .then_with(|| other.cid.cmp(&self.cid))
+    }
+}
+
+impl PartialOrd for Candidate<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Candidate<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cid == other.cid
+    }
+}
+
+impl Eq for Candidate<'_> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::{Message, SecretKey, SigType, UnsignedMessage};
+    use fvm_ipld_blockstore::MemoryBlockstore;
+
+    fn signed(key: &SecretKey, nonce: u64, premium: u64, to: Cid) -> SignedMessage {
+        UnsignedMessage {
+            from: key.account(),
+            nonce,
+            value: 0,
+            gas_limit: 1_000,
+            gas_fee_cap: 100,
+            gas_premium: premium,
+            message: Message::Transfer { to, amount: 1 },
+        }
+        .sign(key)
+    }
+
+    fn funded(keys: &[&SecretKey]) -> ActorState {
+        let mut state = ActorState::new();
+        for key in keys {
+            state.handle_message(&Message::Mint { to: key.account(), amount: 100 });
+        }
+        state
+    }
+
+    #[test]
+    fn test_selection_is_independent_of_arrival_order() {
+        let alice = SecretKey::from_bytes(SigType::Secp256k1, &[1; 32]).unwrap();
+        let bob = SecretKey::from_bytes(SigType::Secp256k1, &[2; 32]).unwrap();
+        let messages = vec![
+            signed(&alice, 0, 5, bob.account()),
+            signed(&alice, 1, 50, bob.account()),
+            signed(&alice, 2, 5, bob.account()),
+            signed(&bob, 0, 10, alice.account()),
+            signed(&bob, 1, 1, alice.account()),
+        ];
+
+        let mut roots = Vec::new();
+        for order in [messages.clone(), messages.iter().rev().cloned().collect()] {
+            let bs = MemoryBlockstore::new();
+            let mut state = funded(&[&alice, &bob]);
+            let mut pool = MessagePool::new();
+            for msg in order {
+                pool.add(msg, &state).unwrap();
+            }
+            let nonces: Vec<_> = pool
+                .select(&state, BatchLimits::default())
+                .iter()
+                .map(|m| (m.message.from == alice.account(), m.message.nonce))
+                .collect();
+            // Bob's premium 10 beats Alice's 5; Alice's 50 only counts once
+            // her nonce 0 has gone.
+            assert_eq!(nonces, vec![(false, 0), (true, 0), (true, 1), (true, 2), (false, 1)]);
+
+            let batch = pool.execute_batch(&mut state, &bs, BatchLimits::default()).unwrap();
+            assert!(batch.receipts.iter().all(|r| r.is_success()));
+            assert!(pool.is_empty());
+            roots.push(batch.state_root);
+        }
+        assert_eq!(roots[0], roots[1]);
+    }
+
+    #[test]
+    fn test_add_rejects_duplicates_stale_and_underpriced() {
+        let alice = SecretKey::from_bytes(SigType::Bls, &[3; 32]).unwrap();
+        let bob = SecretKey::from_bytes(SigType::Bls, &[4; 32]).unwrap();
+        let mut state = funded(&[&alice]);
+        let mut pool = MessagePool::new();
+
+        let first = signed(&alice, 0, 5, bob.account());
+        pool.add(first.clone(), &state).unwrap();
+        assert_eq!(pool.add(first.clone(), &state), Err(PoolError::Duplicate));
+        assert_eq!(
+            pool.add(signed(&alice, 0, 5, alice.account()), &state),
+            Err(PoolError::FeeTooLow { pending_premium: 5 })
+        );
+        let replacement = pool.add(signed(&alice, 0, 6, alice.account()), &state).unwrap();
+        assert_eq!(pool.len(), 1);
+        assert!(pool.contains(&replacement) && !pool.contains(&first.cid()));
+
+        let mut forged = signed(&alice, 1, 5, bob.account());
+        forged.message.value = 10;
+        assert_eq!(pool.add(forged, &state), Err(PoolError::Invalid(ActorError::InvalidSignature)));
+
+        // A gap in nonces holds back everything after it.
+        pool.add(signed(&alice, 2, 5, bob.account()), &state).unwrap();
+        assert_eq!(pool.select(&state, BatchLimits::default()).len(), 1);
+
+        pool.execute_batch(&mut state, &MemoryBlockstore::new(), BatchLimits::default()).unwrap();
+        assert_eq!(state.nonce_of(&alice.account()), 1);
+        assert_eq!(
+            pool.add(first, &state),
+            Err(PoolError::NonceTooLow { expected: 1, got: 0 })
+        );
+        assert_eq!(pool.len(), 1);
+    }
+
+    #[test]
+    fn test_batch_respects_gas_limit() {
+        let alice = SecretKey::from_bytes(SigType::Secp256k1, &[5; 32]).unwrap();
+        let state = funded(&[&alice]);
+        let mut pool = MessagePool::new();
+        for nonce in 0..5 {
+            pool.add(signed(&alice, nonce, 1, alice.account()), &state).unwrap();
+        }
+        let limits = BatchLimits { max_messages: 10, gas_limit: 3_500 };
+        assert_eq!(pool.select(&state, limits).len(), 3);
+        let limits = BatchLimits { max_messages: 2, gas_limit: u64::MAX };
+        assert_eq!(pool.select(&state, limits).len(), 2);
+    }
+}
@@ -1,19 +1,631 @@
+use crate::actor_state::{ActorError, Permission};
+use crate::dao::governance::VotingRules;
+use anyhow::{anyhow, Result};
+use bls_signatures::Serialize as _;
+use cid::multihash::Multihash;
 use cid::Cid;
+use fvm_ipld_encoding::tuple::*;
+use fvm_ipld_encoding::{strict_bytes, BytesDe, BytesSer, RawBytes, DAG_CBOR};
+use k256::ecdsa::signature::{Signer, Verifier};
+use multihash_codetable::{Code, MultihashDigest};
+use serde::de::{self, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
 
+/// Multicodec of a compressed secp256k1 public key.
+pub const SECP256K1_PUB: u64 = 0xe7;
+/// Multicodec of a BLS12-381 G1 public key (the min-pk scheme Filecoin uses).
+pub const BLS12_381_G1_PUB: u64 = 0xea;
+const IDENTITY: u64 = 0x00;
 
+/// Encoding version of `UnsignedMessage`, the first field on the wire.
+pub const MESSAGE_VERSION: u64 = 1;
 
+/// Method numbers identifying each `Message` variant in its encoding.
+pub mod method {
+    pub const TRANSFER: u64 = 1;
+    pub const MINT: u64 = 2;
+    pub const BURN: u64 = 3;
+    pub const SET_DATA: u64 = 4;
+    pub const DELEGATE: u64 = 5;
+    pub const REVOKE: u64 = 6;
+    pub const BATCH_TRANSFER: u64 = 7;
+    pub const QUERY_BALANCE: u64 = 8;
+    pub const VOTE: u64 = 9;
+    pub const WITHDRAW: u64 = 10;
+    pub const CUSTOM: u64 = 11;
+    pub const PROPOSE: u64 = 12;
+    pub const TRANSFER_FROM: u64 = 13;
+    pub const ADD_MINTER: u64 = 14;
+    pub const REMOVE_MINTER: u64 = 15;
+    pub const DELETE_DATA: u64 = 16;
+    pub const GET_DATA: u64 = 17;
+    pub const SET_VOTING_RULES: u64 = 18;
+}
 
-#[derive(Debug)]
+/// Encoded in DAG-CBOR as `[method, params]`, with `params` the variant's
+/// fields as a tuple in declaration order, so a message maps onto the method
+/// number and parameter block of an FVM send.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Message {
+    /// Pays `amount` to `to`: from the sender of a signed message, otherwise
+    /// from the actor's own balance.
     Transfer { to: Cid, amount: u64 },
+    /// Creates `amount` new tokens for `to`. Sent by the system or a minter.
     Mint { to: Cid, amount: u64 },
     Burn { from: Cid, amount: u64 },
+    /// Writes `value` under `key` in the actor's data store. Keys of the form
+    /// `<account>/...` belong to that account; others to their first writer.
     SetData { key: String, value: String },
-    Delegate { from: Cid, to: Cid, permissions: Vec<String> },
+    /// Grants `to` the given permissions on behalf of `from` until the epoch
+    /// `expires_at`, or indefinitely.
+    Delegate { from: Cid, to: Cid, permissions: Vec<Permission>, expires_at: Option<u64> },
     Revoke { from: Cid, to: Cid },
     BatchTransfer { transfers: Vec<(Cid, u64)> },
     QueryBalance { account: Cid },
     Vote { proposal_id: u64, voter: Cid, support: bool },
     Withdraw { from: Cid, amount: u64 },
-    Custom { data: Vec<u8> },
+    /// An application-defined method with encoded parameters, executed by
+    /// the handler registered for `method`; see `custom::HandlerRegistry`.
+    Custom { method: u64, data: Vec<u8> },
+    /// Opens a proposal to apply `actions`, voted on under the actor's
+    /// current rules for `voting_period` epochs from the current one. Only
+    /// payouts from the actor balance and `SetVotingRules` may be proposed.
+    Propose {
+        proposer: Cid,
+        description: String,
+        actions: Vec<Message>,
+        voting_period: u64,
+    },
+    /// Pays `amount` from `owner` to `to`. Sent by the owner, or by a
+    /// delegate within its `TransferUpTo` cap.
+    TransferFrom { owner: Cid, to: Cid, amount: u64 },
+    /// Lets `minter` mint. Sent by the system or an existing minter, as is
+    /// `RemoveMinter`.
+    AddMinter { minter: Cid },
+    RemoveMinter { minter: Cid },
+    /// Clears `key`, keeping its history. Allowed to whoever may set it.
+    DeleteData { key: String },
+    /// Returns the value under `key`, DAG-CBOR encoded, or null.
+    GetData { key: String },
+    /// Replaces the rules for proposals opened from now on. Sent by the
+    /// system or as the action of a passed proposal.
+    SetVotingRules { rules: VotingRules },
+}
+
+impl Message {
+    pub fn method(&self) -> u64 {
+        match self {
+            Message::Transfer { .. } => method::TRANSFER,
+            Message::Mint { .. } => method::MINT,
+            Message::Burn { .. } => method::BURN,
+            Message::SetData { .. } => method::SET_DATA,
+            Message::Delegate { .. } => method::DELEGATE,
+            Message::Revoke { .. } => method::REVOKE,
+            Message::BatchTransfer { .. } => method::BATCH_TRANSFER,
+            Message::QueryBalance { .. } => method::QUERY_BALANCE,
+            Message::Vote { .. } => method::VOTE,
+            Message::Withdraw { .. } => method::WITHDRAW,
+            Message::Custom { .. } => method::CUSTOM,
+            Message::Propose { .. } => method::PROPOSE,
+            Message::TransferFrom { .. } => method::TRANSFER_FROM,
+            Message::AddMinter { .. } => method::ADD_MINTER,
+            Message::RemoveMinter { .. } => method::REMOVE_MINTER,
+            Message::DeleteData { .. } => method::DELETE_DATA,
+            Message::GetData { .. } => method::GET_DATA,
+            Message::SetVotingRules { .. } => method::SET_VOTING_RULES,
+        }
+    }
+
+    /// The encoded parameter block, as passed to an FVM actor alongside
+    /// `method()`.
+    pub fn params(&self) -> RawBytes {
+        RawBytes::serialize(Params(self)).expect("messages always encode")
+    }
+}
+
+/// Serializes a message's fields without the method number.
+struct Params<'a>(&'a Message);
+
+impl Serialize for Params<'_> {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        match self.0 {
+            Message::Transfer { to, amount } => (to, amount).serialize(s),
+            Message::Mint { to, amount } => (to, amount).serialize(s),
+            Message::Burn { from, amount } => (from, amount).serialize(s),
+            Message::SetData { key, value } => (key, value).serialize(s),
+            Message::Delegate { from, to, permissions, expires_at } => (from, to, permissions, expires_at).serialize(s),
+            Message::Revoke { from, to } => (from, to).serialize(s),
+            Message::BatchTransfer { transfers } => (transfers,).serialize(s),
+            Message::QueryBalance { account } => (account,).serialize(s),
+            Message::Vote { proposal_id, voter, support } => (proposal_id, voter, support).serialize(s),
+            Message::Withdraw { from, amount } => (from, amount).serialize(s),
+            Message::Custom { method, data } => (method, BytesSer(data)).serialize(s),
+            Message::Propose { proposer, description, actions, voting_period } => {
+                (proposer, description, actions, voting_period).serialize(s)
+            }
+            Message::TransferFrom { owner, to, amount } => (owner, to, amount).serialize(s),
+            Message::AddMinter { minter } | Message::RemoveMinter { minter } => (minter,).serialize(s),
+            Message::DeleteData { key } | Message::GetData { key } => (key,).serialize(s),
+            Message::SetVotingRules { rules } => (rules,).serialize(s),
+        }
+    }
+}
+
+impl Serialize for Message {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        (self.method(), Params(self)).serialize(s)
+    }
+}
+
+impl<'de> Deserialize<'de> for Message {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        struct MessageVisitor;
+
+        impl<'de> Visitor<'de> for MessageVisitor {
+            type Value = Message;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a [method, params] message")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Message, A::Error> {
+                let method: u64 = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+                macro_rules! params {
+                    () => {
+                        seq.next_element()?.ok_or_else(|| de::Error::invalid_length(1, &self))?
+                    };
+                }
+                let message = match method {
+                    method::TRANSFER => {
+                        let (to, amount) = params!();
+                        Message::Transfer { to, amount }
+                    }
+                    method::MINT => {
+                        let (to, amount) = params!();
+                        Message::Mint { to, amount }
+                    }
+                    method::BURN => {
+                        let (from, amount) = params!();
+                        Message::Burn { from, amount }
+                    }
+                    method::SET_DATA => {
+                        let (key, value) = params!();
+                        Message::SetData { key, value }
+                    }
+                    method::DELEGATE => {
+                        let (from, to, permissions, expires_at) = params!();
+                        Message::Delegate { from, to, permissions, expires_at }
+                    }
+                    method::REVOKE => {
+                        let (from, to) = params!();
+                        Message::Revoke { from, to }
+                    }
+                    method::BATCH_TRANSFER => {
+                        let (transfers,) = params!();
+                        Message::BatchTransfer { transfers }
+                    }
+                    method::QUERY_BALANCE => {
+                        let (account,) = params!();
+                        Message::QueryBalance { account }
+                    }
+                    method::VOTE => {
+                        let (proposal_id, voter, support) = params!();
+                        Message::Vote { proposal_id, voter, support }
+                    }
+                    method::WITHDRAW => {
+                        let (from, amount) = params!();
+                        Message::Withdraw { from, amount }
+                    }
+                    method::CUSTOM => {
+                        let (method, BytesDe(data)) = params!();
+                        Message::Custom { method, data }
+                    }
+                    method::PROPOSE => {
+                        let (proposer, description, actions, voting_period) = params!();
+                        Message::Propose { proposer, description, actions, voting_period }
+                    }
+                    method::TRANSFER_FROM => {
+                        let (owner, to, amount) = params!();
+                        Message::TransferFrom { owner, to, amount }
+                    }
+                    method::ADD_MINTER => {
+                        let (minter,) = params!();
+                        Message::AddMinter { minter }
+                    }
+                    method::REMOVE_MINTER => {
+                        let (minter,) = params!();
+                        Message::RemoveMinter { minter }
+                    }
+                    method::DELETE_DATA => {
+                        let (key,) = params!();
+                        Message::DeleteData { key }
+                    }
+                    method::GET_DATA => {
+                        let (key,) = params!();
+                        Message::GetData { key }
+                    }
+                    method::SET_VOTING_RULES => {
+                        let (rules,) = params!();
+                        Message::SetVotingRules { rules }
+                    }
+                    other => return Err(de::Error::custom(format!("unknown message method {}", other))),
+                };
+                if seq.next_element::<de::IgnoredAny>()?.is_some() {
+                    return Err(de::Error::invalid_length(3, &self));
+                }
+                Ok(message)
+            }
+        }
+
+        d.deserialize_seq(MessageVisitor)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SigType {
+    Secp256k1,
+    Bls,
+}
+
+impl SigType {
+    fn codec(self) -> u64 {
+        match self {
+            SigType::Secp256k1 => SECP256K1_PUB,
+            SigType::Bls => BLS12_381_G1_PUB,
+        }
+    }
+
+    /// Leading byte of an encoded signature, as in Filecoin.
+    fn tag(self) -> u8 {
+        match self {
+            SigType::Secp256k1 => 1,
+            SigType::Bls => 2,
+        }
+    }
+}
+
+/// Encoded as a byte string: the type tag followed by the signature bytes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signature {
+    pub sig_type: SigType,
+    pub bytes: Vec<u8>,
+}
+
+impl Serialize for Signature {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        let mut encoded = Vec::with_capacity(self.bytes.len() + 1);
+        encoded.push(self.sig_type.tag());
+        encoded.extend_from_slice(&self.bytes);
+        strict_bytes::serialize(&encoded, s)
+    }
+}
+
+impl<'de> Deserialize<'de> for Signature {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        let encoded: Vec<u8> = strict_bytes::deserialize(d)?;
+        let sig_type = match encoded.first() {
+            Some(1) => SigType::Secp256k1,
+            Some(2) => SigType::Bls,
+            Some(tag) => return Err(de::Error::custom(format!("unknown signature type {}", tag))),
+            None => return Err(de::Error::custom("empty signature")),
+        };
+        Ok(Signature {
+            sig_type,
+            bytes: encoded[1..].to_vec(),
+        })
+    }
+}
+
+/// A message together with the sender and the parameters needed to order,
+/// meter and authenticate it. Encoded as a tuple led by `MESSAGE_VERSION`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(into = "MessageRecord", try_from = "MessageRecord")]
+pub struct UnsignedMessage {
+    /// Account of the signer; see `account_for_key`.
+    pub from: Cid,
+    /// Must equal the number of messages the sender has had applied so far.
+    pub nonce: u64,
+    /// Paid from the sender to the actor balance before the message runs.
+    pub value: u64,
+    /// Most gas the message may use; it fails without effect if it needs more.
+    pub gas_limit: u64,
+    pub gas_fee_cap: u64,
+    pub gas_premium: u64,
+    pub message: Message,
+}
+
+#[derive(Serialize_tuple, Deserialize_tuple)]
+struct MessageRecord {
+    version: u64,
+    from: Cid,
+    nonce: u64,
+    value: u64,
+    gas_limit: u64,
+    gas_fee_cap: u64,
+    gas_premium: u64,
+    message: Message,
+}
+
+impl From<UnsignedMessage> for MessageRecord {
+    fn from(m: UnsignedMessage) -> Self {
+        MessageRecord {
+            version: MESSAGE_VERSION,
+            from: m.from,
+            nonce: m.nonce,
+            value: m.value,
+            gas_limit: m.gas_limit,
+            gas_fee_cap: m.gas_fee_cap,
+            gas_premium: m.gas_premium,
+            message: m.message,
+        }
+    }
+}
+
+impl TryFrom<MessageRecord> for UnsignedMessage {
+    type Error = String;
+
+    fn try_from(r: MessageRecord) -> Result<Self, String> {
+        if r.version != MESSAGE_VERSION {
+            return Err(format!("unsupported message version {}", r.version));
+        }
+        Ok(UnsignedMessage {
+            from: r.from,
+            nonce: r.nonce,
+            value: r.value,
+            gas_limit: r.gas_limit,
+            gas_fee_cap: r.gas_fee_cap,
+            gas_premium: r.gas_premium,
+            message: r.message,
+        })
+    }
+}
+
+impl UnsignedMessage {
+    /// CID of the message's DAG-CBOR encoding. Its bytes are what gets signed.
+    pub fn cid(&self) -> Cid {
+        let bytes = fvm_ipld_encoding::to_vec(self).expect("messages always encode");
+        Cid::new_v1(DAG_CBOR, Code::Sha2_256.digest(&bytes))
+    }
+
+    pub fn sign(self, key: &SecretKey) -> SignedMessage {
+        let signature = key.sign(&self.cid().to_bytes());
+        SignedMessage { message: self, signature }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize_tuple, Deserialize_tuple)]
+pub struct SignedMessage {
+    pub message: UnsignedMessage,
+    pub signature: Signature,
+}
+
+impl SignedMessage {
+    pub fn cid(&self) -> Cid {
+        let bytes = fvm_ipld_encoding::to_vec(self).expect("messages always encode");
+        Cid::new_v1(DAG_CBOR, Code::Sha2_256.digest(&bytes))
+    }
+
+    /// Checks that the signature was made over the message by the key the
+    /// sender account embeds.
+    pub fn verify(&self) -> Result<(), ActorError> {
+        let (sig_type, public_key) = key_for_account(&self.message.from).ok_or(ActorError::InvalidSignature)?;
+        if sig_type != self.signature.sig_type {
+            return Err(ActorError::InvalidSignature);
+        }
+        let signed = self.message.cid().to_bytes();
+        let valid = match sig_type {
+            SigType::Secp256k1 => {
+                let key = k256::ecdsa::VerifyingKey::from_sec1_bytes(public_key);
+                let signature = k256::ecdsa::Signature::from_slice(&self.signature.bytes);
+                matches!((key, signature), (Ok(key), Ok(signature)) if key.verify(&signed, &signature).is_ok())
+            }
+            SigType::Bls => {
+                let key = bls_signatures::PublicKey::from_bytes(public_key);
+                let signature = bls_signatures::Signature::from_bytes(&self.signature.bytes);
+                matches!((key, signature), (Ok(key), Ok(signature)) if key.verify(signature, &signed))
+            }
+        };
+        if valid {
+            Ok(())
+        } else {
+            Err(ActorError::InvalidSignature)
+        }
+    }
+}
+
+/// A private key for signing messages.
+pub enum SecretKey {
+    Secp256k1(k256::ecdsa::SigningKey),
+    Bls(bls_signatures::PrivateKey),
+}
+
+impl SecretKey {
+    /// Generates a random key of the given type.
+    pub fn generate(sig_type: SigType) -> Result<Self> {
+        loop {
+            let mut seed = [0u8; 32];
+            getrandom::fill(&mut seed).map_err(|e| anyhow!("random source failed: {}", e))?;
+            match sig_type {
+                SigType::Bls => return Ok(SecretKey::Bls(bls_signatures::PrivateKey::new(seed))),
+                // A seed outside the curve order is rejected; draw another.
+                SigType::Secp256k1 => {
+                    if let Ok(key) = k256::ecdsa::SigningKey::from_slice(&seed) {
+                        return Ok(SecretKey::Secp256k1(key));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Parses a 32-byte secret key.
+    pub fn from_bytes(sig_type: SigType, bytes: &[u8]) -> Result<Self> {
+        match sig_type {
+            SigType::Secp256k1 => Ok(SecretKey::Secp256k1(
+                k256::ecdsa::SigningKey::from_slice(bytes).map_err(|e| anyhow!("invalid secp256k1 key: {}", e))?,
+            )),
+            SigType::Bls => Ok(SecretKey::Bls(
+                bls_signatures::PrivateKey::from_bytes(bytes).map_err(|e| anyhow!("invalid BLS key: {}", e))?,
+            )),
+        }
+    }
+
+    pub fn sig_type(&self) -> SigType {
+        match self {
+            SecretKey::Secp256k1(_) => SigType::Secp256k1,
+            SecretKey::Bls(_) => SigType::Bls,
+        }
+    }
+
+    /// The account messages signed with this key are sent from.
+    pub fn account(&self) -> Cid {
+        let public_key = match self {
+            SecretKey::Secp256k1(key) => key.verifying_key().to_sec1_bytes().to_vec(),
+            SecretKey::Bls(key) => key.public_key().as_bytes(),
+        };
+        account_for_key(self.sig_type(), &public_key)
+    }
+
+    fn sign(&self, data: &[u8]) -> Signature {
+        let bytes = match self {
+            SecretKey::Secp256k1(key) => {
+                let signature: k256::ecdsa::Signature = key.sign(data);
+                signature.to_vec()
+            }
+            SecretKey::Bls(key) => key.sign(data).as_bytes(),
+        };
+        Signature {
+            sig_type: self.sig_type(),
+            bytes,
+        }
+    }
+}
+
+/// Account CID for a public key: the key itself, in an identity multihash
+/// tagged with the key type's multicodec.
+pub fn account_for_key(sig_type: SigType, public_key: &[u8]) -> Cid {
+    let hash = Multihash::wrap(IDENTITY, public_key).expect("public keys fit in an identity multihash");
+    Cid::new_v1(sig_type.codec(), hash)
+}
+
+/// The key type and public key embedded in `account`, if it is a key account.
+pub fn key_for_account(account: &Cid) -> Option<(SigType, &[u8])> {
+    if account.hash().code() != IDENTITY {
+        return None;
+    }
+    let sig_type = match account.codec() {
+        SECP256K1_PUB => SigType::Secp256k1,
+        BLS12_381_G1_PUB => SigType::Bls,
+        _ => return None,
+    };
+    Some((sig_type, account.hash().digest()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transfer(from: Cid) -> UnsignedMessage {
+        UnsignedMessage {
+            from,
+            nonce: 0,
+            value: 0,
+            gas_limit: 1_000,
+            gas_fee_cap: 1,
+            gas_premium: 1,
+            message: Message::Transfer { to: from, amount: 1 },
+        }
+    }
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    fn raw_account(name: &str) -> Cid {
+        Cid::new_v1(0x55, Multihash::wrap(IDENTITY, name.as_bytes()).unwrap())
+    }
+
+    #[test]
+    fn test_golden_vectors() {
+        let (alice, bob) = (raw_account("alice"), raw_account("bob"));
+        let vectors = [
+            (
+                Message::Transfer { to: alice, amount: 1000 },
+                "820182d82a4a0001550005616c6963651903e8",
+            ),
+            (
+                Message::Delegate {
+                    from: alice,
+                    to: bob,
+                    permissions: vec![Permission::Vote],
+                    expires_at: Some(100),
+                },
+                "820584d82a4a0001550005616c696365d82a480001550003626f628164766f74651864",
+            ),
+            (Message::Custom { method: 7, data: vec![1, 2, 3] }, "820b820743010203"),
+            (
+                Message::Propose {
+                    proposer: alice,
+                    description: "pay".to_string(),
+                    actions: vec![Message::Transfer { to: bob, amount: 5 }],
+                    voting_period: 10,
+                },
+                "820c84d82a4a0001550005616c6963656370617981820182d82a480001550003626f62050a",
+            ),
+            (Message::SetVotingRules { rules: VotingRules::majority() }, "821281830018321833"),
+        ];
+        for (message, expected) in vectors {
+            let bytes = fvm_ipld_encoding::to_vec(&message).unwrap();
+            assert_eq!(hex(&bytes), expected);
+            assert_eq!(fvm_ipld_encoding::from_slice::<Message>(&bytes).unwrap(), message);
+        }
+
+        let unsigned = UnsignedMessage {
+            from: alice,
+            nonce: 7,
+            value: 5,
+            gas_limit: 1000,
+            gas_fee_cap: 2,
+            gas_premium: 1,
+            message: Message::QueryBalance { account: bob },
+        };
+        let bytes = fvm_ipld_encoding::to_vec(&unsigned).unwrap();
+        assert_eq!(hex(&bytes), "8801d82a4a0001550005616c69636507051903e80201820881d82a480001550003626f62");
+        assert_eq!(unsigned.cid().to_string(), "bafyreihtjqctwa4yonrh6l23zghb7q3nvsxsufny2dsjlj5ci5chcfanfa");
+        assert_eq!(hex(unsigned.message.params().bytes()), "81d82a480001550003626f62");
+
+        let signed = SignedMessage {
+            message: unsigned,
+            signature: Signature { sig_type: SigType::Bls, bytes: vec![9; 4] },
+        };
+        let bytes = fvm_ipld_encoding::to_vec(&signed).unwrap();
+        assert!(hex(&bytes).ends_with("450209090909"));
+        assert_eq!(fvm_ipld_encoding::from_slice::<SignedMessage>(&bytes).unwrap(), signed);
+
+        // Unknown versions and methods are rejected rather than misread.
+        let mut future = fvm_ipld_encoding::to_vec(&signed.message).unwrap();
+        future[1] = 0x02;
+        assert!(fvm_ipld_encoding::from_slice::<UnsignedMessage>(&future).is_err());
+        assert!(fvm_ipld_encoding::from_slice::<Message>(&[0x82, 0x18, 0x63, 0x80]).is_err());
+    }
+
+    #[test]
+    fn test_sign_and_verify() {
+        for sig_type in [SigType::Secp256k1, SigType::Bls] {
+            let key = SecretKey::generate(sig_type).unwrap();
+            let account = key.account();
+            assert_eq!(key_for_account(&account).map(|(t, _)| t), Some(sig_type));
+
+            let signed = transfer(account).sign(&key);
+            assert_eq!(signed.verify(), Ok(()));
+
+            let mut tampered = signed.clone();
+            tampered.message.nonce = 1;
+            assert_eq!(tampered.verify(), Err(ActorError::InvalidSignature));
+
+            // Signed by someone else on the sender's behalf.
+            let forged = transfer(account).sign(&SecretKey::generate(sig_type).unwrap());
+            assert_eq!(forged.verify(), Err(ActorError::InvalidSignature));
+        }
+    }
 }
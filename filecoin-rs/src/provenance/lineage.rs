@@ -1,5 +1,7 @@
 use serde::{Serialize, Deserialize};
-use crate::storage::provider::StorageProvider;
+use crate::storage::provider::{StorageError, StorageProvider};
+use std::collections::HashMap;
+use std::sync::Mutex;
 
 #[derive(Serialize, Deserialize)]
 pub struct DataLineage {
@@ -12,14 +14,18 @@ pub struct DataLineage {
 
 pub struct ProvenanceManager<T: StorageProvider> {
     storage: T,
+    lineages: Mutex<HashMap<String, String>>, // Data CID -> lineage CID
 }
 
 impl<T: StorageProvider> ProvenanceManager<T> {
     pub fn new(storage: T) -> Self {
-        ProvenanceManager { storage }
+        ProvenanceManager {
+            storage,
+            lineages: Mutex::new(HashMap::new()),
+        }
     }
 
-    pub async fn track(&self, data: Vec<u8>, origin: String, creator: String) -> Result<String, String> {
+    pub async fn track(&self, data: Vec<u8>, origin: String, creator: String) -> Result<String, StorageError> {
         // Upload data to Filecoin
         let cid = self.storage.upload(data.clone()).await?;
 
@@ -39,24 +45,33 @@ impl<T: StorageProvider> ProvenanceManager<T> {
         let lineage_data = serde_json::to_vec(&lineage).unwrap();
         let lineage_cid = self.storage.upload(lineage_data).await?;
 
-        // In a real implementation, store a mapping of data CID to lineage CID (e.g., in a local database or on-chain)
-        println!("Lineage CID for data CID {}: {}", cid, lineage_cid);
+        // Remember which lineage record belongs to this data CID
+        self.lineages.lock().unwrap().insert(cid.clone(), lineage_cid);
 
         Ok(cid)
     }
 
-    pub async fn verify(&self, cid: &str) -> Result<DataLineage, String> {
-        // In a real implementation, retrieve the lineage CID for the given data CID
-        // For now, assume lineage CID is known or stored
-        let lineage_cid = "mock-lineage-cid"; // Replace with actual retrieval logic
+    pub async fn verify(&self, cid: &str) -> Result<DataLineage, StorageError> {
+        // Retrieve the lineage CID recorded for the given data CID
+        let lineage_cid = self
+            .lineages
+            .lock()
+            .unwrap()
+            .get(cid)
+            .cloned()
+            .ok_or_else(|| StorageError::NotFound(format!("lineage for {}", cid)))?;
 
         // Download lineage metadata
-        let lineage_data = self.storage.download(lineage_cid).await?;
-        let lineage: DataLineage = serde_json::from_slice(&lineage_data).unwrap();
+        let lineage_data = self.storage.download(&lineage_cid).await?;
+        let lineage: DataLineage = serde_json::from_slice(&lineage_data)
+            .map_err(|e| StorageError::Integrity(e.to_string()))?;
+        if lineage.cid != cid {
+            return Err(StorageError::Integrity(format!("Lineage {} does not describe {}", lineage_cid, cid)));
+        }
 
-        // Verify the data hasn't been tampered with
-        let original_data = self.storage.download(cid).await?;
-        // Add integrity check (e.g., hash comparison)
+        // Make sure the data itself is still retrievable; content-addressed
+        // providers verify its hash against the CID on download
+        self.storage.download(cid).await?;
 
         Ok(lineage)
     }
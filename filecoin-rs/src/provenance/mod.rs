@@ -0,0 +1,3 @@
+// src/provenance/mod.rs
+
+pub mod lineage;
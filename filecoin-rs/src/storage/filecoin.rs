@@ -1,28 +1,393 @@
-use crate::storage::provider::StorageProvider;
+use crate::storage::lotus::{DataRef, FileRef, LotusCid, LotusClient, LotusError, RetrievalOrder, StartDealParams};
+use crate::storage::provider::{ListPage, ObjectStat, StorageError, StorageProvider};
 use async_trait::async_trait;
+use cid::Cid;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
 
-// Placeholder for Filecoin client (e.g., using lotus or a Filecoin Rust library)
+/// Parameters for the storage deal proposed after each upload. The wallet is
+/// also used as the paying client for retrievals; without a deal config the
+/// node's default wallet pays.
+#[derive(Debug, Clone)]
+pub struct DealConfig {
+    pub wallet: String,
+    pub miner: String,
+    /// Price per epoch in attoFIL.
+    pub epoch_price: String,
+    pub min_blocks_duration: u64,
+    pub fast_retrieval: bool,
+    pub verified_deal: bool,
+}
+
+/// Storage backed by a Lotus node over its JSON-RPC API.
+///
+/// Lotus imports and retrieves files by path on the node's own filesystem, so
+/// `import_dir` must be a directory visible to both this process and the node.
 pub struct FilecoinStorage {
-    // Add Filecoin client configuration (e.g., API endpoint, auth token)
+    client: LotusClient,
+    import_dir: PathBuf,
+    deal: Option<DealConfig>,
+    counter: AtomicU64,
 }
 
 impl FilecoinStorage {
-    pub fn new() -> Self {
-        FilecoinStorage {}
+    pub fn new(endpoint: &str, token: Option<String>, import_dir: impl Into<PathBuf>) -> Self {
+        FilecoinStorage {
+            client: LotusClient::new(endpoint, token),
+            import_dir: import_dir.into(),
+            deal: None,
+            counter: AtomicU64::new(0),
+        }
+    }
+
+    /// Proposes a storage deal with these parameters after every upload.
+    pub fn with_deal_config(mut self, deal: DealConfig) -> Self {
+        self.deal = Some(deal);
+        self
+    }
+
+    /// The underlying JSON-RPC client, for chain and state queries.
+    pub fn client(&self) -> &LotusClient {
+        &self.client
+    }
+
+    fn scratch_path(&self, label: &str) -> PathBuf {
+        let seq = self.counter.fetch_add(1, Ordering::Relaxed);
+        self.import_dir
+            .join(format!("filecoin-rs-{}-{}-{}", std::process::id(), seq, label))
+    }
+
+    async fn import(&self, data: &[u8]) -> Result<Cid, LotusError> {
+        std::fs::create_dir_all(&self.import_dir)?;
+        let path = self.scratch_path("import");
+        std::fs::write(&path, data)?;
+        let file = FileRef {
+            path: path.to_string_lossy().into_owned(),
+            is_car: false,
+        };
+        let result = self.client.client_import(&file).await;
+        // Lotus copies the data into its own store during the import.
+        let _ = std::fs::remove_file(&path);
+        let root = result?.root.0;
+
+        if let Some(deal) = &self.deal {
+            let params = StartDealParams {
+                data: DataRef {
+                    transfer_type: "graphsync".to_string(),
+                    root: LotusCid(root),
+                },
+                wallet: deal.wallet.clone(),
+                miner: deal.miner.clone(),
+                epoch_price: deal.epoch_price.clone(),
+                min_blocks_duration: deal.min_blocks_duration,
+                deal_start_epoch: -1,
+                fast_retrieval: deal.fast_retrieval,
+                verified_deal: deal.verified_deal,
+            };
+            let proposal = self.client.client_start_deal(&params).await?;
+            log::info!("proposed deal {} for {} with {}", proposal, root, deal.miner);
+        }
+        Ok(root)
+    }
+
+    async fn retrieve(&self, root: &Cid) -> Result<Vec<u8>, LotusError> {
+        let wallet = match &self.deal {
+            Some(deal) => deal.wallet.clone(),
+            None => self
+                .client
+                .wallet_default_address()
+                .await?
+                .ok_or(LotusError::NoWallet)?,
+        };
+        let offers = self.client.client_find_data(root).await?;
+        let offer = offers
+            .iter()
+            .find(|o| o.err.is_empty())
+            .ok_or(LotusError::NoOffers(*root))?;
+
+        std::fs::create_dir_all(&self.import_dir)?;
+        let path = self.scratch_path("retrieve");
+        let file = FileRef {
+            path: path.to_string_lossy().into_owned(),
+            is_car: false,
+        };
+        self.client
+            .client_retrieve(&RetrievalOrder::from_offer(offer, &wallet), &file)
+            .await?;
+        let data = std::fs::read(&path)?;
+        let _ = std::fs::remove_file(&path);
+        Ok(data)
+    }
+}
+
+impl From<LotusError> for StorageError {
+    fn from(e: LotusError) -> Self {
+        match e {
+            LotusError::Unauthorized => StorageError::Unauthorized,
+            LotusError::Transport(msg) => StorageError::Transport(msg),
+            LotusError::Decode(msg) => StorageError::Transport(msg),
+            // Standard JSON-RPC codes say what went wrong regardless of the
+            // message; Lotus' own errors are only told apart by their text.
+            LotusError::Rpc { code: -32601, method, .. } => {
+                StorageError::Unsupported(format!("lotus rpc method {}", method))
+            }
+            LotusError::Rpc { code: -32700 | -32600, message, .. } => StorageError::Transport(message),
+            LotusError::Rpc { code: -32602, message, .. } => StorageError::Other(message),
+            LotusError::Rpc { message, .. } => {
+                let lower = message.to_lowercase();
+                if lower.contains("not found") {
+                    StorageError::NotFound(message)
+                } else if lower.contains("insufficient") || lower.contains("quota") {
+                    StorageError::Quota(message)
+                } else {
+                    StorageError::Other(message)
+                }
+            }
+            LotusError::Io(msg) => StorageError::Other(msg),
+            LotusError::NoOffers(root) => StorageError::NotFound(root.to_string()),
+            LotusError::NoWallet => StorageError::Unsupported("retrieval without a wallet".to_string()),
+        }
     }
 }
 
 #[async_trait]
 impl StorageProvider for FilecoinStorage {
-    async fn upload(&self, data: Vec<u8>) -> Result<String, String> {
-        // Implement Filecoin upload (e.g., via Filecoin API or lotus client)
-        // For now, return a mock CID
-        Ok("bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi".to_string())
+    async fn upload(&self, data: Vec<u8>) -> Result<String, StorageError> {
+        Ok(self.import(&data).await?.to_string())
+    }
+
+    async fn download(&self, cid: &str) -> Result<Vec<u8>, StorageError> {
+        let cid = cid.parse::<Cid>()?;
+        Ok(self.retrieve(&cid).await?)
+    }
+
+    async fn exists(&self, cid: &str) -> Result<bool, StorageError> {
+        Ok(self.client.client_has_local(&cid.parse::<Cid>()?).await?)
+    }
+
+    async fn stat(&self, cid: &str) -> Result<ObjectStat, StorageError> {
+        let root = cid.parse::<Cid>()?;
+        if !self.client.client_has_local(&root).await? {
+            return Err(StorageError::NotFound(cid.to_string()));
+        }
+        let size = self.client.client_deal_size(&root).await?;
+        // Lotus does not expose the block structure, only the payload size.
+        Ok(ObjectStat {
+            cid: cid.to_string(),
+            size: size.payload_size,
+            codec: root.codec(),
+            links: Vec::new(),
+        })
+    }
+
+    /// Lists the roots of the node's client imports.
+    async fn list(&self, cursor: Option<String>, limit: usize) -> Result<ListPage, StorageError> {
+        let mut roots: Vec<String> = self
+            .client
+            .client_list_imports()
+            .await?
+            .into_iter()
+            .filter(|i| i.err.is_empty())
+            .filter_map(|i| i.root.map(|r| r.0.to_string()))
+            .collect();
+        roots.sort();
+        roots.dedup();
+        let start = match &cursor {
+            Some(c) => roots.partition_point(|r| r <= c),
+            None => 0,
+        };
+        let end = (start + limit).min(roots.len());
+        Ok(ListPage {
+            cids: roots[start..end].to_vec(),
+            next_cursor: if end < roots.len() && end > start {
+                Some(roots[end - 1].clone())
+            } else {
+                None
+            },
+        })
+    }
+
+    /// Removes the client imports for `cid`. Deals already made are unaffected.
+    async fn delete(&self, cid: &str) -> Result<(), StorageError> {
+        let root = cid.parse::<Cid>()?;
+        let imports: Vec<u64> = self
+            .client
+            .client_list_imports()
+            .await?
+            .into_iter()
+            .filter(|i| i.root.map(|r| r.0) == Some(root))
+            .map(|i| i.key)
+            .collect();
+        if imports.is_empty() {
+            return Err(StorageError::NotFound(cid.to_string()));
+        }
+        for key in imports {
+            self.client.client_remove_import(key).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+
+    const ROOT: &str = "bafkreifzjut3te2nhyekklss27nh3k72ysco7y32koao5eei66wof36n5e";
+
+    /// Serves JSON-RPC requests on a local port, answering each with the
+    /// response object returned by `handler`.
+    fn spawn_mock_node(handler: fn(&str, &Value) -> Value) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut content_length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                    if let Some(v) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                        content_length = v.trim().parse().unwrap();
+                    }
+                }
+                let mut body = vec![0u8; content_length];
+                reader.read_exact(&mut body).unwrap();
+                let request: Value = serde_json::from_slice(&body).unwrap();
+                let method = request["method"].as_str().unwrap().trim_start_matches("Filecoin.");
+                let mut response = handler(method, &request["params"]);
+                response["jsonrpc"] = json!("2.0");
+                response["id"] = request["id"].clone();
+                let payload = response.to_string();
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    payload.len(),
+                    payload
+                )
+                .unwrap();
+            }
+        });
+        format!("http://{}/rpc/v0", addr)
+    }
+
+    #[tokio::test]
+    async fn test_chain_and_state_queries() {
+        let endpoint = spawn_mock_node(|method, _| match method {
+            "ChainHead" => json!({ "result": { "Cids": [{ "/": ROOT }], "Blocks": [], "Height": 42 } }),
+            "StateGetActor" => json!({ "result": {
+                "Code": { "/": ROOT }, "Head": { "/": ROOT }, "Nonce": 7, "Balance": "1000"
+            } }),
+            _ => json!({ "error": { "code": -32601, "message": "method not found" } }),
+        });
+        let storage = FilecoinStorage::new(&endpoint, Some("token".to_string()), std::env::temp_dir());
+
+        let head = storage.client().chain_head().await.unwrap();
+        assert_eq!(head.height, 42);
+        let actor = storage.client().state_get_actor("f01234", &[head.cids[0].0]).await.unwrap();
+        assert_eq!((actor.nonce, actor.balance.as_str()), (7, "1000"));
+
+        // "method not found" names a missing RPC method, not a missing CID.
+        assert_eq!(
+            storage.exists(ROOT).await.unwrap_err(),
+            StorageError::Unsupported("lotus rpc method Filecoin.ClientHasLocal".to_string())
+        );
     }
 
-    async fn download(&self, cid: &str) -> Result<Vec<u8>, String> {
-        // Implement Filecoin download
-        // For now, return mock data
-        Ok(vec![1, 2, 3])
+    #[tokio::test]
+    async fn test_download_without_offers_is_not_found() {
+        let endpoint = spawn_mock_node(|method, _| match method {
+            "WalletDefaultAddress" => json!({ "result": "f1default" }),
+            "ClientFindData" => json!({ "result": [
+                { "Err": "miner offline", "Root": { "/": ROOT }, "Size": 11, "MinPrice": "0",
+                  "UnsealPrice": "0", "PaymentInterval": 0, "PaymentIntervalIncrease": 0, "Miner": "f09999" },
+            ] }),
+            _ => json!({ "error": { "code": -32601, "message": "method not found" } }),
+        });
+        let storage = FilecoinStorage::new(&endpoint, None, std::env::temp_dir());
+        assert_eq!(storage.download(ROOT).await.unwrap_err(), StorageError::NotFound(ROOT.to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_upload_proposes_deal_and_download_retrieves() {
+        let endpoint = spawn_mock_node(|method, params| match method {
+            "ClientImport" => json!({ "result": { "Root": { "/": ROOT }, "ImportID": 1 } }),
+            "ClientStartDeal" => {
+                let deal = &params[0];
+                assert_eq!(deal["Data"]["Root"]["/"], ROOT);
+                assert_eq!(deal["Data"]["TransferType"], "graphsync");
+                assert_eq!((deal["Wallet"].as_str(), deal["Miner"].as_str()), (Some("f1client"), Some("f01000")));
+                assert_eq!((deal["MinBlocksDuration"].as_u64(), deal["DealStartEpoch"].as_i64()), (Some(518400), Some(-1)));
+                json!({ "result": { "/": ROOT } })
+            }
+            "ClientFindData" => {
+                assert_eq!(params[0]["/"], ROOT);
+                json!({ "result": [
+                    { "Err": "miner offline", "Root": { "/": ROOT }, "Size": 11, "MinPrice": "0",
+                      "UnsealPrice": "0", "PaymentInterval": 0, "PaymentIntervalIncrease": 0, "Miner": "f09999" },
+                    { "Root": { "/": ROOT }, "Size": 11, "MinPrice": "22", "UnsealPrice": "0",
+                      "PaymentInterval": 1048576, "PaymentIntervalIncrease": 1048576, "Miner": "f01000" },
+                ] })
+            }
+            "ClientRetrieve" => {
+                let (order, file) = (&params[0], &params[1]);
+                assert_eq!((order["Client"].as_str(), order["Miner"].as_str()), (Some("f1client"), Some("f01000")));
+                assert_eq!(order["Total"], "22");
+                std::fs::write(file["Path"].as_str().unwrap(), b"hello world").unwrap();
+                json!({ "result": null })
+            }
+            _ => json!({ "error": { "code": -32601, "message": "method not found" } }),
+        });
+        let storage = FilecoinStorage::new(&endpoint, None, std::env::temp_dir()).with_deal_config(DealConfig {
+            wallet: "f1client".to_string(),
+            miner: "f01000".to_string(),
+            epoch_price: "0".to_string(),
+            min_blocks_duration: 518400,
+            fast_retrieval: true,
+            verified_deal: false,
+        });
+
+        assert_eq!(storage.upload(b"hello world".to_vec()).await.unwrap(), ROOT);
+        assert_eq!(storage.download(ROOT).await.unwrap(), b"hello world");
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_upload_imports_file_and_maps_errors() {
+        let endpoint = spawn_mock_node(|method, params| match method {
+            "ClientImport" => {
+                let path = params[0]["Path"].as_str().unwrap();
+                assert_eq!(std::fs::read(path).unwrap(), b"hello world");
+                json!({ "result": { "Root": { "/": ROOT }, "ImportID": 1 } })
+            }
+            "WalletDefaultAddress" => json!({ "result": "" }),
+            _ => json!({ "error": { "code": 1, "message": "no offers" } }),
+        });
+        let storage = FilecoinStorage::new(&endpoint, None, std::env::temp_dir());
+
+        assert_eq!(storage.upload(b"hello world".to_vec()).await.unwrap(), ROOT);
+        // No deal config, and the node has no default wallet either.
+        assert_eq!(
+            storage.download(ROOT).await.unwrap_err(),
+            StorageError::Unsupported("retrieval without a wallet".to_string())
+        );
+
+        let storage = storage.with_deal_config(DealConfig {
+            wallet: "f1client".to_string(),
+            miner: "f01000".to_string(),
+            epoch_price: "0".to_string(),
+            min_blocks_duration: 518400,
+            fast_retrieval: true,
+            verified_deal: false,
+        });
+        assert_eq!(
+            storage.download(ROOT).await.unwrap_err(),
+            StorageError::Other("no offers".to_string())
+        );
+    }
+}
@@ -0,0 +1,145 @@
+// src/storage/index.rs
+//
+// Root/pin bookkeeping shared by the blockstore-backed providers.
+
+use crate::car::block_links;
+use crate::storage::provider::{ListPage, ObjectStat, StorageError};
+use crate::unixfs::{self, DagError};
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashSet};
+
+/// The set of uploaded roots and the subset of them that is pinned.
+#[derive(Debug, Default, Clone)]
+pub struct RootIndex {
+    roots: BTreeSet<Cid>,
+    pins: BTreeSet<Cid>,
+}
+
+/// On-disk form of `RootIndex`, with CIDs as strings.
+#[derive(Serialize, Deserialize)]
+struct RootIndexFile {
+    roots: Vec<String>,
+    pins: Vec<String>,
+}
+
+impl RootIndex {
+    pub fn add_root(&mut self, cid: Cid) {
+        self.roots.insert(cid);
+    }
+
+    pub fn contains(&self, cid: &Cid) -> bool {
+        self.roots.contains(cid)
+    }
+
+    pub fn is_pinned(&self, cid: &Cid) -> bool {
+        self.pins.contains(cid)
+    }
+
+    pub fn roots(&self) -> impl Iterator<Item = &Cid> {
+        self.roots.iter()
+    }
+
+    /// Pins a root, returning `NotFound` if it was never uploaded.
+    pub fn pin(&mut self, cid: Cid) -> Result<(), StorageError> {
+        if !self.roots.contains(&cid) {
+            return Err(StorageError::NotFound(cid.to_string()));
+        }
+        self.pins.insert(cid);
+        Ok(())
+    }
+
+    pub fn unpin(&mut self, cid: &Cid) {
+        self.pins.remove(cid);
+    }
+
+    /// Removes an unpinned root from the index, returning `Pinned` for a
+    /// pinned one.
+    pub fn remove(&mut self, cid: &Cid) -> Result<(), StorageError> {
+        if self.pins.contains(cid) {
+            return Err(StorageError::Pinned(cid.to_string()));
+        }
+        if !self.roots.remove(cid) {
+            return Err(StorageError::NotFound(cid.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Returns up to `limit` roots ordered by CID bytes, after `cursor`.
+    pub fn page(&self, cursor: Option<String>, limit: usize) -> Result<ListPage, StorageError> {
+        let start = cursor.map(|c| c.parse::<Cid>()).transpose()?;
+        let mut iter = self
+            .roots
+            .iter()
+            .filter(|cid| start.is_none_or(|s| **cid > s));
+        let cids: Vec<Cid> = iter.by_ref().take(limit).copied().collect();
+        let next_cursor = match (cids.last(), iter.next()) {
+            (Some(last), Some(_)) => Some(last.to_string()),
+            _ => None,
+        };
+        Ok(ListPage {
+            cids: cids.iter().map(|c| c.to_string()).collect(),
+            next_cursor,
+        })
+    }
+
+    pub fn to_json(&self) -> Result<Vec<u8>, StorageError> {
+        let file = RootIndexFile {
+            roots: self.roots.iter().map(|c| c.to_string()).collect(),
+            pins: self.pins.iter().map(|c| c.to_string()).collect(),
+        };
+        serde_json::to_vec_pretty(&file).map_err(|e| StorageError::Other(e.to_string()))
+    }
+
+    pub fn from_json(bytes: &[u8]) -> Result<Self, StorageError> {
+        let file: RootIndexFile =
+            serde_json::from_slice(bytes).map_err(|e| StorageError::Integrity(e.to_string()))?;
+        let parse = |v: Vec<String>| -> Result<BTreeSet<Cid>, StorageError> {
+            v.iter().map(|c| Ok(c.parse::<Cid>()?)).collect()
+        };
+        Ok(RootIndex {
+            roots: parse(file.roots)?,
+            pins: parse(file.pins)?,
+        })
+    }
+}
+
+/// Describes the DAG rooted at `cid`.
+pub fn stat_dag<B: Blockstore + ?Sized>(store: &B, cid: &Cid) -> Result<ObjectStat, StorageError> {
+    let block = store
+        .get(cid)?
+        .ok_or_else(|| StorageError::NotFound(cid.to_string()))?;
+    unixfs::verify_block(cid, &block)?;
+    let size = match cid.codec() {
+        unixfs::RAW | unixfs::DAG_PB => unixfs::file_size(store, cid)?,
+        _ => block.len() as u64,
+    };
+    Ok(ObjectStat {
+        cid: cid.to_string(),
+        size,
+        codec: cid.codec(),
+        links: block_links(cid, &block)?.iter().map(|c| c.to_string()).collect(),
+    })
+}
+
+/// Returns every block reachable from `roots`. Missing blocks are skipped so a
+/// partially stored DAG does not block garbage collection.
+pub fn reachable<'a, B, I>(store: &B, roots: I) -> Result<HashSet<Cid>, StorageError>
+where
+    B: Blockstore + ?Sized,
+    I: IntoIterator<Item = &'a Cid>,
+{
+    let mut seen = HashSet::new();
+    let mut stack: Vec<Cid> = roots.into_iter().copied().collect();
+    while let Some(cid) = stack.pop() {
+        if !seen.insert(cid) {
+            continue;
+        }
+        match store.get(&cid)? {
+            Some(block) => stack.extend(block_links(&cid, &block)?),
+            None => log::warn!("{}", DagError::NotFound(cid)),
+        }
+    }
+    Ok(seen)
+}
@@ -0,0 +1,110 @@
+use crate::blockstore::{FsBlockstore, ManagedBlockstore};
+use crate::storage::index::{reachable, stat_dag, RootIndex};
+use crate::storage::provider::{ListPage, ObjectStat, StorageError, StorageProvider};
+use crate::unixfs::{self, FixedSizeChunker};
+use async_trait::async_trait;
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+const BLOCKS_DIR: &str = "blocks";
+const INDEX_FILE: &str = "index.json";
+
+/// Content-addressed provider storing blocks in a local directory, for
+/// offline use. Data, roots and pins persist across restarts.
+#[derive(Debug)]
+pub struct LocalFsStorageProvider {
+    root: PathBuf,
+    store: FsBlockstore,
+    index: Mutex<RootIndex>,
+}
+
+impl LocalFsStorageProvider {
+    pub fn open<P: AsRef<Path>>(root: P) -> anyhow::Result<Self> {
+        let root = root.as_ref().to_path_buf();
+        let store = FsBlockstore::open(root.join(BLOCKS_DIR))?;
+        let index = match std::fs::read(root.join(INDEX_FILE)) {
+            Ok(bytes) => RootIndex::from_json(&bytes)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => RootIndex::default(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(LocalFsStorageProvider {
+            root,
+            store,
+            index: Mutex::new(index),
+        })
+    }
+
+    /// The underlying blockstore.
+    pub fn blockstore(&self) -> &FsBlockstore {
+        &self.store
+    }
+
+    /// Writes the index next to the blocks, replacing the old file atomically.
+    fn save_index(&self, index: &RootIndex) -> Result<(), StorageError> {
+        let tmp = self.root.join(format!("{}.tmp", INDEX_FILE));
+        std::fs::write(&tmp, index.to_json()?)
+            .and_then(|_| std::fs::rename(&tmp, self.root.join(INDEX_FILE)))
+            .map_err(|e| StorageError::Other(e.to_string()))
+    }
+}
+
+#[async_trait]
+impl StorageProvider for LocalFsStorageProvider {
+    async fn upload(&self, data: Vec<u8>) -> Result<String, StorageError> {
+        // Hold the index while writing so a concurrent `delete` cannot
+        // collect the new blocks before their root is recorded.
+        let mut index = self.index.lock().unwrap();
+        let cid = unixfs::add_bytes(&self.store, &FixedSizeChunker::default(), &data)?;
+        if !index.contains(&cid) {
+            index.add_root(cid);
+            self.save_index(&index)?;
+        }
+        Ok(cid.to_string())
+    }
+
+    async fn download(&self, cid: &str) -> Result<Vec<u8>, StorageError> {
+        let cid = cid.parse::<Cid>()?;
+        Ok(unixfs::read_file(&self.store, &cid)?)
+    }
+
+    async fn exists(&self, cid: &str) -> Result<bool, StorageError> {
+        Ok(self.store.has(&cid.parse::<Cid>()?)?)
+    }
+
+    async fn stat(&self, cid: &str) -> Result<ObjectStat, StorageError> {
+        stat_dag(&self.store, &cid.parse::<Cid>()?)
+    }
+
+    async fn list(&self, cursor: Option<String>, limit: usize) -> Result<ListPage, StorageError> {
+        self.index.lock().unwrap().page(cursor, limit)
+    }
+
+    async fn pin(&self, cid: &str) -> Result<(), StorageError> {
+        let mut index = self.index.lock().unwrap();
+        index.pin(cid.parse::<Cid>()?)?;
+        self.save_index(&index)
+    }
+
+    async fn unpin(&self, cid: &str) -> Result<(), StorageError> {
+        let mut index = self.index.lock().unwrap();
+        index.unpin(&cid.parse::<Cid>()?);
+        self.save_index(&index)
+    }
+
+    async fn delete(&self, cid: &str) -> Result<(), StorageError> {
+        let cid = cid.parse::<Cid>()?;
+        let mut index = self.index.lock().unwrap();
+        index.remove(&cid)?;
+        self.save_index(&index)?;
+        // Drop every block no longer reachable from a remaining root.
+        let live = reachable(&self.store, index.roots())?;
+        for key in self.store.keys()? {
+            if !live.contains(&key) {
+                self.store.delete(&key)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,337 @@
+// src/storage/lotus.rs
+//
+// Typed client for the subset of the Lotus JSON-RPC API (v0) used by
+// `FilecoinStorage`.
+
+use cid::Cid;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{json, Value};
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Errors returned by the Lotus client.
+#[derive(Debug)]
+pub enum LotusError {
+    /// The HTTP request failed or returned a non-success status.
+    Transport(String),
+    /// The node rejected the credentials.
+    Unauthorized,
+    /// The node answered `method` with a JSON-RPC error object.
+    Rpc { method: String, code: i64, message: String },
+    /// The response could not be decoded into the expected type.
+    Decode(String),
+    /// Local file handling around imports/retrievals failed.
+    Io(String),
+    /// No miner offered to serve a retrieval of the root.
+    NoOffers(Cid),
+    /// No wallet was configured and the node has no default wallet to pay
+    /// for retrievals with.
+    NoWallet,
+}
+
+impl fmt::Display for LotusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LotusError::Transport(e) => write!(f, "lotus transport error: {}", e),
+            LotusError::Unauthorized => write!(f, "lotus rejected the auth token"),
+            LotusError::Rpc { method, code, message } => {
+                write!(f, "lotus rpc error {} from {}: {}", code, method, message)
+            }
+            LotusError::Decode(e) => write!(f, "invalid lotus response: {}", e),
+            LotusError::Io(e) => write!(f, "lotus file error: {}", e),
+            LotusError::NoOffers(root) => write!(f, "no retrieval offers for {}", root),
+            LotusError::NoWallet => write!(f, "no wallet configured and the lotus node has no default wallet"),
+        }
+    }
+}
+
+impl std::error::Error for LotusError {}
+
+impl From<std::io::Error> for LotusError {
+    fn from(e: std::io::Error) -> Self {
+        LotusError::Io(e.to_string())
+    }
+}
+
+/// A CID in Lotus' JSON form: `{"/": "bafy..."}`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LotusCid(pub Cid);
+
+impl Serialize for LotusCid {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        json!({ "/": self.0.to_string() }).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for LotusCid {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        struct Raw {
+            #[serde(rename = "/")]
+            cid: String,
+        }
+        let raw = Raw::deserialize(deserializer)?;
+        raw.cid.parse().map(LotusCid).map_err(serde::de::Error::custom)
+    }
+}
+
+/// `api.FileRef`: a file on the node's filesystem.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct FileRef {
+    pub path: String,
+    #[serde(rename = "IsCAR")]
+    pub is_car: bool,
+}
+
+/// `api.ImportRes`, returned by `ClientImport`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ImportRes {
+    pub root: LotusCid,
+    #[serde(rename = "ImportID")]
+    pub import_id: u64,
+}
+
+/// `api.Import`, returned by `ClientListImports`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct Import {
+    pub key: u64,
+    #[serde(default)]
+    pub err: String,
+    pub root: Option<LotusCid>,
+    #[serde(default)]
+    pub source: String,
+    #[serde(default)]
+    pub file_path: String,
+}
+
+/// `api.DataSize`, returned by `ClientDealSize`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct DataSize {
+    pub payload_size: u64,
+    pub piece_size: u64,
+}
+
+/// `storagemarket.DataRef`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct DataRef {
+    pub transfer_type: String,
+    pub root: LotusCid,
+}
+
+/// `api.StartDealParams`, the argument of `ClientStartDeal`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct StartDealParams {
+    pub data: DataRef,
+    pub wallet: String,
+    pub miner: String,
+    /// Price per epoch in attoFIL.
+    pub epoch_price: String,
+    pub min_blocks_duration: u64,
+    pub deal_start_epoch: i64,
+    pub fast_retrieval: bool,
+    pub verified_deal: bool,
+}
+
+/// `api.QueryOffer`, returned by `ClientFindData`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct QueryOffer {
+    #[serde(default)]
+    pub err: String,
+    pub root: LotusCid,
+    pub size: u64,
+    pub min_price: String,
+    pub unseal_price: String,
+    pub payment_interval: u64,
+    pub payment_interval_increase: u64,
+    pub miner: String,
+}
+
+/// `api.RetrievalOrder`, the argument of `ClientRetrieve`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct RetrievalOrder {
+    pub root: LotusCid,
+    pub size: u64,
+    pub total: String,
+    pub unseal_price: String,
+    pub payment_interval: u64,
+    pub payment_interval_increase: u64,
+    pub client: String,
+    pub miner: String,
+}
+
+impl RetrievalOrder {
+    /// Builds an order accepting `offer` on behalf of `client`.
+    pub fn from_offer(offer: &QueryOffer, client: &str) -> Self {
+        RetrievalOrder {
+            root: offer.root,
+            size: offer.size,
+            total: offer.min_price.clone(),
+            unseal_price: offer.unseal_price.clone(),
+            payment_interval: offer.payment_interval,
+            payment_interval_increase: offer.payment_interval_increase,
+            client: client.to_string(),
+            miner: offer.miner.clone(),
+        }
+    }
+}
+
+/// `types.TipSet`, returned by `ChainHead`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct TipSet {
+    pub cids: Vec<LotusCid>,
+    pub height: i64,
+}
+
+/// `types.Actor`, returned by `StateGetActor`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct Actor {
+    pub code: LotusCid,
+    pub head: LotusCid,
+    pub nonce: u64,
+    /// Balance in attoFIL.
+    pub balance: String,
+}
+
+#[derive(Deserialize)]
+struct RpcResponse {
+    #[serde(default)]
+    result: Option<Value>,
+    #[serde(default)]
+    error: Option<RpcError>,
+}
+
+#[derive(Deserialize)]
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+/// Minimal Lotus JSON-RPC client.
+pub struct LotusClient {
+    endpoint: String,
+    token: Option<String>,
+    http: reqwest::Client,
+    next_id: AtomicU64,
+}
+
+impl LotusClient {
+    /// Creates a client for `endpoint` (e.g. `http://127.0.0.1:1234/rpc/v0`),
+    /// sending `token` as a bearer token when given.
+    pub fn new(endpoint: &str, token: Option<String>) -> Self {
+        LotusClient {
+            endpoint: endpoint.to_string(),
+            token,
+            http: reqwest::Client::new(),
+            next_id: AtomicU64::new(1),
+        }
+    }
+
+    pub fn endpoint(&self) -> &str {
+        &self.endpoint
+    }
+
+    /// Calls `Filecoin.<method>` with positional `params`.
+    pub async fn call<T: DeserializeOwned>(&self, method: &str, params: Value) -> Result<T, LotusError> {
+        let body = json!({
+            "jsonrpc": "2.0",
+            "method": format!("Filecoin.{}", method),
+            "params": params,
+            "id": self.next_id.fetch_add(1, Ordering::Relaxed),
+        });
+        let mut request = self.http.post(&self.endpoint).json(&body);
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+        let response = request
+            .send()
+            .await
+            .map_err(|e| LotusError::Transport(e.to_string()))?;
+        let status = response.status();
+        if status == reqwest::StatusCode::UNAUTHORIZED || status == reqwest::StatusCode::FORBIDDEN {
+            return Err(LotusError::Unauthorized);
+        }
+        if !status.is_success() {
+            return Err(LotusError::Transport(format!("http status {}", status)));
+        }
+        let response: RpcResponse = response
+            .json()
+            .await
+            .map_err(|e| LotusError::Decode(e.to_string()))?;
+        if let Some(error) = response.error {
+            return Err(LotusError::Rpc {
+                method: format!("Filecoin.{}", method),
+                code: error.code,
+                message: error.message,
+            });
+        }
+        serde_json::from_value(response.result.unwrap_or(Value::Null))
+            .map_err(|e| LotusError::Decode(e.to_string()))
+    }
+
+    pub async fn client_import(&self, file: &FileRef) -> Result<ImportRes, LotusError> {
+        self.call("ClientImport", json!([file])).await
+    }
+
+    pub async fn client_list_imports(&self) -> Result<Vec<Import>, LotusError> {
+        let imports: Option<Vec<Import>> = self.call("ClientListImports", json!([])).await?;
+        Ok(imports.unwrap_or_default())
+    }
+
+    pub async fn client_remove_import(&self, import_id: u64) -> Result<(), LotusError> {
+        let _: Value = self.call("ClientRemoveImport", json!([import_id])).await?;
+        Ok(())
+    }
+
+    /// Whether the node holds the data for `root` in its local client store.
+    pub async fn client_has_local(&self, root: &Cid) -> Result<bool, LotusError> {
+        self.call("ClientHasLocal", json!([LotusCid(*root)])).await
+    }
+
+    pub async fn client_deal_size(&self, root: &Cid) -> Result<DataSize, LotusError> {
+        self.call("ClientDealSize", json!([LotusCid(*root)])).await
+    }
+
+    /// Proposes a storage deal and returns the proposal CID.
+    pub async fn client_start_deal(&self, params: &StartDealParams) -> Result<Cid, LotusError> {
+        let cid: LotusCid = self.call("ClientStartDeal", json!([params])).await?;
+        Ok(cid.0)
+    }
+
+    pub async fn client_find_data(&self, root: &Cid) -> Result<Vec<QueryOffer>, LotusError> {
+        let offers: Option<Vec<QueryOffer>> = self.call("ClientFindData", json!([LotusCid(*root), null])).await?;
+        Ok(offers.unwrap_or_default())
+    }
+
+    /// Retrieves data described by `order` into `file` on the node's filesystem.
+    pub async fn client_retrieve(&self, order: &RetrievalOrder, file: &FileRef) -> Result<(), LotusError> {
+        let _: Value = self.call("ClientRetrieve", json!([order, file])).await?;
+        Ok(())
+    }
+
+    /// The node's default wallet address, `None` if it has none.
+    pub async fn wallet_default_address(&self) -> Result<Option<String>, LotusError> {
+        let address: Option<String> = self.call("WalletDefaultAddress", json!([])).await?;
+        Ok(address.filter(|a| !a.is_empty()))
+    }
+
+    pub async fn chain_head(&self) -> Result<TipSet, LotusError> {
+        self.call("ChainHead", json!([])).await
+    }
+
+    /// Looks up an actor at the given tipset key (the chain head when empty).
+    pub async fn state_get_actor(&self, address: &str, tipset: &[Cid]) -> Result<Actor, LotusError> {
+        let key: Vec<LotusCid> = tipset.iter().copied().map(LotusCid).collect();
+        self.call("StateGetActor", json!([address, key])).await
+    }
+}
@@ -0,0 +1,170 @@
+use crate::storage::index::{reachable, stat_dag, RootIndex};
+use crate::storage::provider::{ListPage, ObjectStat, StorageError, StorageProvider};
+use crate::unixfs::{self, FixedSizeChunker};
+use async_trait::async_trait;
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+/// Content-addressed provider that keeps blocks in memory.
+///
+/// Uploads are stored as UnixFS DAGs, so the returned CIDs are the same ones
+/// `MyStorage` and Kubo compute for the data.
+#[derive(Debug, Default)]
+pub struct MemoryStorageProvider {
+    blocks: RwLock<HashMap<Cid, Vec<u8>>>,
+    index: RwLock<RootIndex>,
+}
+
+impl MemoryStorageProvider {
+    pub fn new() -> Self {
+        MemoryStorageProvider::default()
+    }
+
+    /// Number of blocks currently held.
+    pub fn block_count(&self) -> usize {
+        self.blocks.read().unwrap().len()
+    }
+}
+
+impl Blockstore for MemoryStorageProvider {
+    fn get(&self, k: &Cid) -> anyhow::Result<Option<Vec<u8>>> {
+        Ok(self.blocks.read().unwrap().get(k).cloned())
+    }
+
+    fn put_keyed(&self, k: &Cid, block: &[u8]) -> anyhow::Result<()> {
+        self.blocks.write().unwrap().insert(*k, block.to_vec());
+        Ok(())
+    }
+
+    fn has(&self, k: &Cid) -> anyhow::Result<bool> {
+        Ok(self.blocks.read().unwrap().contains_key(k))
+    }
+}
+
+#[async_trait]
+impl StorageProvider for MemoryStorageProvider {
+    async fn upload(&self, data: Vec<u8>) -> Result<String, StorageError> {
+        // Hold the index while writing so a concurrent `delete` cannot
+        // collect the new blocks before their root is recorded.
+        let mut index = self.index.write().unwrap();
+        let cid = unixfs::add_bytes(self, &FixedSizeChunker::default(), &data)?;
+        index.add_root(cid);
+        Ok(cid.to_string())
+    }
+
+    async fn download(&self, cid: &str) -> Result<Vec<u8>, StorageError> {
+        let cid = cid.parse::<Cid>()?;
+        Ok(unixfs::read_file(self, &cid)?)
+    }
+
+    async fn exists(&self, cid: &str) -> Result<bool, StorageError> {
+        let cid = cid.parse::<Cid>()?;
+        Ok(self.blocks.read().unwrap().contains_key(&cid))
+    }
+
+    async fn stat(&self, cid: &str) -> Result<ObjectStat, StorageError> {
+        stat_dag(self, &cid.parse::<Cid>()?)
+    }
+
+    async fn list(&self, cursor: Option<String>, limit: usize) -> Result<ListPage, StorageError> {
+        self.index.read().unwrap().page(cursor, limit)
+    }
+
+    async fn pin(&self, cid: &str) -> Result<(), StorageError> {
+        self.index.write().unwrap().pin(cid.parse::<Cid>()?)
+    }
+
+    async fn unpin(&self, cid: &str) -> Result<(), StorageError> {
+        self.index.write().unwrap().unpin(&cid.parse::<Cid>()?);
+        Ok(())
+    }
+
+    async fn delete(&self, cid: &str) -> Result<(), StorageError> {
+        let cid = cid.parse::<Cid>()?;
+        let mut index = self.index.write().unwrap();
+        index.remove(&cid)?;
+        // Drop every block no longer reachable from a remaining root.
+        let live = reachable(self, index.roots())?;
+        self.blocks.write().unwrap().retain(|k, _| live.contains(k));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dao::attribution::DataDAO;
+    use crate::provenance::lineage::ProvenanceManager;
+    use futures::executor::block_on;
+
+    #[test]
+    fn test_upload_download_roundtrip() {
+        let provider = MemoryStorageProvider::new();
+        let cid = block_on(provider.upload(b"hello world".to_vec())).unwrap();
+        assert_eq!(cid, "bafkreifzjut3te2nhyekklss27nh3k72ysco7y32koao5eei66wof36n5e");
+        assert_eq!(block_on(provider.download(&cid)).unwrap(), b"hello world");
+        assert!(block_on(provider.download("bafkreiaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa")).is_err());
+    }
+
+    #[test]
+    fn test_stat_list_pin_delete() {
+        let provider = MemoryStorageProvider::new();
+        let a = block_on(provider.upload(vec![1u8; 600_000])).unwrap();
+        let b = block_on(provider.upload(b"small".to_vec())).unwrap();
+
+        let stat = block_on(provider.stat(&a)).unwrap();
+        assert_eq!((stat.size, stat.codec, stat.links.len()), (600_000, unixfs::DAG_PB, 3));
+
+        let first = block_on(provider.list(None, 1)).unwrap();
+        let second = block_on(provider.list(first.next_cursor.clone(), 1)).unwrap();
+        let mut all = [first.cids, second.cids].concat();
+        all.sort();
+        let mut expected = vec![a.clone(), b.clone()];
+        expected.sort();
+        assert_eq!((all, second.next_cursor), (expected, None));
+
+        block_on(provider.pin(&a)).unwrap();
+        assert!(matches!(block_on(provider.delete(&a)), Err(StorageError::Pinned(_))));
+        block_on(provider.unpin(&a)).unwrap();
+        block_on(provider.delete(&a)).unwrap();
+        assert!(!block_on(provider.exists(&a)).unwrap());
+        assert_eq!(provider.block_count(), 1);
+        assert!(matches!(block_on(provider.download(&a)), Err(StorageError::NotFound(_))));
+    }
+
+    #[test]
+    fn test_delete_does_not_collect_concurrent_uploads() {
+        let provider = MemoryStorageProvider::new();
+        let uploaded = std::thread::scope(|s| {
+            s.spawn(|| {
+                for i in 0..200u32 {
+                    let cid = block_on(provider.upload(format!("scratch {}", i).into_bytes())).unwrap();
+                    block_on(provider.delete(&cid)).unwrap();
+                }
+            });
+            let writer = s.spawn(|| {
+                (0..200u32)
+                    .map(|i| block_on(provider.upload(format!("kept {}", i).into_bytes())).unwrap())
+                    .collect::<Vec<_>>()
+            });
+            writer.join().unwrap()
+        });
+        for (i, cid) in uploaded.iter().enumerate() {
+            assert_eq!(block_on(provider.download(cid)).unwrap(), format!("kept {}", i).into_bytes());
+        }
+    }
+
+    #[test]
+    fn test_provenance_and_dao_end_to_end() {
+        let manager = ProvenanceManager::new(MemoryStorageProvider::new());
+        let cid = block_on(manager.track(b"dataset".to_vec(), "user:1".into(), "alice".into())).unwrap();
+        let lineage = block_on(manager.verify(&cid)).unwrap();
+        assert_eq!((lineage.cid.as_str(), lineage.creator.as_str()), (cid.as_str(), "alice"));
+
+        let dao = DataDAO::new(MemoryStorageProvider::new());
+        let contribution = block_on(dao.contribute(cid, "alice".into())).unwrap();
+        assert!(contribution.starts_with("bafk"));
+    }
+}
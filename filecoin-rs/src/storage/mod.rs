@@ -0,0 +1,19 @@
+// src/storage/mod.rs
+
+#[cfg(not(target_arch = "wasm32"))]
+pub mod filecoin;
+pub mod index;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod local;
+pub mod lotus;
+pub mod memory;
+pub mod provider;
+pub mod replicated;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use filecoin::FilecoinStorage;
+#[cfg(not(target_arch = "wasm32"))]
+pub use local::LocalFsStorageProvider;
+pub use memory::MemoryStorageProvider;
+pub use provider::{ListPage, ObjectStat, StorageError, StorageProvider};
+pub use replicated::{RepairReport, ReplicaHealth, ReplicatedStorage};
@@ -1,7 +1,107 @@
 use async_trait::async_trait;
+use std::fmt;
+
+/// Errors returned by storage providers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StorageError {
+    /// The CID is unknown to the provider.
+    NotFound(String),
+    /// The provider rejected our credentials.
+    Unauthorized,
+    /// A storage or rate limit was exceeded.
+    Quota(String),
+    /// The provider could not be reached or answered unexpectedly.
+    Transport(String),
+    /// Data did not match its CID, or could not be decoded.
+    Integrity(String),
+    /// The operation is not supported by this backend.
+    Unsupported(String),
+    /// The CID is pinned and must be unpinned before it can be deleted.
+    Pinned(String),
+    /// Any other provider-specific failure.
+    Other(String),
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageError::NotFound(cid) => write!(f, "{} not found", cid),
+            StorageError::Unauthorized => write!(f, "unauthorized"),
+            StorageError::Quota(e) => write!(f, "quota exceeded: {}", e),
+            StorageError::Transport(e) => write!(f, "transport error: {}", e),
+            StorageError::Integrity(e) => write!(f, "integrity error: {}", e),
+            StorageError::Unsupported(op) => write!(f, "{} is not supported by this provider", op),
+            StorageError::Pinned(cid) => write!(f, "{} is pinned", cid),
+            StorageError::Other(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for StorageError {}
+
+impl From<anyhow::Error> for StorageError {
+    fn from(e: anyhow::Error) -> Self {
+        use crate::unixfs::DagError;
+        match e.downcast_ref::<DagError>() {
+            Some(DagError::NotFound(cid)) => StorageError::NotFound(cid.to_string()),
+            Some(DagError::Integrity(cid)) => StorageError::Integrity(format!("block {} failed hash verification", cid)),
+            None => StorageError::Other(e.to_string()),
+        }
+    }
+}
+
+impl From<cid::Error> for StorageError {
+    fn from(e: cid::Error) -> Self {
+        StorageError::Other(format!("invalid CID: {}", e))
+    }
+}
+
+/// Metadata about a stored object.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObjectStat {
+    pub cid: String,
+    /// Size of the content in bytes (the file size for UnixFS DAGs).
+    pub size: u64,
+    /// Multicodec of the root block.
+    pub codec: u64,
+    /// CIDs the root block links to.
+    pub links: Vec<String>,
+}
+
+/// One page of `StorageProvider::list` results.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ListPage {
+    pub cids: Vec<String>,
+    /// Cursor to pass to the next `list` call, or `None` on the last page.
+    pub next_cursor: Option<String>,
+}
 
 #[async_trait]
 pub trait StorageProvider {
-    async fn upload(&self, data: Vec<u8>) -> Result<String, String>; // Returns CID
-    async fn download(&self, cid: &str) -> Result<Vec<u8>, String>;
-}
\ No newline at end of file
+    async fn upload(&self, data: Vec<u8>) -> Result<String, StorageError>; // Returns CID
+    async fn download(&self, cid: &str) -> Result<Vec<u8>, StorageError>;
+
+    async fn exists(&self, cid: &str) -> Result<bool, StorageError>;
+
+    async fn stat(&self, cid: &str) -> Result<ObjectStat, StorageError>;
+
+    /// Lists uploaded objects in a stable order, starting after `cursor`.
+    async fn list(&self, _cursor: Option<String>, _limit: usize) -> Result<ListPage, StorageError> {
+        Err(StorageError::Unsupported("list".to_string()))
+    }
+
+    /// Protects an object from deletion and garbage collection.
+    async fn pin(&self, _cid: &str) -> Result<(), StorageError> {
+        Err(StorageError::Unsupported("pin".to_string()))
+    }
+
+    async fn unpin(&self, _cid: &str) -> Result<(), StorageError> {
+        Err(StorageError::Unsupported("unpin".to_string()))
+    }
+
+    /// Removes an object where the backend supports it. Pinned objects must be
+    /// unpinned first.
+    async fn delete(&self, _cid: &str) -> Result<(), StorageError> {
+        Err(StorageError::Unsupported("delete".to_string()))
+    }
+}
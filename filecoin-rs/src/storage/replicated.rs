@@ -0,0 +1,499 @@
+// src/storage/replicated.rs
+//
+// Fans storage operations out over several providers (e.g. Filecoin,
+// Storacha, a local node) and keeps track of where each CID lives.
+
+use crate::storage::provider::{ListPage, ObjectStat, StorageError, StorageProvider};
+use async_trait::async_trait;
+use futures::future::join_all;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::RwLock;
+
+/// Consecutive failures after which a replica is considered unhealthy.
+const UNHEALTHY_AFTER: u32 = 3;
+/// Weight of the newest sample in the latency moving average.
+const LATENCY_ALPHA: f64 = 0.3;
+
+type DynProvider = Box<dyn StorageProvider + Send + Sync>;
+
+struct Replica {
+    name: String,
+    provider: DynProvider,
+}
+
+/// Observed health of one replica.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ReplicaHealth {
+    pub consecutive_failures: u32,
+    /// Moving average of successful call latency, in milliseconds.
+    pub latency_ms: f64,
+}
+
+impl ReplicaHealth {
+    pub fn is_healthy(&self) -> bool {
+        self.consecutive_failures < UNHEALTHY_AFTER
+    }
+}
+
+/// Outcome of repairing a single CID.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RepairReport {
+    pub cid: String,
+    /// Providers the CID was copied to.
+    pub repaired: Vec<String>,
+    /// Providers that still lack the CID, with the reason.
+    pub failed: Vec<(String, String)>,
+}
+
+/// A `StorageProvider` that writes to several providers and reads from the
+/// fastest healthy one that holds the data.
+///
+/// An upload succeeds once `write_quorum` providers have stored the data and
+/// agree on its CID. Placements are recorded so `repair` can restore the
+/// replication level when a provider loses data, including the copies left by
+/// an upload that missed its quorum.
+pub struct ReplicatedStorage {
+    replicas: Vec<Replica>,
+    write_quorum: usize,
+    health: RwLock<Vec<ReplicaHealth>>,
+    placements: RwLock<BTreeMap<String, BTreeSet<usize>>>,
+}
+
+impl ReplicatedStorage {
+    pub fn new(write_quorum: usize) -> Self {
+        ReplicatedStorage {
+            replicas: Vec::new(),
+            write_quorum: write_quorum.max(1),
+            health: RwLock::new(Vec::new()),
+            placements: RwLock::new(BTreeMap::new()),
+        }
+    }
+
+    /// Adds a named provider to the replica set.
+    pub fn with_provider<P>(mut self, name: &str, provider: P) -> Self
+    where
+        P: StorageProvider + Send + Sync + 'static,
+    {
+        self.replicas.push(Replica {
+            name: name.to_string(),
+            provider: Box::new(provider),
+        });
+        self.health.write().unwrap().push(ReplicaHealth::default());
+        self
+    }
+
+    pub fn write_quorum(&self) -> usize {
+        self.write_quorum
+    }
+
+    /// Names of the providers recorded as holding `cid`.
+    pub fn holders(&self, cid: &str) -> Vec<String> {
+        self.placements
+            .read()
+            .unwrap()
+            .get(cid)
+            .map(|set| set.iter().map(|&i| self.replicas[i].name.clone()).collect())
+            .unwrap_or_default()
+    }
+
+    /// Health of each provider, in insertion order.
+    pub fn health(&self) -> Vec<(String, ReplicaHealth)> {
+        let health = self.health.read().unwrap();
+        self.replicas
+            .iter()
+            .zip(health.iter())
+            .map(|(r, h)| (r.name.clone(), h.clone()))
+            .collect()
+    }
+
+    /// Checks every provider for `cid` and copies it to the ones missing it,
+    /// so that all providers hold a replica again.
+    pub async fn repair(&self, cid: &str) -> Result<RepairReport, StorageError> {
+        let present = join_all(self.replicas.iter().map(|r| r.provider.exists(cid))).await;
+        let mut holders = BTreeSet::new();
+        let mut missing = Vec::new();
+        for (i, result) in present.into_iter().enumerate() {
+            match result {
+                Ok(true) => {
+                    holders.insert(i);
+                }
+                Ok(false) | Err(StorageError::NotFound(_)) => missing.push(i),
+                Err(e) => log::warn!("cannot check {} on {}: {}", cid, self.replicas[i].name, e),
+            }
+        }
+        self.set_placement(cid, holders.clone());
+
+        let mut report = RepairReport {
+            cid: cid.to_string(),
+            ..RepairReport::default()
+        };
+        if missing.is_empty() {
+            return Ok(report);
+        }
+        let data = self.read_from(cid, &holders).await?;
+        for i in missing {
+            let name = self.replicas[i].name.clone();
+            match self.replicas[i].provider.upload(data.clone()).await {
+                Ok(copy) if copy == cid => {
+                    self.add_placement(cid, i);
+                    report.repaired.push(name);
+                }
+                Ok(copy) => report.failed.push((name, format!("stored as {}", copy))),
+                Err(e) => report.failed.push((name, e.to_string())),
+            }
+        }
+        Ok(report)
+    }
+
+    /// Repairs every CID with a recorded placement.
+    pub async fn repair_all(&self) -> Vec<Result<RepairReport, StorageError>> {
+        let cids: Vec<String> = self.placements.read().unwrap().keys().cloned().collect();
+        let mut reports = Vec::with_capacity(cids.len());
+        for cid in cids {
+            reports.push(self.repair(&cid).await);
+        }
+        reports
+    }
+
+    /// Replica indices to read `cid` from: recorded holders first, healthy
+    /// before unhealthy, then by latency.
+    fn read_order(&self, holders: &BTreeSet<usize>) -> Vec<usize> {
+        let health = self.health.read().unwrap();
+        let mut order: Vec<usize> = (0..self.replicas.len()).collect();
+        order.sort_by(|&a, &b| {
+            let key = |i: usize| (!holders.contains(&i), !health[i].is_healthy());
+            key(a)
+                .cmp(&key(b))
+                .then(health[a].latency_ms.total_cmp(&health[b].latency_ms))
+        });
+        order
+    }
+
+    async fn read_from(&self, cid: &str, holders: &BTreeSet<usize>) -> Result<Vec<u8>, StorageError> {
+        let mut last_error = StorageError::NotFound(cid.to_string());
+        for i in self.read_order(holders) {
+            let started = clock::now();
+            match self.replicas[i].provider.download(cid).await {
+                Ok(data) => {
+                    self.record_success(i, clock::elapsed_ms(started));
+                    return Ok(data);
+                }
+                Err(e) => {
+                    log::warn!("download of {} from {} failed: {}", cid, self.replicas[i].name, e);
+                    if !matches!(e, StorageError::NotFound(_)) {
+                        self.record_failure(i);
+                    }
+                    last_error = e;
+                }
+            }
+        }
+        Err(last_error)
+    }
+
+    fn recorded_holders(&self, cid: &str) -> BTreeSet<usize> {
+        self.placements.read().unwrap().get(cid).cloned().unwrap_or_default()
+    }
+
+    fn set_placement(&self, cid: &str, holders: BTreeSet<usize>) {
+        self.placements.write().unwrap().insert(cid.to_string(), holders);
+    }
+
+    fn add_placement(&self, cid: &str, replica: usize) {
+        self.placements
+            .write()
+            .unwrap()
+            .entry(cid.to_string())
+            .or_default()
+            .insert(replica);
+    }
+
+    /// Records a successful call that took `elapsed_ms`, if it was measured.
+    fn record_success(&self, replica: usize, elapsed_ms: Option<f64>) {
+        let mut health = self.health.write().unwrap();
+        let entry = &mut health[replica];
+        entry.consecutive_failures = 0;
+        if let Some(elapsed) = elapsed_ms {
+            entry.latency_ms = if entry.latency_ms == 0.0 {
+                elapsed
+            } else {
+                LATENCY_ALPHA * elapsed + (1.0 - LATENCY_ALPHA) * entry.latency_ms
+            };
+        }
+    }
+
+    fn record_failure(&self, replica: usize) {
+        self.health.write().unwrap()[replica].consecutive_failures += 1;
+    }
+}
+
+#[async_trait]
+impl StorageProvider for ReplicatedStorage {
+    async fn upload(&self, data: Vec<u8>) -> Result<String, StorageError> {
+        let results = join_all(self.replicas.iter().map(|r| {
+            let data = data.clone();
+            async move {
+                let started = clock::now();
+                let result = r.provider.upload(data).await;
+                (result, clock::elapsed_ms(started))
+            }
+        }))
+        .await;
+
+        // Group the successful replicas by the CID they reported.
+        let mut by_cid: BTreeMap<String, BTreeSet<usize>> = BTreeMap::new();
+        let mut errors = Vec::new();
+        for (i, (result, elapsed)) in results.into_iter().enumerate() {
+            match result {
+                Ok(cid) => {
+                    self.record_success(i, elapsed);
+                    by_cid.entry(cid).or_default().insert(i);
+                }
+                Err(e) => {
+                    self.record_failure(i);
+                    errors.push(format!("{}: {}", self.replicas[i].name, e));
+                }
+            }
+        }
+        let Some((cid, holders)) = by_cid
+            .iter()
+            .max_by_key(|(_, holders)| holders.len())
+            .map(|(cid, holders)| (cid.clone(), holders.clone()))
+            .filter(|(_, holders)| holders.len() >= self.write_quorum)
+        else {
+            // Record the copies that were written so `repair_all` can bring
+            // them up to the replication level, and name them in the error.
+            let mut stored = Vec::new();
+            for (cid, holders) in by_cid {
+                let names: Vec<&str> = holders.iter().map(|&i| self.replicas[i].name.as_str()).collect();
+                stored.push(format!("{} on {}", cid, names.join(", ")));
+                self.set_placement(&cid, holders);
+            }
+            return Err(StorageError::Transport(format!(
+                "write quorum of {} not met; stored {}: {}",
+                self.write_quorum,
+                if stored.is_empty() { "nowhere".to_string() } else { stored.join(", ") },
+                errors.join("; ")
+            )));
+        };
+        self.set_placement(&cid, holders);
+        Ok(cid)
+    }
+
+    async fn download(&self, cid: &str) -> Result<Vec<u8>, StorageError> {
+        self.read_from(cid, &self.recorded_holders(cid)).await
+    }
+
+    async fn exists(&self, cid: &str) -> Result<bool, StorageError> {
+        // Only report absence if some replica actually answered; if every
+        // one failed, surface the last error instead.
+        let mut answered = false;
+        let mut last_error = None;
+        for i in self.read_order(&self.recorded_holders(cid)) {
+            match self.replicas[i].provider.exists(cid).await {
+                Ok(true) => return Ok(true),
+                Ok(false) | Err(StorageError::NotFound(_)) => answered = true,
+                Err(e) => last_error = Some(e),
+            }
+        }
+        match last_error {
+            Some(e) if !answered => Err(e),
+            _ => Ok(false),
+        }
+    }
+
+    async fn stat(&self, cid: &str) -> Result<ObjectStat, StorageError> {
+        let mut last_error = StorageError::NotFound(cid.to_string());
+        for i in self.read_order(&self.recorded_holders(cid)) {
+            match self.replicas[i].provider.stat(cid).await {
+                Ok(stat) => return Ok(stat),
+                Err(e) => last_error = e,
+            }
+        }
+        Err(last_error)
+    }
+
+    /// Lists the CIDs uploaded through this wrapper.
+    async fn list(&self, cursor: Option<String>, limit: usize) -> Result<ListPage, StorageError> {
+        let placements = self.placements.read().unwrap();
+        let mut iter = placements
+            .keys()
+            .filter(|cid| cursor.as_ref().is_none_or(|c| *cid > c));
+        let cids: Vec<String> = iter.by_ref().take(limit).cloned().collect();
+        let next_cursor = match (cids.last(), iter.next()) {
+            (Some(last), Some(_)) => Some(last.clone()),
+            _ => None,
+        };
+        Ok(ListPage { cids, next_cursor })
+    }
+
+    async fn pin(&self, cid: &str) -> Result<(), StorageError> {
+        self.on_holders(cid, |p| p.pin(cid)).await
+    }
+
+    async fn unpin(&self, cid: &str) -> Result<(), StorageError> {
+        self.on_holders(cid, |p| p.unpin(cid)).await
+    }
+
+    async fn delete(&self, cid: &str) -> Result<(), StorageError> {
+        self.on_holders(cid, |p| p.delete(cid)).await?;
+        self.placements.write().unwrap().remove(cid);
+        Ok(())
+    }
+}
+
+impl ReplicatedStorage {
+    /// Runs `op` on every provider recorded as holding `cid`; fails if any of
+    /// them fails for a reason other than not having the data.
+    async fn on_holders<'a, F>(&'a self, cid: &'a str, op: F) -> Result<(), StorageError>
+    where
+        F: Fn(&'a DynProvider) -> futures::future::BoxFuture<'a, Result<(), StorageError>>,
+    {
+        let holders = self.recorded_holders(cid);
+        if holders.is_empty() {
+            return Err(StorageError::NotFound(cid.to_string()));
+        }
+        let results = join_all(holders.iter().map(|&i| op(&self.replicas[i].provider))).await;
+        for (i, result) in holders.iter().zip(results) {
+            match result {
+                Ok(()) | Err(StorageError::NotFound(_)) => {}
+                Err(e) => {
+                    return Err(StorageError::Other(format!("{}: {}", self.replicas[*i].name, e)));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Wall-clock measurement for latency tracking; `Instant` is unavailable on
+/// wasm32-unknown-unknown, where latency ranking is skipped.
+mod clock {
+    #[cfg(not(target_arch = "wasm32"))]
+    pub use std::time::Instant;
+    #[cfg(target_arch = "wasm32")]
+    pub type Instant = ();
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn now() -> Option<Instant> {
+        Some(Instant::now())
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    pub fn now() -> Option<Instant> {
+        None
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn elapsed_ms(started: Option<Instant>) -> Option<f64> {
+        started.map(|s| s.elapsed().as_secs_f64() * 1000.0)
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    pub fn elapsed_ms(_started: Option<Instant>) -> Option<f64> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorageProvider;
+    use futures::executor::block_on;
+    use std::sync::Arc;
+
+    /// Provider whose every call fails, standing in for an unreachable node.
+    struct Offline;
+
+    #[async_trait]
+    impl StorageProvider for Offline {
+        async fn upload(&self, _data: Vec<u8>) -> Result<String, StorageError> {
+            Err(StorageError::Transport("offline".into()))
+        }
+        async fn download(&self, _cid: &str) -> Result<Vec<u8>, StorageError> {
+            Err(StorageError::Transport("offline".into()))
+        }
+        async fn exists(&self, _cid: &str) -> Result<bool, StorageError> {
+            Err(StorageError::Transport("offline".into()))
+        }
+        async fn stat(&self, _cid: &str) -> Result<ObjectStat, StorageError> {
+            Err(StorageError::Transport("offline".into()))
+        }
+    }
+
+    /// Shares one in-memory provider between the test and the wrapper.
+    struct Shared(Arc<MemoryStorageProvider>);
+
+    #[async_trait]
+    impl StorageProvider for Shared {
+        async fn upload(&self, data: Vec<u8>) -> Result<String, StorageError> {
+            self.0.upload(data).await
+        }
+        async fn download(&self, cid: &str) -> Result<Vec<u8>, StorageError> {
+            self.0.download(cid).await
+        }
+        async fn exists(&self, cid: &str) -> Result<bool, StorageError> {
+            self.0.exists(cid).await
+        }
+        async fn stat(&self, cid: &str) -> Result<ObjectStat, StorageError> {
+            self.0.stat(cid).await
+        }
+        async fn delete(&self, cid: &str) -> Result<(), StorageError> {
+            self.0.delete(cid).await
+        }
+    }
+
+    #[test]
+    fn test_quorum_fallback_and_repair() {
+        let local = Arc::new(MemoryStorageProvider::new());
+        let storage = ReplicatedStorage::new(2)
+            .with_provider("offline", Offline)
+            .with_provider("local", Shared(local.clone()))
+            .with_provider("backup", MemoryStorageProvider::new());
+
+        let cid = block_on(storage.upload(b"replicate me".to_vec())).unwrap();
+        assert_eq!(storage.holders(&cid), vec!["local", "backup"]);
+        assert_eq!(storage.health()[0].1.consecutive_failures, 1);
+
+        // Losing the local copy falls back to the backup, and repair restores it.
+        block_on(local.delete(&cid)).unwrap();
+        assert_eq!(block_on(storage.download(&cid)).unwrap(), b"replicate me");
+        let report = block_on(storage.repair(&cid)).unwrap();
+        assert_eq!(report.repaired, vec!["local"]);
+        assert_eq!(report.failed.len(), 0);
+        assert!(block_on(local.exists(&cid)).unwrap());
+    }
+
+    #[test]
+    fn test_exists_reports_unreachable_replicas() {
+        let cid = block_on(MemoryStorageProvider::new().upload(vec![4, 5, 6])).unwrap();
+        let storage = ReplicatedStorage::new(1)
+            .with_provider("offline", Offline)
+            .with_provider("local", MemoryStorageProvider::new());
+        // `local` answers definitively, so the offline replica is not an error.
+        assert!(!block_on(storage.exists(&cid)).unwrap());
+
+        let unreachable = ReplicatedStorage::new(1).with_provider("offline", Offline);
+        assert!(matches!(
+            block_on(unreachable.exists(&cid)),
+            Err(StorageError::Transport(_))
+        ));
+    }
+
+    #[test]
+    fn test_failed_quorum_keeps_written_copies_for_repair() {
+        let strict = ReplicatedStorage::new(3)
+            .with_provider("offline", Offline)
+            .with_provider("local", MemoryStorageProvider::new());
+        let cid = block_on(MemoryStorageProvider::new().upload(vec![1, 2, 3])).unwrap();
+        match block_on(strict.upload(vec![1, 2, 3])) {
+            Err(StorageError::Transport(msg)) => assert!(msg.contains(&format!("{} on local", cid)), "{}", msg),
+            other => panic!("expected a quorum error, got {:?}", other),
+        }
+
+        // The copy on `local` is tracked, so `repair_all` revisits it.
+        assert_eq!(strict.holders(&cid), vec!["local"]);
+        let reports = block_on(strict.repair_all());
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].as_ref().unwrap().cid, cid);
+    }
+}
@@ -0,0 +1,47 @@
+// src/token.rs
+//
+// Metadata of the fungible token an `ActorState` keeps balances in, modelled
+// on FRC-46. Supply and the minter set live on the state itself; this is the
+// part fixed when the token is created.
+
+use fvm_ipld_encoding::tuple::*;
+
+/// FRC-46 tokens have 18 decimal places.
+pub const DEFAULT_DECIMALS: u8 = 18;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize_tuple, Deserialize_tuple)]
+pub struct TokenInfo {
+    pub name: String,
+    pub symbol: String,
+    /// Decimal places of the display unit; balances are in base units.
+    pub decimals: u8,
+    /// Most base units that may ever be in circulation, if capped.
+    pub max_supply: Option<u64>,
+}
+
+impl TokenInfo {
+    pub fn new(name: &str, symbol: &str) -> Self {
+        TokenInfo {
+            name: name.to_string(),
+            symbol: symbol.to_string(),
+            decimals: DEFAULT_DECIMALS,
+            max_supply: None,
+        }
+    }
+
+    pub fn decimals(mut self, decimals: u8) -> Self {
+        self.decimals = decimals;
+        self
+    }
+
+    pub fn max_supply(mut self, max_supply: u64) -> Self {
+        self.max_supply = Some(max_supply);
+        self
+    }
+}
+
+impl Default for TokenInfo {
+    fn default() -> Self {
+        TokenInfo::new("", "")
+    }
+}
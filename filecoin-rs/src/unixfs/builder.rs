@@ -0,0 +1,169 @@
+// src/unixfs/builder.rs
+
+use super::pb::{PbLink, PbNode, UnixFsData, UNIXFS_FILE};
+use super::{Chunker, DAG_PB, DEFAULT_MAX_LINKS, RAW};
+use anyhow::Result;
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+use multihash_codetable::{Code, MultihashDigest};
+
+#[derive(Debug, Clone)]
+struct Link {
+    cid: Cid,
+    /// Cumulative size of the linked block and everything below it.
+    tsize: u64,
+    /// Number of file bytes reachable through the link.
+    filesize: u64,
+}
+
+/// Builds a balanced UnixFS file DAG from leaves pushed in order.
+///
+/// Leaves are stored as raw blocks and internal nodes as DAG-PB with at most
+/// `max_links` children, matching `ipfs add --cid-version=1` (raw leaves,
+/// balanced layout). Only one partially filled node per tree level is kept in
+/// memory, so arbitrarily large files can be built incrementally.
+pub struct DagBuilder<'a, B: Blockstore + ?Sized> {
+    store: &'a B,
+    max_links: usize,
+    levels: Vec<Vec<Link>>,
+    size: u64,
+}
+
+impl<'a, B: Blockstore + ?Sized> DagBuilder<'a, B> {
+    pub fn new(store: &'a B) -> Self {
+        Self::with_max_links(store, DEFAULT_MAX_LINKS)
+    }
+
+    pub fn with_max_links(store: &'a B, max_links: usize) -> Self {
+        assert!(max_links > 1, "a DAG node needs room for at least two links");
+        DagBuilder {
+            store,
+            max_links,
+            levels: Vec::new(),
+            size: 0,
+        }
+    }
+
+    /// Number of file bytes pushed so far.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Stores `chunk` as a raw leaf and appends it to the file.
+    pub fn push_leaf(&mut self, chunk: &[u8]) -> Result<Cid> {
+        let cid = put_block(self.store, RAW, chunk)?;
+        let len = chunk.len() as u64;
+        self.size += len;
+        self.push_link(0, Link { cid, tsize: len, filesize: len })?;
+        Ok(cid)
+    }
+
+    /// Flushes the remaining levels and returns the root CID of the file.
+    pub fn finish(mut self) -> Result<Cid> {
+        if self.levels.is_empty() {
+            // An empty file is a single empty raw leaf, as in Kubo.
+            self.push_leaf(&[])?;
+        }
+        let mut depth = 0;
+        loop {
+            let is_top = depth + 1 == self.levels.len();
+            if is_top && self.levels[depth].len() == 1 {
+                return Ok(self.levels[depth][0].cid);
+            }
+            let links = std::mem::take(&mut self.levels[depth]);
+            let node = self.write_node(links)?;
+            self.push_link(depth + 1, node)?;
+            depth += 1;
+        }
+    }
+
+    fn push_link(&mut self, depth: usize, link: Link) -> Result<()> {
+        if self.levels.len() <= depth {
+            self.levels.push(Vec::new());
+        }
+        if self.levels[depth].len() == self.max_links {
+            let full = std::mem::take(&mut self.levels[depth]);
+            let node = self.write_node(full)?;
+            self.push_link(depth + 1, node)?;
+        }
+        self.levels[depth].push(link);
+        Ok(())
+    }
+
+    fn write_node(&self, links: Vec<Link>) -> Result<Link> {
+        let filesize = links.iter().map(|l| l.filesize).sum();
+        let data = UnixFsData {
+            data_type: UNIXFS_FILE,
+            data: None,
+            filesize: Some(filesize),
+            blocksizes: links.iter().map(|l| l.filesize).collect(),
+        };
+        let node = PbNode {
+            links: links
+                .iter()
+                .map(|l| PbLink { cid: l.cid, name: String::new(), tsize: l.tsize })
+                .collect(),
+            data: Some(data.encode()),
+        };
+        let bytes = node.encode();
+        let cid = put_block(self.store, DAG_PB, &bytes)?;
+        let tsize = bytes.len() as u64 + links.iter().map(|l| l.tsize).sum::<u64>();
+        Ok(Link { cid, tsize, filesize })
+    }
+}
+
+/// Chunks data written in arbitrary pieces and feeds it to a `DagBuilder`.
+///
+/// At most `chunker.max_size()` bytes are buffered, which keeps memory bounded
+/// when importing from a reader or stream.
+pub struct FileImporter<'a, B: Blockstore + ?Sized, C: Chunker + ?Sized> {
+    builder: DagBuilder<'a, B>,
+    chunker: &'a C,
+    buf: Vec<u8>,
+}
+
+impl<'a, B: Blockstore + ?Sized, C: Chunker + ?Sized> FileImporter<'a, B, C> {
+    pub fn new(store: &'a B, chunker: &'a C) -> Self {
+        FileImporter {
+            builder: DagBuilder::new(store),
+            chunker,
+            buf: Vec::with_capacity(chunker.max_size()),
+        }
+    }
+
+    /// Number of file bytes stored so far (excluding buffered bytes).
+    pub fn stored(&self) -> u64 {
+        self.builder.size()
+    }
+
+    /// Appends `data` to the file, storing every chunk that is complete.
+    pub fn write(&mut self, mut data: &[u8]) -> Result<()> {
+        let max = self.chunker.max_size();
+        while !data.is_empty() {
+            let take = (max - self.buf.len()).min(data.len());
+            self.buf.extend_from_slice(&data[..take]);
+            data = &data[take..];
+            if self.buf.len() == max {
+                let len = self.chunker.cut(&self.buf, false).unwrap_or(max);
+                self.builder.push_leaf(&self.buf[..len])?;
+                self.buf.drain(..len);
+            }
+        }
+        Ok(())
+    }
+
+    /// Stores the buffered tail and returns the root CID of the file.
+    pub fn finish(mut self) -> Result<Cid> {
+        while let Some(len) = self.chunker.cut(&self.buf, true) {
+            self.builder.push_leaf(&self.buf[..len])?;
+            self.buf.drain(..len);
+        }
+        self.builder.finish()
+    }
+}
+
+fn put_block<B: Blockstore + ?Sized>(store: &B, codec: u64, bytes: &[u8]) -> Result<Cid> {
+    let cid = Cid::new_v1(codec, Code::Sha2_256.digest(bytes));
+    store.put_keyed(&cid, bytes)?;
+    Ok(cid)
+}
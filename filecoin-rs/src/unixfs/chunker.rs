@@ -0,0 +1,133 @@
+// src/unixfs/chunker.rs
+
+/// Default chunk size used by Kubo and the JS importer (256 KiB).
+pub const DEFAULT_CHUNK_SIZE: usize = 256 * 1024;
+
+/// Splits a byte stream into chunks that become the leaves of a file DAG.
+///
+/// Chunkers are stateless between chunks: `cut` only looks at the bytes at the
+/// start of `buf`, so the same chunker works on a whole buffer or on a stream
+/// that is buffered up to `max_size` bytes at a time.
+pub trait Chunker {
+    /// The largest chunk this chunker will ever produce.
+    fn max_size(&self) -> usize;
+
+    /// Returns the length of the next chunk at the start of `buf`, or `None`
+    /// if more input is needed to decide. When `eof` is set the remaining
+    /// bytes always form a final chunk.
+    fn cut(&self, buf: &[u8], eof: bool) -> Option<usize>;
+
+    /// Splits a complete buffer into chunks.
+    fn split<'a>(&self, mut data: &'a [u8]) -> Vec<&'a [u8]> {
+        let mut chunks = Vec::new();
+        while !data.is_empty() {
+            let len = self.cut(data, true).unwrap_or(data.len());
+            let (chunk, rest) = data.split_at(len);
+            chunks.push(chunk);
+            data = rest;
+        }
+        chunks
+    }
+}
+
+/// Cuts the input into chunks of a fixed size (Kubo's `size-N` chunker).
+#[derive(Debug, Clone, Copy)]
+pub struct FixedSizeChunker {
+    size: usize,
+}
+
+impl FixedSizeChunker {
+    pub fn new(size: usize) -> Self {
+        assert!(size > 0, "chunk size must be positive");
+        FixedSizeChunker { size }
+    }
+}
+
+impl Default for FixedSizeChunker {
+    fn default() -> Self {
+        FixedSizeChunker::new(DEFAULT_CHUNK_SIZE)
+    }
+}
+
+impl Chunker for FixedSizeChunker {
+    fn max_size(&self) -> usize {
+        self.size
+    }
+
+    fn cut(&self, buf: &[u8], eof: bool) -> Option<usize> {
+        if buf.len() >= self.size {
+            Some(self.size)
+        } else if eof && !buf.is_empty() {
+            Some(buf.len())
+        } else {
+            None
+        }
+    }
+}
+
+const RABIN_WINDOW: usize = 48;
+const RABIN_PRIME: u64 = 0x003D_A335_8B4D_C173;
+
+/// Content-defined chunker using a Rabin-Karp rolling fingerprint.
+///
+/// A boundary is placed where the fingerprint of the last `RABIN_WINDOW` bytes
+/// matches a mask derived from the average size, so inserting bytes near the
+/// start of a file only changes the chunks around the edit.
+#[derive(Debug, Clone, Copy)]
+pub struct RabinChunker {
+    min: usize,
+    max: usize,
+    mask: u64,
+    out_factor: u64,
+}
+
+impl RabinChunker {
+    /// Creates a chunker with explicit minimum, average and maximum sizes.
+    pub fn new(min: usize, avg: usize, max: usize) -> Self {
+        assert!(min > 0 && min <= avg && avg <= max, "invalid rabin chunk sizes");
+        let bits = usize::BITS - 1 - avg.leading_zeros();
+        let mask = (1u64 << bits) - 1;
+        let out_factor = (0..RABIN_WINDOW).fold(1u64, |acc, _| acc.wrapping_mul(RABIN_PRIME));
+        RabinChunker { min, max, mask, out_factor }
+    }
+
+    /// Creates a chunker around an average size, using the same min/max
+    /// ratios as Kubo's `rabin-[avg]` option.
+    pub fn with_avg(avg: usize) -> Self {
+        RabinChunker::new(avg / 3, avg, avg + avg / 2)
+    }
+}
+
+impl Default for RabinChunker {
+    fn default() -> Self {
+        RabinChunker::with_avg(DEFAULT_CHUNK_SIZE)
+    }
+}
+
+impl Chunker for RabinChunker {
+    fn max_size(&self) -> usize {
+        self.max
+    }
+
+    fn cut(&self, buf: &[u8], eof: bool) -> Option<usize> {
+        let limit = buf.len().min(self.max);
+        let mut hash = 0u64;
+        for i in 0..limit {
+            hash = hash.wrapping_mul(RABIN_PRIME).wrapping_add(u64::from(buf[i]) + 1);
+            if i >= RABIN_WINDOW {
+                let out = u64::from(buf[i - RABIN_WINDOW]) + 1;
+                hash = hash.wrapping_sub(out.wrapping_mul(self.out_factor));
+            }
+            if i + 1 >= self.min && hash & self.mask == self.mask {
+                return Some(i + 1);
+            }
+        }
+        if limit == self.max {
+            Some(self.max)
+        } else if eof && !buf.is_empty() {
+            Some(buf.len())
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,195 @@
+// src/unixfs/pb.rs
+//
+// Minimal protobuf codec for the DAG-PB (PBNode/PBLink) and UnixFS `Data`
+// messages. Only the fields needed for files are supported.
+
+use anyhow::{anyhow, bail, Result};
+use cid::Cid;
+
+/// UnixFS `Data.DataType` for regular files.
+pub const UNIXFS_FILE: u64 = 2;
+/// UnixFS `Data.DataType` for raw leaves wrapped in DAG-PB (CIDv0 DAGs).
+pub const UNIXFS_RAW: u64 = 0;
+
+/// A link inside a DAG-PB node.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PbLink {
+    pub cid: Cid,
+    pub name: String,
+    pub tsize: u64,
+}
+
+/// A decoded DAG-PB node.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PbNode {
+    pub links: Vec<PbLink>,
+    pub data: Option<Vec<u8>>,
+}
+
+/// The UnixFS `Data` message stored in a DAG-PB node's data field.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UnixFsData {
+    pub data_type: u64,
+    pub data: Option<Vec<u8>>,
+    pub filesize: Option<u64>,
+    pub blocksizes: Vec<u64>,
+}
+
+impl PbNode {
+    /// Encodes the node in canonical DAG-PB form (links before data).
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        for link in &self.links {
+            let mut buf = Vec::new();
+            write_bytes(&mut buf, 1, &link.cid.to_bytes());
+            write_bytes(&mut buf, 2, link.name.as_bytes());
+            write_varint_field(&mut buf, 3, link.tsize);
+            write_bytes(&mut out, 2, &buf);
+        }
+        if let Some(data) = &self.data {
+            write_bytes(&mut out, 1, data);
+        }
+        out
+    }
+
+    pub fn decode(mut buf: &[u8]) -> Result<Self> {
+        let mut node = PbNode::default();
+        while !buf.is_empty() {
+            let (field, wire) = read_key(&mut buf)?;
+            match (field, wire) {
+                (1, 2) => node.data = Some(read_bytes(&mut buf)?.to_vec()),
+                (2, 2) => node.links.push(decode_link(read_bytes(&mut buf)?)?),
+                _ => skip_field(&mut buf, wire)?,
+            }
+        }
+        Ok(node)
+    }
+}
+
+fn decode_link(mut buf: &[u8]) -> Result<PbLink> {
+    let mut cid = None;
+    let mut name = String::new();
+    let mut tsize = 0;
+    while !buf.is_empty() {
+        let (field, wire) = read_key(&mut buf)?;
+        match (field, wire) {
+            (1, 2) => cid = Some(Cid::try_from(read_bytes(&mut buf)?)?),
+            (2, 2) => name = String::from_utf8(read_bytes(&mut buf)?.to_vec())?,
+            (3, 0) => tsize = read_varint(&mut buf)?,
+            _ => skip_field(&mut buf, wire)?,
+        }
+    }
+    Ok(PbLink {
+        cid: cid.ok_or_else(|| anyhow!("dag-pb link without hash"))?,
+        name,
+        tsize,
+    })
+}
+
+impl UnixFsData {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        write_varint_field(&mut out, 1, self.data_type);
+        if let Some(data) = &self.data {
+            write_bytes(&mut out, 2, data);
+        }
+        if let Some(filesize) = self.filesize {
+            write_varint_field(&mut out, 3, filesize);
+        }
+        for size in &self.blocksizes {
+            write_varint_field(&mut out, 4, *size);
+        }
+        out
+    }
+
+    pub fn decode(mut buf: &[u8]) -> Result<Self> {
+        let mut msg = UnixFsData::default();
+        while !buf.is_empty() {
+            let (field, wire) = read_key(&mut buf)?;
+            match (field, wire) {
+                (1, 0) => msg.data_type = read_varint(&mut buf)?,
+                (2, 2) => msg.data = Some(read_bytes(&mut buf)?.to_vec()),
+                (3, 0) => msg.filesize = Some(read_varint(&mut buf)?),
+                (4, 0) => msg.blocksizes.push(read_varint(&mut buf)?),
+                (4, 2) => {
+                    // Tolerate packed encoding from other implementations.
+                    let mut packed = read_bytes(&mut buf)?;
+                    while !packed.is_empty() {
+                        msg.blocksizes.push(read_varint(&mut packed)?);
+                    }
+                }
+                _ => skip_field(&mut buf, wire)?,
+            }
+        }
+        Ok(msg)
+    }
+}
+
+/// Appends an unsigned LEB128 varint.
+pub fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+/// Reads an unsigned LEB128 varint, advancing `buf`.
+pub fn read_varint(buf: &mut &[u8]) -> Result<u64> {
+    let mut value = 0u64;
+    for i in 0..10 {
+        let byte = *buf.first().ok_or_else(|| anyhow!("truncated varint"))?;
+        *buf = &buf[1..];
+        value |= u64::from(byte & 0x7f) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    bail!("varint overflow")
+}
+
+fn write_varint_field(out: &mut Vec<u8>, field: u64, value: u64) {
+    write_varint(out, field << 3);
+    write_varint(out, value);
+}
+
+fn write_bytes(out: &mut Vec<u8>, field: u64, bytes: &[u8]) {
+    write_varint(out, (field << 3) | 2);
+    write_varint(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+fn read_key(buf: &mut &[u8]) -> Result<(u64, u64)> {
+    let key = read_varint(buf)?;
+    Ok((key >> 3, key & 7))
+}
+
+fn read_bytes<'a>(buf: &mut &'a [u8]) -> Result<&'a [u8]> {
+    let len = read_varint(buf)? as usize;
+    if buf.len() < len {
+        bail!("truncated protobuf field");
+    }
+    let (bytes, rest) = buf.split_at(len);
+    *buf = rest;
+    Ok(bytes)
+}
+
+fn skip_field(buf: &mut &[u8], wire: u64) -> Result<()> {
+    match wire {
+        0 => {
+            read_varint(buf)?;
+        }
+        1 | 5 => {
+            let len = if wire == 1 { 8 } else { 4 };
+            if buf.len() < len {
+                bail!("truncated protobuf field");
+            }
+            *buf = &buf[len..];
+        }
+        2 => {
+            read_bytes(buf)?;
+        }
+        _ => bail!("unsupported protobuf wire type {}", wire),
+    }
+    Ok(())
+}
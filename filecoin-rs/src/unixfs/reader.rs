@@ -0,0 +1,141 @@
+// src/unixfs/reader.rs
+
+use super::pb::{PbNode, UnixFsData};
+use super::{DAG_PB, RAW};
+use anyhow::{bail, Result};
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+use multihash_codetable::{Code, MultihashDigest};
+use std::fmt;
+
+/// Typed failures when reading a DAG, recoverable via `anyhow::Error::downcast_ref`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DagError {
+    /// A block referenced by the DAG is missing from the store.
+    NotFound(Cid),
+    /// A block's bytes do not hash to its CID.
+    Integrity(Cid),
+}
+
+impl fmt::Display for DagError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DagError::NotFound(cid) => write!(f, "block {} not found", cid),
+            DagError::Integrity(cid) => write!(f, "block {} failed hash verification", cid),
+        }
+    }
+}
+
+impl std::error::Error for DagError {}
+
+/// Iterator over the content of a file DAG, yielding one piece per block.
+///
+/// The DAG is walked depth first; only the pending links of the current path
+/// are held in memory, and every block is checked against its CID.
+pub struct FileChunks<'a, B: Blockstore + ?Sized> {
+    store: &'a B,
+    stack: Vec<Cid>,
+}
+
+impl<'a, B: Blockstore + ?Sized> FileChunks<'a, B> {
+    pub fn new(store: &'a B, root: &Cid) -> Self {
+        FileChunks {
+            store,
+            stack: vec![*root],
+        }
+    }
+
+    fn next_chunk(&mut self) -> Result<Option<Vec<u8>>> {
+        while let Some(cid) = self.stack.pop() {
+            let block = load_block(self.store, &cid)?;
+            match cid.codec() {
+                RAW => return Ok(Some(block)),
+                DAG_PB => {
+                    let node = PbNode::decode(&block)?;
+                    self.stack.extend(node.links.iter().rev().map(|l| l.cid));
+                    if let Some(data) = &node.data {
+                        if let Some(bytes) = UnixFsData::decode(data)?.data {
+                            if !bytes.is_empty() {
+                                return Ok(Some(bytes));
+                            }
+                        }
+                    }
+                }
+                codec => bail!("unsupported codec 0x{:x} in file DAG", codec),
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl<'a, B: Blockstore + ?Sized> Iterator for FileChunks<'a, B> {
+    type Item = Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.next_chunk() {
+            Ok(chunk) => chunk.map(Ok),
+            Err(e) => {
+                // Stop iterating after the first error.
+                self.stack.clear();
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// Walks the file DAG under `root` and passes each piece of file content to
+/// `f` in order.
+pub fn walk_file<B, F>(store: &B, root: &Cid, mut f: F) -> Result<()>
+where
+    B: Blockstore + ?Sized,
+    F: FnMut(&[u8]) -> Result<()>,
+{
+    for chunk in FileChunks::new(store, root) {
+        f(&chunk?)?;
+    }
+    Ok(())
+}
+
+/// Reassembles the whole file stored under `root`.
+pub fn read_file<B: Blockstore + ?Sized>(store: &B, root: &Cid) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    walk_file(store, root, |chunk| {
+        out.extend_from_slice(chunk);
+        Ok(())
+    })?;
+    Ok(out)
+}
+
+/// Returns the size in bytes of the file stored under `root`, reading only
+/// the root block.
+pub fn file_size<B: Blockstore + ?Sized>(store: &B, root: &Cid) -> Result<u64> {
+    let block = load_block(store, root)?;
+    match root.codec() {
+        RAW => Ok(block.len() as u64),
+        DAG_PB => {
+            let node = PbNode::decode(&block)?;
+            let unixfs = UnixFsData::decode(node.data.as_deref().unwrap_or_default())?;
+            Ok(unixfs
+                .filesize
+                .unwrap_or_else(|| unixfs.data.map_or(0, |d| d.len() as u64)))
+        }
+        codec => bail!("unsupported codec 0x{:x} in file DAG", codec),
+    }
+}
+
+/// Checks that `block` hashes to the multihash in `cid`.
+pub fn verify_block(cid: &Cid, block: &[u8]) -> Result<()> {
+    let code = Code::try_from(cid.hash().code())?;
+    if code.digest(block) != *cid.hash() {
+        return Err(DagError::Integrity(*cid).into());
+    }
+    Ok(())
+}
+
+fn load_block<B: Blockstore + ?Sized>(store: &B, cid: &Cid) -> Result<Vec<u8>> {
+    let block = store
+        .get(cid)?
+        .ok_or(DagError::NotFound(*cid))?;
+    verify_block(cid, &block)?;
+    Ok(block)
+}
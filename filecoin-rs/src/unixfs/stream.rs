@@ -0,0 +1,137 @@
+// src/unixfs/stream.rs
+//
+// Async import/export of file DAGs that process data chunk by chunk.
+
+use super::builder::FileImporter;
+use super::reader::{file_size, FileChunks};
+use super::Chunker;
+use anyhow::{anyhow, Result};
+use bytes::Bytes;
+use cid::Cid;
+use futures::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use futures::stream::{self, Stream, StreamExt};
+use fvm_ipld_blockstore::Blockstore;
+
+/// Size of the buffer used when reading from an `AsyncRead`.
+const READ_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Progress of an upload or download, reported after each chunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransferProgress {
+    /// Bytes transferred so far.
+    pub transferred: u64,
+    /// Total bytes, when known up front (downloads only).
+    pub total: Option<u64>,
+}
+
+/// Imports everything readable from `reader` as a file and returns its root CID.
+pub async fn add_reader<B, C, R, P>(store: &B, chunker: &C, mut reader: R, mut progress: P) -> Result<Cid>
+where
+    B: Blockstore + ?Sized,
+    C: Chunker + ?Sized,
+    R: AsyncRead + Unpin,
+    P: FnMut(TransferProgress),
+{
+    let mut importer = FileImporter::new(store, chunker);
+    let mut buf = vec![0u8; READ_BUFFER_SIZE];
+    let mut transferred = 0u64;
+    loop {
+        let n = reader.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        importer.write(&buf[..n])?;
+        transferred += n as u64;
+        progress(TransferProgress { transferred, total: None });
+    }
+    importer.finish()
+}
+
+/// Imports a stream of byte buffers as a file and returns its root CID.
+pub async fn add_stream<B, C, S, E, P>(store: &B, chunker: &C, stream: S, mut progress: P) -> Result<Cid>
+where
+    B: Blockstore + ?Sized,
+    C: Chunker + ?Sized,
+    S: Stream<Item = Result<Bytes, E>>,
+    E: std::fmt::Display,
+    P: FnMut(TransferProgress),
+{
+    let mut importer = FileImporter::new(store, chunker);
+    let mut transferred = 0u64;
+    futures::pin_mut!(stream);
+    while let Some(item) = stream.next().await {
+        let bytes = item.map_err(|e| anyhow!("upload stream failed: {}", e))?;
+        importer.write(&bytes)?;
+        transferred += bytes.len() as u64;
+        progress(TransferProgress { transferred, total: None });
+    }
+    importer.finish()
+}
+
+/// Writes the file stored under `root` to `writer`, returning the number of
+/// bytes written.
+pub async fn write_file<B, W, P>(store: &B, root: &Cid, mut writer: W, mut progress: P) -> Result<u64>
+where
+    B: Blockstore + ?Sized,
+    W: AsyncWrite + Unpin,
+    P: FnMut(TransferProgress),
+{
+    let total = Some(file_size(store, root)?);
+    let mut transferred = 0u64;
+    for chunk in FileChunks::new(store, root) {
+        let chunk = chunk?;
+        writer.write_all(&chunk).await?;
+        transferred += chunk.len() as u64;
+        progress(TransferProgress { transferred, total });
+    }
+    writer.flush().await?;
+    Ok(transferred)
+}
+
+/// Returns the content of the file stored under `root` as a stream of chunks.
+pub fn file_stream<'a, B>(store: &'a B, root: &Cid) -> impl Stream<Item = Result<Bytes>> + 'a
+where
+    B: Blockstore + ?Sized,
+{
+    stream::iter(FileChunks::new(store, root).map(|chunk| chunk.map(Bytes::from)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::unixfs::{add_bytes, FixedSizeChunker};
+    use futures::executor::block_on;
+    use fvm_ipld_blockstore::MemoryBlockstore;
+
+    #[test]
+    fn test_reader_matches_in_memory_import() {
+        let bs = MemoryBlockstore::new();
+        let chunker = FixedSizeChunker::new(1000);
+        let data: Vec<u8> = (0..150_000u32).map(|i| (i % 7) as u8).collect();
+
+        let mut reports = Vec::new();
+        let root = block_on(add_reader(&bs, &chunker, &data[..], |p| reports.push(p))).unwrap();
+        assert_eq!(root, add_bytes(&bs, &chunker, &data).unwrap());
+        assert_eq!(reports.last().unwrap().transferred, data.len() as u64);
+
+        let mut out = Vec::new();
+        let written = block_on(write_file(&bs, &root, &mut out, |p| {
+            assert_eq!(p.total, Some(data.len() as u64));
+        }))
+        .unwrap();
+        assert_eq!(written, data.len() as u64);
+        assert_eq!(out, data);
+    }
+
+    #[test]
+    fn test_stream_roundtrip() {
+        let bs = MemoryBlockstore::new();
+        let chunker = FixedSizeChunker::new(64);
+        let parts: Vec<Result<Bytes, String>> =
+            (0..10u8).map(|i| Ok(Bytes::from(vec![i; 50]))).collect();
+
+        let root = block_on(add_stream(&bs, &chunker, stream::iter(parts), |_| {})).unwrap();
+        let chunks: Vec<Bytes> = block_on(file_stream(&bs, &root).map(|c| c.unwrap()).collect());
+        assert_eq!(chunks.concat(), (0..10u8).flat_map(|i| vec![i; 50]).collect::<Vec<_>>());
+    }
+}
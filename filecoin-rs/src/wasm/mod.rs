@@ -1,4 +1,12 @@
+use crate::backup::snapshot::Entries;
+use crate::backup::{self, BackupError, Codec, Compression, Envelope, RetentionPolicy, SnapshotInfo};
+use crate::blockstore::InMemoryBlockstore;
+use crate::unixfs::{self, FixedSizeChunker};
+use crate::MyStorage;
+use cid::Cid;
+use std::cell::RefCell;
 use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::JsFuture;
 
 // BackupMetadata struct with Clone trait
 #[wasm_bindgen]
@@ -8,13 +16,16 @@ pub struct BackupMetadata {
     encrypted: Option<bool>,
     compression_level: Option<i32>,
     size: Option<i32>,
+    timestamp: Option<f64>,
+    compression: Option<Codec>,
+    envelope: Option<Envelope>,
 }
 
 #[wasm_bindgen]
 impl BackupMetadata {
     #[wasm_bindgen(constructor)]
     pub fn new(path: Option<String>, encrypted: Option<bool>, compression_level: Option<i32>, size: Option<i32>) -> BackupMetadata {
-        BackupMetadata { path, encrypted, compression_level, size }
+        BackupMetadata { path, encrypted, compression_level, size, timestamp: None, compression: None, envelope: None }
     }
 
     #[wasm_bindgen(getter)]
@@ -25,6 +36,17 @@ impl BackupMetadata {
     pub fn compression_level(&self) -> Option<i32> { self.compression_level }
     #[wasm_bindgen(getter)]
     pub fn size(&self) -> Option<i32> { self.size }
+    /// Creation time in seconds since the Unix epoch, used by retention.
+    #[wasm_bindgen(getter)]
+    pub fn timestamp(&self) -> Option<f64> { self.timestamp }
+    /// Codec the payload was compressed with ("zstd", "gzip" or "none").
+    #[wasm_bindgen(getter)]
+    pub fn compression(&self) -> Option<String> { self.compression.map(|c| c.name().to_string()) }
+    /// Cipher, nonce and KDF parameters as JSON, when the backup is encrypted.
+    #[wasm_bindgen(getter)]
+    pub fn envelope(&self) -> Option<String> {
+        self.envelope.as_ref().and_then(|e| serde_json::to_string(e).ok())
+    }
 }
 
 // FilecoinBackupResult struct
@@ -32,19 +54,27 @@ impl BackupMetadata {
 pub struct FilecoinBackupResult {
     success: bool,
     metadata: BackupMetadata,
+    cid: Option<String>,
+    data: Vec<u8>,
 }
 
 #[wasm_bindgen]
 impl FilecoinBackupResult {
     #[wasm_bindgen(constructor)]
     pub fn new(success: bool, metadata: BackupMetadata) -> FilecoinBackupResult {
-        FilecoinBackupResult { success, metadata }
+        FilecoinBackupResult { success, metadata, cid: None, data: Vec::new() }
     }
 
     #[wasm_bindgen(getter)]
     pub fn success(&self) -> bool { self.success }
     #[wasm_bindgen(getter)]
     pub fn metadata(&self) -> BackupMetadata { self.metadata.clone() } // Now works with Clone
+    /// Root CID of the stored backup container.
+    #[wasm_bindgen(getter)]
+    pub fn cid(&self) -> Option<String> { self.cid.clone() }
+    /// The backup container, for keeping a copy outside the store.
+    #[wasm_bindgen(getter)]
+    pub fn data(&self) -> Vec<u8> { self.data.clone() }
 }
 
 #[wasm_bindgen]
@@ -53,33 +83,239 @@ pub fn run() -> Result<(), JsValue> {
     Ok(())
 }
 
+thread_local! {
+    // Storage behind the free backup functions. wasm is single-threaded, so a
+    // thread-local stands in for a global.
+    static STORAGE: RefCell<MyStorage> = RefCell::new(MyStorage::new());
+}
+
+/// Compresses `data` with `codec` (zstd by default) at `compression_level`,
+/// encrypts it with XChaCha20-Poly1305 under a key derived from `passphrase`
+/// via Argon2id when one is given, and stores the resulting container. The
+/// result carries the container's root CID and `timestamp` (seconds since the
+/// Unix epoch) for later retention decisions.
 #[wasm_bindgen]
-pub fn backup_data(data: &[u8]) -> Result<FilecoinBackupResult, JsValue> {
-    let metadata = BackupMetadata::new(
-        Some("backup/backup-file".to_string()),
-        Some(true),
+pub fn backup_data(
+    data: &[u8],
+    passphrase: Option<String>,
+    compression_level: Option<i32>,
+    codec: Option<String>,
+    timestamp: Option<f64>,
+) -> Result<FilecoinBackupResult, JsValue> {
+    let mut result = seal_backup(data, passphrase, compression_level, codec, timestamp)?;
+    let cid = STORAGE
+        .with(|storage| storage.borrow_mut().store_sealed(&result.data))
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+    console_log(&format!("Backed up {} bytes as {}", data.len(), cid));
+    result.cid = Some(cid);
+    Ok(result)
+}
+
+/// Like `backup_data`, but leaves storing the container in `data` to the
+/// caller, e.g. in IndexedDB or with a remote provider. `cid` is unset.
+#[wasm_bindgen]
+pub fn seal_backup(
+    data: &[u8],
+    passphrase: Option<String>,
+    compression_level: Option<i32>,
+    codec: Option<String>,
+    timestamp: Option<f64>,
+) -> Result<FilecoinBackupResult, JsValue> {
+    let codec = match codec {
+        Some(name) => name.parse::<Codec>().map_err(to_js)?,
+        None => Codec::Zstd,
+    };
+    let level = compression_level.unwrap_or(match codec {
+        Codec::Gzip => 6,
+        _ => Compression::ZSTD_DEFAULT_LEVEL,
+    });
+    let compression = Compression::new(codec, level);
+    let (header, sealed) = backup::seal(data, compression, passphrase.as_deref()).map_err(to_js)?;
+
+    let mut metadata = BackupMetadata::new(
         None,
+        Some(header.envelope.is_some()),
+        Some(header.compression.level),
         Some(data.len() as i32),
     );
-    console_log(&format!("Backup data of size {}", data.len()));
-    Ok(FilecoinBackupResult::new(true, metadata))
+    metadata.timestamp = timestamp;
+    metadata.compression = Some(header.compression.codec);
+    metadata.envelope = header.envelope;
+    Ok(FilecoinBackupResult { success: true, metadata, cid: None, data: sealed })
+}
+
+/// Restores the backup stored under `cid` and returns the original bytes,
+/// checked against the digest recorded at backup time. Fails if the backup is
+/// encrypted and the key is missing or wrong, or if it has been modified.
+#[wasm_bindgen]
+pub fn restore_from_backup(cid: &str, decryption_key: Option<String>) -> Result<Vec<u8>, JsValue> {
+    let data = STORAGE
+        .with(|storage| storage.borrow().restore_backup(cid, decryption_key.as_deref()))
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+    console_log(&format!("Restored {} bytes from {}", data.len(), cid));
+    Ok(data)
 }
 
+/// Restores the backup stored under `cid`, asking `fetch(cid)` for the
+/// container when it is not in this module's storage. `fetch` returns the
+/// container's bytes or a promise of them, e.g. from IndexedDB or a gateway;
+/// they must hash to `cid`.
 #[wasm_bindgen]
-pub fn restore_from_backup(backup_path: &str, destination_path: Option<String>, decryption_key: Option<String>) -> Result<(), JsValue> {
-    if let Some(key) = decryption_key {
-        console_log(&format!("Restoring with decryption key: {}", key));
+pub async fn restore_backup_with(
+    cid: String,
+    decryption_key: Option<String>,
+    fetch: js_sys::Function,
+) -> Result<Vec<u8>, JsValue> {
+    let stored = STORAGE.with(|storage| storage.borrow().has(&cid)).unwrap_or(false);
+    if stored {
+        return restore_from_backup(&cid, decryption_key);
     }
-    console_log(&format!("Restoring from {} to {:?}", backup_path, destination_path));
-    Ok(())
+    let root: Cid = cid.parse().map_err(|e: cid::Error| JsValue::from_str(&e.to_string()))?;
+    let fetched = fetch.call1(&JsValue::NULL, &JsValue::from_str(&cid))?;
+    let fetched = JsFuture::from(js_sys::Promise::resolve(&fetched)).await?;
+    let sealed = js_sys::Uint8Array::new(&fetched).to_vec();
+
+    let computed = unixfs::add_bytes(&InMemoryBlockstore::new(), &FixedSizeChunker::default(), &sealed)
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+    if computed != root {
+        return Err(JsValue::from_str(&format!("fetched backup is {}, not {}", computed, root)));
+    }
+    open_backup(&sealed, decryption_key)
+}
+
+/// Unpacks a backup container obtained elsewhere, e.g. read back from a file
+/// written from `FilecoinBackupResult.data`.
+#[wasm_bindgen]
+pub fn open_backup(backup: &[u8], decryption_key: Option<String>) -> Result<Vec<u8>, JsValue> {
+    backup::open(backup, decryption_key.as_deref()).map_err(to_js)
+}
+
+/// Collects entries for an incremental snapshot.
+#[wasm_bindgen]
+#[derive(Default)]
+pub struct SnapshotBuilder {
+    entries: Entries,
 }
 
+#[wasm_bindgen]
+impl SnapshotBuilder {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> SnapshotBuilder {
+        SnapshotBuilder::default()
+    }
+
+    #[wasm_bindgen(js_name = addEntry)]
+    pub fn add_entry(&mut self, path: String, data: &[u8]) {
+        self.entries.insert(path, data.to_vec());
+    }
+
+    /// Stores the snapshot on top of `parent` and returns its CID. Only
+    /// chunks not already stored are written.
+    pub fn commit(&self, parent: Option<String>, timestamp: f64) -> Result<String, JsValue> {
+        let receipt = STORAGE
+            .with(|storage| storage.borrow_mut().snapshot(&self.entries, parent.as_deref(), timestamp as u64))
+            .map_err(|e| JsValue::from_str(&e.to_string()))?;
+        console_log(&format!(
+            "Snapshot {}: {} entries, {} new blocks, {} reused",
+            receipt.cid,
+            receipt.manifest.entries.len(),
+            receipt.stats.new_blocks,
+            receipt.stats.reused_blocks
+        ));
+        Ok(receipt.cid.to_string())
+    }
+}
+
+/// Entries restored from a snapshot, in path order.
+#[wasm_bindgen]
+pub struct SnapshotContents {
+    entries: Vec<(String, Vec<u8>)>,
+}
+
+#[wasm_bindgen]
+impl SnapshotContents {
+    #[wasm_bindgen(getter)]
+    pub fn length(&self) -> usize { self.entries.len() }
+    pub fn path(&self, index: usize) -> Option<String> { self.entries.get(index).map(|e| e.0.clone()) }
+    pub fn data(&self, index: usize) -> Option<Vec<u8>> { self.entries.get(index).map(|e| e.1.clone()) }
+}
+
+/// Restores the snapshot stored under `cid`, limited to the entries under
+/// `filter` when given.
+#[wasm_bindgen]
+pub fn restore_snapshot(cid: &str, filter: Option<String>) -> Result<SnapshotContents, JsValue> {
+    let entries = STORAGE
+        .with(|storage| storage.borrow().restore_snapshot(cid, filter.as_deref()))
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+    Ok(SnapshotContents { entries: entries.into_iter().collect() })
+}
+
+/// Returns `{added, removed, changed}` path lists between two snapshots, as JSON.
+#[wasm_bindgen]
+pub fn diff_snapshots(a: &str, b: &str) -> Result<String, JsValue> {
+    let diff = STORAGE
+        .with(|storage| storage.borrow().diff_snapshots(a, b))
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+    serde_json::to_string(&diff).map_err(|e| JsValue::from_str(&e.to_string()))
+}
+
+/// Evaluates a retention policy (JSON `RetentionPolicy`) over backups given as
+/// a JSON array of `{cid, timestamp}`, e.g. collected from `BackupMetadata`.
+/// Returns the prune plan as JSON; nothing is unpinned.
+#[wasm_bindgen]
+pub fn plan_retention(policy: &str, backups: &str, now: f64) -> Result<String, JsValue> {
+    let backups: Vec<SnapshotInfo> = serde_json::from_str(backups).map_err(|e| JsValue::from_str(&e.to_string()))?;
+    prune_plan_json(policy, &backups, now)
+}
+
+/// Like `plan_retention`, over the snapshot chain ending at `head`.
+#[wasm_bindgen]
+pub fn plan_snapshot_retention(policy: &str, head: &str, now: f64) -> Result<String, JsValue> {
+    let chain = STORAGE
+        .with(|storage| storage.borrow().snapshot_history(head))
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+    prune_plan_json(policy, &chain, now)
+}
+
+/// Applies `policy` to the snapshot chain ending at `head`: pruned snapshots
+/// are removed along with the blocks only they used. Returns the
+/// `CollectReport` as JSON.
+#[wasm_bindgen]
+pub fn apply_snapshot_retention(policy: &str, head: &str, now: f64) -> Result<String, JsValue> {
+    let policy: RetentionPolicy = serde_json::from_str(policy).map_err(|e| JsValue::from_str(&e.to_string()))?;
+    let report = STORAGE
+        .with(|storage| {
+            let mut storage = storage.borrow_mut();
+            let plan = policy.plan(&storage.snapshot_history(head)?, now as u64);
+            storage.apply_retention(&plan)
+        })
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+    serde_json::to_string(&report).map_err(|e| JsValue::from_str(&e.to_string()))
+}
+
+fn prune_plan_json(policy: &str, backups: &[SnapshotInfo], now: f64) -> Result<String, JsValue> {
+    let policy: RetentionPolicy = serde_json::from_str(policy).map_err(|e| JsValue::from_str(&e.to_string()))?;
+    let plan = policy.plan(backups, now as u64);
+    serde_json::to_string(&plan).map_err(|e| JsValue::from_str(&e.to_string()))
+}
+
+fn to_js(e: BackupError) -> JsValue {
+    JsValue::from_str(&e.to_string())
+}
+
+#[cfg(target_arch = "wasm32")]
 #[wasm_bindgen]
 extern "C" {
     #[wasm_bindgen(js_namespace = console)]
     fn log(s: &str);
 }
 
+#[cfg(target_arch = "wasm32")]
 fn console_log(s: &str) {
     log(s);
-}
\ No newline at end of file
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn console_log(s: &str) {
+    log::info!("{}", s);
+}